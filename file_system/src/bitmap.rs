@@ -62,6 +62,72 @@ impl Bitmap {
         None
     }
 
+    /// 分配最多 `max_len` 个连续的bit，只使用编号小于 `limit` 的bit。
+    /// 从 `goal` 开始向后寻找第一个空闲的bit（找不到时再从头寻找），然后尽量向后延伸，
+    /// 使顺序写入的文件在磁盘上连续存放。返回第一个bit的编号和分配的个数，
+    /// 如果所有bit均已经被分配出去了，则返回 None 。
+    pub fn alloc_contiguous(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        goal: usize,
+        max_len: usize,
+        limit: usize,
+    ) -> Option<(usize, usize)> {
+        let limit = limit.min(self.maximum());
+        let goal = if goal < limit { goal } else { 0 };
+        let mut first = self.find(block_device, goal, limit, false);
+        if first == limit {
+            first = self.find(block_device, 0, goal, false);
+            if first == goal {
+                return None;
+            }
+        }
+        let end = self.find(block_device, first, limit.min(first + max_len), true);
+        let mut bit = first;
+        while bit < end {
+            let (block_pos, _, _) = decomposition(bit);
+            let block_end = ((block_pos + 1) * BLOCK_BITS).min(end);
+            get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+                .lock()
+                .modify(0, |bitmap_block: &mut BitmapBlock| {
+                    for b in bit..block_end {
+                        let (_, bits64_pos, inner_pos) = decomposition(b);
+                        bitmap_block[bits64_pos] |= 1u64 << inner_pos;
+                    }
+                });
+            bit = block_end;
+        }
+        Some((first, end - first))
+    }
+
+    /// `[from, to)` 中第一个分配状态为 `allocated` 的bit，不存在时返回 `to`
+    fn find(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        from: usize,
+        to: usize,
+        allocated: bool,
+    ) -> usize {
+        let mut bit = from;
+        while bit < to {
+            let (block_pos, _, _) = decomposition(bit);
+            let block_end = ((block_pos + 1) * BLOCK_BITS).min(to);
+            let found = get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+                .lock()
+                .read(0, |bitmap_block: &BitmapBlock| {
+                    (bit..block_end).find(|&b| {
+                        let (_, bits64_pos, inner_pos) = decomposition(b);
+                        (bitmap_block[bits64_pos] & (1u64 << inner_pos) > 0) == allocated
+                    })
+                });
+            if let Some(found) = found {
+                return found;
+            }
+            bit = block_end;
+        }
+        to
+    }
+
     pub fn dealloc(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) {
         let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
         get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
@@ -72,6 +138,42 @@ impl Bitmap {
             });
     }
 
+    /// 编号为 `bit` 的位是否已被分配
+    pub fn is_allocated(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) -> bool {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+        get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+            .lock()
+            .read(0, |bitmap_block: &BitmapBlock| {
+                bitmap_block[bits64_pos] & (1u64 << inner_pos) > 0
+            })
+    }
+
+    /// 把编号为 `bit` 的位标记为已分配，用于修复位图
+    pub fn mark_allocated(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+        get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+            .lock()
+            .modify(0, |bitmap_block: &mut BitmapBlock| {
+                bitmap_block[bits64_pos] |= 1u64 << inner_pos;
+            });
+    }
+
+    /// 已分配的bit数
+    pub fn count_allocated(&self, block_device: &Arc<dyn BlockDevice>) -> usize {
+        (0..self.blocks)
+            .map(|block_id| {
+                get_block_cache(block_id + self.start_block_id, Arc::clone(block_device))
+                    .lock()
+                    .read(0, |bitmap_block: &BitmapBlock| {
+                        bitmap_block
+                            .iter()
+                            .map(|bits64| bits64.count_ones() as usize)
+                            .sum::<usize>()
+                    })
+            })
+            .sum()
+    }
+
     pub fn maximum(&self) -> usize {
         self.blocks * BLOCK_BITS
     }
@@ -1,11 +1,13 @@
-use super::{BlockDevice, BLOCK_SZ};
+use super::{BlockDevice, BLOCK_SZ, record_context_error, verify_block};
 use alloc::boxed::Box;
-use alloc::collections::VecDeque;
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::alloc::Layout;
 use core::mem::ManuallyDrop;
 use core::ptr::{addr_of, addr_of_mut};
 use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use lazy_static::*;
 use spin::Mutex;
 
@@ -53,6 +55,12 @@ pub struct BlockCache {
     block_id: usize,
     block_device: Arc<dyn BlockDevice>,
     modified: bool,
+    /// 元数据已被修改但还没有通过日志提交，不能直接写回原位置
+    journaled: bool,
+    /// 内容已经校验过，或者内容来自内存中的修改，作为元数据读取时不再校验
+    checked: bool,
+    /// 校验和不符，之后每次作为元数据读取都记录到读取者名下
+    bad: bool,
 }
 
 impl BlockCache {
@@ -60,12 +68,21 @@ impl BlockCache {
     pub fn new(block_id: usize, block_device: Arc<dyn BlockDevice>) -> Self {
         // for alignment and move effciency
         let mut cache = CacheData::new();
-        block_device.read_block(block_id, cache.as_mut());
+        // 被换出的未提交元数据块以内存中的版本为准
+        let pending = PENDING_BLOCKS.lock().remove(&block_id);
+        let journaled = pending.is_some();
+        match pending {
+            Some(data) => cache.as_mut().copy_from_slice(&data),
+            None => block_device.read_block(block_id, cache.as_mut()),
+        }
         Self {
             cache,
             block_id,
             block_device,
-            modified: false,
+            modified: journaled,
+            journaled,
+            checked: journaled,
+            bad: false,
         }
     }
 
@@ -87,18 +104,49 @@ impl BlockCache {
         unsafe { &*addr }
     }
 
+    /// 以元数据的方式修改，修改要经过日志提交后才会写回
     pub fn get_mut<T>(&mut self, offset: usize) -> &mut T
+    where
+        T: Sized,
+    {
+        if !self.journaled {
+            self.journaled = true;
+            JOURNALED_BLOCKS.fetch_add(1, Ordering::Relaxed);
+        }
+        self.get_data_mut(offset)
+    }
+
+    /// 以普通数据的方式修改，不经过日志
+    pub fn get_data_mut<T>(&mut self, offset: usize) -> &mut T
     where
         T: Sized,
     {
         let type_size = core::mem::size_of::<T>();
         assert!(offset + type_size <= BLOCK_SZ);
         self.modified = true;
+        self.checked = true;
         let addr = self.addr_of_offset_mut(offset) as *mut T;
         unsafe { &mut *addr }
     }
 
-    pub fn read<T, V>(&self, offset: usize, f: impl FnOnce(&T) -> V) -> V {
+    /// 以元数据的方式读取，第一次读取时按校验和表校验块的内容
+    pub fn read<T, V>(&mut self, offset: usize, f: impl FnOnce(&T) -> V) -> V {
+        if !self.checked {
+            self.checked = true;
+            if !verify_block(self.block_id, self.cache.as_ref(), &self.block_device) {
+                // 损坏的内容不再被解析，读出为 0
+                self.cache.as_mut().fill(0);
+                self.bad = true;
+            }
+        }
+        if self.bad {
+            record_context_error();
+        }
+        f(self.get_ref(offset))
+    }
+
+    /// 以普通数据的方式读取，不校验
+    pub fn read_data<T, V>(&self, offset: usize, f: impl FnOnce(&T) -> V) -> V {
         f(self.get_ref(offset))
     }
 
@@ -106,32 +154,113 @@ impl BlockCache {
         f(self.get_mut(offset))
     }
 
-    pub fn sync(&mut self) {
-        if self.modified {
+    pub fn modify_data<T, V>(&mut self, offset: usize, f: impl FnOnce(&mut T) -> V) -> V {
+        f(self.get_data_mut(offset))
+    }
+
+    /// 写回修改过的数据块，未提交的元数据块留给日志处理，返回是否写了磁盘
+    pub fn sync(&mut self) -> bool {
+        if self.modified && !self.journaled {
             self.modified = false;
             self.block_device
                 .write_block(self.block_id, self.cache.as_ref());
+            return true;
         }
+        false
     }
 }
 
 impl Drop for BlockCache {
     fn drop(&mut self) {
-        self.sync()
+        if self.journaled {
+            PENDING_BLOCKS
+                .lock()
+                .insert(self.block_id, Vec::from(self.cache.as_ref()));
+        } else {
+            self.sync();
+        }
     }
 }
 
-const BLOCK_CACHE_SIZE: usize = 16;
+/// 默认缓存的块数，可以在打开文件系统之前用 `init_block_cache` 修改
+const DEFAULT_BLOCK_CACHE_SIZE: usize = 256;
+
+/// 块缓存的命中统计
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BlockCacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    /// 预读加载的块数
+    pub read_ahead: usize,
+    pub evictions: usize,
+    /// 写回磁盘的块数，不包括经过日志写回的元数据块
+    pub write_backs: usize,
+}
 
+/// 按 LRU 顺序换出的块缓存
 pub struct BlockCacheManager {
-    queue: VecDeque<(usize, Arc<Mutex<BlockCache>>)>,
+    capacity: usize,
+    /// 块号到缓存块及其最近一次被访问的序号
+    map: BTreeMap<usize, (Arc<Mutex<BlockCache>>, u64)>,
+    /// 按访问序号排列的块号，最前面的是最久没有被访问的块
+    lru: BTreeMap<u64, usize>,
+    clock: u64,
+    stats: BlockCacheStats,
 }
 
 impl BlockCacheManager {
-    pub fn new() -> Self {
+    pub fn new(capacity: usize) -> Self {
         Self {
-            queue: VecDeque::new(),
+            capacity,
+            map: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            stats: BlockCacheStats::default(),
+        }
+    }
+
+    fn touch(&mut self, block_id: usize) {
+        self.clock += 1;
+        let (_, stamp) = self.map.get_mut(&block_id).unwrap();
+        self.lru.remove(stamp);
+        *stamp = self.clock;
+        self.lru.insert(self.clock, block_id);
+    }
+
+    /// 换出最久没有被访问且没有被引用的块，所有块都在使用中时返回 false
+    fn evict(&mut self) -> bool {
+        let victim = self
+            .lru
+            .iter()
+            .find(|(_, block_id)| Arc::strong_count(&self.map[block_id].0) == 1)
+            .map(|(&stamp, &block_id)| (stamp, block_id));
+        let Some((stamp, block_id)) = victim else {
+            return false;
+        };
+        self.lru.remove(&stamp);
+        let (cache, _) = self.map.remove(&block_id).unwrap();
+        if cache.lock().sync() {
+            self.stats.write_backs += 1;
+        }
+        self.stats.evictions += 1;
+        true
+    }
+
+    /// 从磁盘加载一个块，缓存已满且无法换出时返回 None
+    fn load(
+        &mut self,
+        block_id: usize,
+        block_device: Arc<dyn BlockDevice>,
+    ) -> Option<Arc<Mutex<BlockCache>>> {
+        if self.map.len() >= self.capacity && !self.evict() {
+            return None;
         }
+        let block_cache = Arc::new(Mutex::new(BlockCache::new(block_id, block_device)));
+        self.clock += 1;
+        self.map
+            .insert(block_id, (Arc::clone(&block_cache), self.clock));
+        self.lru.insert(self.clock, block_id);
+        Some(block_cache)
     }
 
     pub fn get_block_cache(
@@ -139,37 +268,44 @@ impl BlockCacheManager {
         block_id: usize,
         block_device: Arc<dyn BlockDevice>,
     ) -> Arc<Mutex<BlockCache>> {
-        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == block_id) {
-            Arc::clone(&pair.1)
+        if self.map.contains_key(&block_id) {
+            self.stats.hits += 1;
+            self.touch(block_id);
+            Arc::clone(&self.map[&block_id].0)
         } else {
-            // substitute
-            if self.queue.len() == BLOCK_CACHE_SIZE {
-                // from front to tail
-                if let Some((idx, _)) = self
-                    .queue
-                    .iter()
-                    .enumerate()
-                    .find(|(_, pair)| Arc::strong_count(&pair.1) == 1)
-                {
-                    self.queue.drain(idx..=idx);
-                } else {
-                    panic!("Run out of BlockCache!");
-                }
+            self.stats.misses += 1;
+            self.load(block_id, block_device)
+                .expect("Run out of BlockCache!")
+        }
+    }
+
+    /// 提前加载还不在缓存中的块，没有可以换出的块时停止
+    pub fn read_ahead(&mut self, block_ids: &[usize], block_device: Arc<dyn BlockDevice>) {
+        for &block_id in block_ids {
+            if self.map.contains_key(&block_id) {
+                continue;
+            }
+            if self.load(block_id, Arc::clone(&block_device)).is_none() {
+                break;
             }
-            // load block into mem and push back
-            let block_cache = Arc::new(Mutex::new(BlockCache::new(
-                block_id,
-                Arc::clone(&block_device),
-            )));
-            self.queue.push_back((block_id, Arc::clone(&block_cache)));
-            block_cache
+            self.stats.read_ahead += 1;
         }
     }
 }
 
 lazy_static! {
     pub static ref BLOCK_CACHE_MANAGER: Mutex<BlockCacheManager> =
-        Mutex::new(BlockCacheManager::new());
+        Mutex::new(BlockCacheManager::new(DEFAULT_BLOCK_CACHE_SIZE));
+    /// 从缓存中换出的未提交元数据块
+    static ref PENDING_BLOCKS: Mutex<BTreeMap<usize, Vec<u8>>> = Mutex::new(BTreeMap::new());
+}
+
+/// 未提交的元数据块数，包括被换出的块
+static JOURNALED_BLOCKS: AtomicUsize = AtomicUsize::new(0);
+
+/// 设置缓存的块数，应在打开文件系统之前调用，已缓存的块会被写回
+pub fn init_block_cache(capacity: usize) {
+    *BLOCK_CACHE_MANAGER.lock() = BlockCacheManager::new(capacity);
 }
 
 pub fn get_block_cache(
@@ -181,9 +317,79 @@ pub fn get_block_cache(
         .get_block_cache(block_id, block_device)
 }
 
+/// 预读：把接下来可能访问的块提前加载到缓存中
+pub fn block_cache_read_ahead(block_ids: &[usize], block_device: Arc<dyn BlockDevice>) {
+    BLOCK_CACHE_MANAGER
+        .lock()
+        .read_ahead(block_ids, block_device)
+}
+
+pub fn block_cache_stats() -> BlockCacheStats {
+    BLOCK_CACHE_MANAGER.lock().stats
+}
+
+/// 当前缓存的所有块，按块号排序
+///
+/// 锁住单个块时不能持有管理器的锁：其他核可能正持有某个块的锁并等待管理器的锁
+fn cached_blocks() -> Vec<Arc<Mutex<BlockCache>>> {
+    BLOCK_CACHE_MANAGER
+        .lock()
+        .map
+        .values()
+        .map(|(cache, _)| Arc::clone(cache))
+        .collect()
+}
+
+/// 按块号顺序写回所有修改过的块
 pub fn block_cache_sync_all() {
-    let manager = BLOCK_CACHE_MANAGER.lock();
-    for (_, cache) in manager.queue.iter() {
-        cache.lock().sync();
+    let written = cached_blocks()
+        .iter()
+        .filter(|cache| cache.lock().sync())
+        .count();
+    BLOCK_CACHE_MANAGER.lock().stats.write_backs += written;
+}
+
+/// 取出所有未提交的元数据块，按块号排序
+///
+/// 取出后这些块在缓存中被视为干净的，由调用者负责写回
+pub fn take_journaled_blocks() -> Vec<(usize, Vec<u8>)> {
+    let cached = cached_blocks();
+    let mut blocks = core::mem::take(&mut *PENDING_BLOCKS.lock());
+    for cache in cached.iter() {
+        let mut cache = cache.lock();
+        if cache.journaled {
+            cache.journaled = false;
+            cache.modified = false;
+            blocks.insert(cache.block_id, Vec::from(cache.cache.as_ref()));
+        }
+    }
+    JOURNALED_BLOCKS.fetch_sub(blocks.len(), Ordering::Relaxed);
+    blocks.into_iter().collect()
+}
+
+/// 未提交的元数据块数
+pub fn journaled_block_count() -> usize {
+    JOURNALED_BLOCKS.load(Ordering::Relaxed)
+}
+
+/// 放回没有提交的元数据块，之后与其他未提交的块一样留在内存中，不会写回原位置
+pub fn restore_journaled_blocks(blocks: Vec<(usize, Vec<u8>)>) {
+    JOURNALED_BLOCKS.fetch_add(blocks.len(), Ordering::Relaxed);
+    for (block_id, data) in blocks {
+        let cache = BLOCK_CACHE_MANAGER
+            .lock()
+            .map
+            .get(&block_id)
+            .map(|(cache, _)| Arc::clone(cache));
+        match cache {
+            Some(cache) => {
+                let mut cache = cache.lock();
+                cache.journaled = true;
+                cache.modified = true;
+            }
+            None => {
+                PENDING_BLOCKS.lock().insert(block_id, data);
+            }
+        }
     }
 }
@@ -0,0 +1,182 @@
+use super::{BLOCK_SZ, BlockDevice, get_block_cache};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+/// CRC32C（Castagnoli）多项式的反射形式
+const CRC32C_POLY: u32 = 0x82f63b78;
+const CRC32C_TABLE: [u32; 256] = crc32c_table();
+/// 每个表块记录的校验和个数
+const ENTRIES_PER_BLOCK: usize = BLOCK_SZ / 4;
+
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC32C_POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+pub fn crc32c(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        CRC32C_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// 表中记录的校验和，0 留给没有记录校验和的块
+fn block_checksum(data: &[u8]) -> u32 {
+    crc32c(data).max(1)
+}
+
+/// 元数据块的校验和表，每个块对应表中的一个 u32，为 0 表示这个块没有记录校验和
+///
+/// 表位于数据区和日志区之间，在提交时随被修改的元数据块一起经过日志写回。
+/// 超级块带有自己的校验和，表本身和普通数据不校验
+#[derive(Clone, Copy)]
+pub struct ChecksumTable {
+    start: u32,
+    blocks: u32,
+}
+
+impl ChecksumTable {
+    pub fn new(start: u32, blocks: u32) -> Self {
+        Self { start, blocks }
+    }
+
+    /// 为 `total_blocks` 个块记录校验和需要的表块数
+    pub fn blocks_for(total_blocks: u32) -> u32 {
+        (total_blocks as usize * 4).div_ceil(BLOCK_SZ) as u32
+    }
+
+    fn area(&self) -> Range<usize> {
+        self.start as usize..(self.start + self.blocks) as usize
+    }
+
+    fn covers(&self, block_id: usize) -> bool {
+        block_id != 0
+            && !self.area().contains(&block_id)
+            && block_id < self.blocks as usize * ENTRIES_PER_BLOCK
+    }
+
+    fn entry_pos(&self, block_id: usize) -> (usize, usize) {
+        (
+            self.start as usize + block_id / ENTRIES_PER_BLOCK,
+            block_id % ENTRIES_PER_BLOCK * 4,
+        )
+    }
+
+    fn entry(&self, block_id: usize, block_device: &Arc<dyn BlockDevice>) -> u32 {
+        let (table_block, offset) = self.entry_pos(block_id);
+        get_block_cache(table_block, Arc::clone(block_device))
+            .lock()
+            .read_data(offset, |entry: &u32| *entry)
+    }
+
+    fn set_entry(&self, block_id: usize, value: u32, block_device: &Arc<dyn BlockDevice>) {
+        let (table_block, offset) = self.entry_pos(block_id);
+        let block_cache = get_block_cache(table_block, Arc::clone(block_device));
+        let mut block_cache = block_cache.lock();
+        if block_cache.read_data(offset, |entry: &u32| *entry != value) {
+            block_cache.modify(offset, |entry: &mut u32| *entry = value);
+        }
+    }
+}
+
+/// 当前打开的文件系统的校验和表，与块缓存一样全局只有一份，旧镜像没有这张表
+static TABLE: Mutex<Option<ChecksumTable>> = Mutex::new(None);
+/// 打开文件系统以来发现的校验和不符的元数据块数
+static ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+/// 返回当前执行者的标识，读到损坏的元数据时记在这个执行者名下
+static CONTEXT: Mutex<fn() -> usize> = Mutex::new(no_context);
+/// 各执行者读到损坏元数据的次数，由 `take_context_errors` 取走
+static CONTEXT_ERRORS: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+fn no_context() -> usize {
+    0
+}
+
+/// 打开文件系统时设置校验和表，同时清零错误计数
+pub fn set_checksum_table(table: Option<ChecksumTable>) {
+    *TABLE.lock() = table;
+    ERRORS.store(0, Ordering::Relaxed);
+    CONTEXT_ERRORS.lock().clear();
+}
+
+/// 设置执行者标识的来源，未设置时所有读取都记在同一个执行者名下
+pub fn set_error_context(context: fn() -> usize) {
+    *CONTEXT.lock() = context;
+}
+
+/// 当前执行者读到了损坏的元数据，每次读取都记录一次
+pub fn record_context_error() {
+    let context = *CONTEXT.lock();
+    *CONTEXT_ERRORS.lock().entry(context()).or_insert(0) += 1;
+}
+
+/// 取走当前执行者上次调用以来读到损坏元数据的次数，用来判断一次操作是否受到损坏的影响
+pub fn take_context_errors() -> usize {
+    let context = *CONTEXT.lock();
+    CONTEXT_ERRORS.lock().remove(&context()).unwrap_or(0)
+}
+
+/// 打开文件系统以来发现的损坏的元数据块数，不为 0 时文件系统不再提交修改
+pub fn metadata_errors() -> usize {
+    ERRORS.load(Ordering::Relaxed)
+}
+
+fn table() -> Option<ChecksumTable> {
+    *TABLE.lock()
+}
+
+/// 校验第一次作为元数据读取的块，校验和不符时计数并返回 false
+pub fn verify_block(block_id: usize, data: &[u8], block_device: &Arc<dyn BlockDevice>) -> bool {
+    let Some(table) = table() else {
+        return true;
+    };
+    if !table.covers(block_id) {
+        return true;
+    }
+    let expected = table.entry(block_id, block_device);
+    if expected == 0 || expected == block_checksum(data) {
+        return true;
+    }
+    ERRORS.fetch_add(1, Ordering::Relaxed);
+    false
+}
+
+/// 提交前为即将写回的元数据块记录校验和，被修改的表块需要随同一个事务提交
+pub fn record_checksums(blocks: &[(usize, Vec<u8>)], block_device: &Arc<dyn BlockDevice>) {
+    let Some(table) = table() else {
+        return;
+    };
+    for (block_id, data) in blocks {
+        if table.covers(*block_id) {
+            table.set_entry(*block_id, block_checksum(data), block_device);
+        }
+    }
+}
+
+/// 块被释放后清除它的校验和，重新分配为普通数据后不再校验
+pub fn forget_checksum(block_id: usize, block_device: &Arc<dyn BlockDevice>) {
+    if let Some(table) = table()
+        && table.covers(block_id)
+    {
+        table.set_entry(block_id, 0, block_device);
+    }
+}
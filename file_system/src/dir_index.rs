@@ -0,0 +1,428 @@
+use super::{
+    BLOCK_SZ, BlockDevice, DIR_INDEX_MARK, DIRENT_HEADER_SZ, DirEntryHeader, DiskInode,
+    NAME_LENGTH_LIMIT, dir_records, parse_dir,
+};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// 目录增长到这么多块时建立哈希索引，更小的目录直接线性查找
+const DIR_INDEX_THRESHOLD: u32 = 4;
+/// 根节点和叶子块中表项数的上限
+const INDEX_ENTRIES: usize = (BLOCK_SZ - DIRENT_HEADER_SZ - 8) / 8;
+/// 空闲空间表能记录的目录块数
+const FREE_MAP_BLOCKS: usize = BLOCK_SZ - DIRENT_HEADER_SZ;
+
+type DataBlock = [u8; BLOCK_SZ];
+
+// 索引块以一条 `file_type` 为 `DIR_INDEX_MARK`、占满整个块的未使用记录开头，
+// 解析目录项时被跳过，也不会被用来存放目录项。记录头之后的内容：
+// - 根节点（目录的第一个块）：空闲空间表所在的块、叶子数和按哈希值排序的
+//   (叶子中最小的哈希值, 叶子所在的块)，第一个叶子的最小哈希值总是 0
+// - 叶子：表项数和按哈希值排序的 (名称的哈希值, 目录项在目录中的偏移)
+// - 空闲空间表：每个目录块中最大的空闲记录空间，以 4 字节为单位
+
+/// 名称的 FNV-1a 哈希值
+fn name_hash(name: &[u8]) -> u32 {
+    name.iter().fold(0x811c9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+fn read_block(dir_inode: &DiskInode, block: u32, block_device: &Arc<dyn BlockDevice>) -> DataBlock {
+    let mut data = [0u8; BLOCK_SZ];
+    dir_inode.read_at(block as usize * BLOCK_SZ, &mut data, block_device);
+    data
+}
+
+fn word(data: &DataBlock, i: usize) -> u32 {
+    let start = DIRENT_HEADER_SZ + i * 4;
+    u32::from_le_bytes(data[start..start + 4].try_into().unwrap())
+}
+
+fn set_word(data: &mut DataBlock, i: usize, value: u32) {
+    let start = DIRENT_HEADER_SZ + i * 4;
+    data[start..start + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// 从第 `count_word` 个字中的表项数开始的表项
+fn entries(data: &DataBlock, count_word: usize) -> Vec<(u32, u32)> {
+    let count = (word(data, count_word) as usize).min(INDEX_ENTRIES);
+    (0..count)
+        .map(|i| {
+            let first = count_word + 1 + i * 2;
+            (word(data, first), word(data, first + 1))
+        })
+        .collect()
+}
+
+/// 把目录的第 `block` 块写成索引块，`fill` 填写记录头之后的内容
+fn write_index(
+    dir_inode: &mut DiskInode,
+    block: u32,
+    block_device: &Arc<dyn BlockDevice>,
+    fill: impl FnOnce(&mut DataBlock),
+) {
+    let mut data = [0u8; BLOCK_SZ];
+    let header = DirEntryHeader {
+        inode_number: 0,
+        rec_len: BLOCK_SZ as u16,
+        name_len: 0,
+        file_type: DIR_INDEX_MARK,
+    };
+    data[..DIRENT_HEADER_SZ].copy_from_slice(&header.to_bytes());
+    fill(&mut data);
+    dir_inode.write_at(block as usize * BLOCK_SZ, &data, block_device);
+}
+
+fn write_entries(data: &mut DataBlock, count_word: usize, list: &[(u32, u32)]) {
+    set_word(data, count_word, list.len() as u32);
+    for (i, &(key, value)) in list.iter().enumerate() {
+        set_word(data, count_word + 1 + i * 2, key);
+        set_word(data, count_word + 2 + i * 2, value);
+    }
+}
+
+/// 根节点中的空闲空间表所在的块和叶子
+fn read_root(dir_inode: &DiskInode, block_device: &Arc<dyn BlockDevice>) -> (u32, Vec<(u32, u32)>) {
+    let data = read_block(dir_inode, 0, block_device);
+    (word(&data, 0), entries(&data, 1))
+}
+
+fn write_root(
+    dir_inode: &mut DiskInode,
+    free_map: u32,
+    leaves: &[(u32, u32)],
+    block_device: &Arc<dyn BlockDevice>,
+) {
+    write_index(dir_inode, 0, block_device, |data| {
+        set_word(data, 0, free_map);
+        write_entries(data, 1, leaves);
+    });
+}
+
+fn write_leaf(
+    dir_inode: &mut DiskInode,
+    block: u32,
+    list: &[(u32, u32)],
+    block_device: &Arc<dyn BlockDevice>,
+) {
+    write_index(dir_inode, block, block_device, |data| {
+        write_entries(data, 0, list)
+    });
+}
+
+/// 哈希值 `hash` 所在的叶子在根节点中的位置
+fn leaf_pos(leaves: &[(u32, u32)], hash: u32) -> Option<usize> {
+    leaves
+        .partition_point(|&(lower, _)| lower <= hash)
+        .checked_sub(1)
+}
+
+/// 取一个新的索引块：优先使用除第一个块以外完全空闲的目录块，例如删除索引时留下的块，
+/// 没有时在目录末尾追加一个块，返回它在目录中的块号
+fn take_block(
+    dir_inode: &mut DiskInode,
+    alloc: &mut impl FnMut(u32, u32) -> (u32, u32),
+    block_device: &Arc<dyn BlockDevice>,
+) -> u32 {
+    let mut header = [0u8; DIRENT_HEADER_SZ];
+    let empty = (1..dir_inode.data_blocks()).find(|&block| {
+        dir_inode.read_at(block as usize * BLOCK_SZ, &mut header, block_device);
+        let header = DirEntryHeader::from_bytes(&header);
+        header.name_len == 0 && header.rec_len as usize == BLOCK_SZ && !header.is_dir_index()
+    });
+    empty.unwrap_or_else(|| append_block(dir_inode, alloc, block_device))
+}
+
+/// 在目录末尾追加一个块，返回它在目录中的块号
+fn append_block(
+    dir_inode: &mut DiskInode,
+    alloc: &mut impl FnMut(u32, u32) -> (u32, u32),
+    block_device: &Arc<dyn BlockDevice>,
+) -> u32 {
+    let block = dir_inode.data_blocks();
+    // 目录不使用区段树，不会有需要释放的块
+    dir_inode.map_blocks(block..block + 1, alloc, block_device);
+    dir_inode.size = (block + 1) * BLOCK_SZ as u32;
+    block
+}
+
+/// 用哈希索引查找名为 `name` 的目录项，返回它的偏移和 inode 编号
+pub fn lookup(
+    dir_inode: &DiskInode,
+    name: &str,
+    block_device: &Arc<dyn BlockDevice>,
+) -> Option<(usize, u32)> {
+    let hash = name_hash(name.as_bytes());
+    let (_, leaves) = read_root(dir_inode, block_device);
+    let leaf = leaves[leaf_pos(&leaves, hash)?].1;
+    let data = read_block(dir_inode, leaf, block_device);
+    let mut record = [0u8; DIRENT_HEADER_SZ + NAME_LENGTH_LIMIT];
+    entries(&data, 0)
+        .into_iter()
+        .filter(|&(entry_hash, _)| entry_hash == hash)
+        .find_map(|(_, offset)| {
+            // 哈希值相同时读出目录项比较名称
+            let record = &mut record[..DIRENT_HEADER_SZ + name.len()];
+            let len = dir_inode.read_at(offset as usize, record, block_device);
+            let header = DirEntryHeader::from_bytes(record);
+            (len == record.len()
+                && header.name_len as usize == name.len()
+                && &record[DIRENT_HEADER_SZ..] == name.as_bytes())
+            .then_some((offset as usize, header.inode_number))
+        })
+}
+
+/// 把偏移为 `offset` 的目录项 `name` 加入索引，索引放不下时删除索引，之后一直线性查找
+pub fn insert(
+    dir_inode: &mut DiskInode,
+    name: &[u8],
+    offset: usize,
+    alloc: &mut impl FnMut(u32, u32) -> (u32, u32),
+    block_device: &Arc<dyn BlockDevice>,
+) {
+    if !insert_entry(
+        dir_inode,
+        name_hash(name),
+        offset as u32,
+        alloc,
+        block_device,
+    ) {
+        drop_full_index(dir_inode, block_device);
+    }
+}
+
+fn insert_entry(
+    dir_inode: &mut DiskInode,
+    hash: u32,
+    offset: u32,
+    alloc: &mut impl FnMut(u32, u32) -> (u32, u32),
+    block_device: &Arc<dyn BlockDevice>,
+) -> bool {
+    let (free_map, mut leaves) = read_root(dir_inode, block_device);
+    let Some(pos) = leaf_pos(&leaves, hash) else {
+        return false;
+    };
+    let leaf = leaves[pos].1;
+    let mut list = entries(&read_block(dir_inode, leaf, block_device), 0);
+    let at = list.partition_point(|&entry| entry < (hash, offset));
+    list.insert(at, (hash, offset));
+    if list.len() <= INDEX_ENTRIES {
+        write_leaf(dir_inode, leaf, &list, block_device);
+        return true;
+    }
+    if leaves.len() == INDEX_ENTRIES {
+        return false;
+    }
+    // 在中点附近哈希值变化的位置分裂，哈希值相同的表项留在同一个叶子中
+    let mid = list.len() / 2;
+    let Some(split) = (mid..list.len())
+        .chain((1..mid).rev())
+        .find(|&i| list[i - 1].0 != list[i].0)
+    else {
+        return false;
+    };
+    let upper = list.split_off(split);
+    let new_leaf = take_block(dir_inode, alloc, block_device);
+    write_leaf(dir_inode, leaf, &list, block_device);
+    write_leaf(dir_inode, new_leaf, &upper, block_device);
+    leaves.insert(pos + 1, (upper[0].0, new_leaf));
+    write_root(dir_inode, free_map, &leaves, block_device);
+    // 重新使用的块原来记为空闲
+    update_free(dir_inode, new_leaf, block_device);
+    true
+}
+
+/// 从索引中移除偏移为 `offset` 的目录项 `name`
+pub fn remove(
+    dir_inode: &mut DiskInode,
+    name: &[u8],
+    offset: usize,
+    block_device: &Arc<dyn BlockDevice>,
+) {
+    let hash = name_hash(name);
+    let (_, leaves) = read_root(dir_inode, block_device);
+    let Some(pos) = leaf_pos(&leaves, hash) else {
+        return;
+    };
+    let leaf = leaves[pos].1;
+    let mut list = entries(&read_block(dir_inode, leaf, block_device), 0);
+    let len = list.len();
+    list.retain(|&entry| entry != (hash, offset as u32));
+    if list.len() != len {
+        write_leaf(dir_inode, leaf, &list, block_device);
+    }
+}
+
+/// 第一个有不少于 `needed` 字节空闲记录空间的目录块
+///
+/// 空闲空间表之外的块中只考虑最后一块，调用者需要在块中确认空间确实足够
+pub fn find_free(
+    dir_inode: &DiskInode,
+    needed: usize,
+    block_device: &Arc<dyn BlockDevice>,
+) -> Option<u32> {
+    let (free_map, _) = read_root(dir_inode, block_device);
+    let map = read_block(dir_inode, free_map, block_device);
+    let blocks = dir_inode.data_blocks();
+    map[DIRENT_HEADER_SZ..]
+        .iter()
+        .take(blocks as usize)
+        .position(|&free| free as usize * 4 >= needed)
+        .map(|block| block as u32)
+        .or_else(|| (blocks as usize > FREE_MAP_BLOCKS).then(|| blocks - 1))
+}
+
+/// 目录的第 `block` 块中的记录发生变化后，更新空闲空间表
+pub fn update_free(dir_inode: &mut DiskInode, block: u32, block_device: &Arc<dyn BlockDevice>) {
+    if block as usize >= FREE_MAP_BLOCKS {
+        return;
+    }
+    let data = read_block(dir_inode, block, block_device);
+    let free = dir_records(&data)
+        .into_iter()
+        .map(|(_, header)| header.rec_len as usize - header.used_len())
+        .max()
+        .unwrap_or(0);
+    let (free_map, _) = read_root(dir_inode, block_device);
+    let offset = free_map as usize * BLOCK_SZ + DIRENT_HEADER_SZ + block as usize;
+    let mut units = [0u8];
+    dir_inode.read_at(offset, &mut units, block_device);
+    if units[0] as usize != free / 4 {
+        dir_inode.write_at(offset, &[(free / 4) as u8], block_device);
+    }
+}
+
+/// 目录增长到 `DIR_INDEX_THRESHOLD` 块时建立哈希索引，索引曾经放不下的目录不再建立
+///
+/// 第一个块中的记录原样搬到一个空闲的块中，块内的偏移不变，第一个块改为根节点，
+/// 之后取空闲空间表和第一个叶子，再加入所有目录项
+pub fn build_if_large(
+    dir_inode: &mut DiskInode,
+    alloc: &mut impl FnMut(u32, u32) -> (u32, u32),
+    block_device: &Arc<dyn BlockDevice>,
+) {
+    if dir_inode.has_dir_index()
+        || dir_inode.dir_index_full()
+        || dir_inode.data_blocks() < DIR_INDEX_THRESHOLD
+    {
+        return;
+    }
+    let first = read_block(dir_inode, 0, block_device);
+    let moved = take_block(dir_inode, alloc, block_device);
+    dir_inode.write_at(moved as usize * BLOCK_SZ, &first, block_device);
+    let free_map = take_block(dir_inode, alloc, block_device);
+    write_index(dir_inode, free_map, block_device, |_| {});
+    let leaf = take_block(dir_inode, alloc, block_device);
+    write_leaf(dir_inode, leaf, &[], block_device);
+    write_root(dir_inode, free_map, &[(0, leaf)], block_device);
+    dir_inode.set_dir_index(true);
+
+    for block in 0..dir_inode.data_blocks() {
+        update_free(dir_inode, block, block_device);
+    }
+    let mut data = vec![0u8; dir_inode.size as usize];
+    dir_inode.read_at(0, &mut data, block_device);
+    for (offset, dirent) in parse_dir(&data) {
+        let hash = name_hash(dirent.name().as_bytes());
+        if !insert_entry(dir_inode, hash, offset as u32, alloc, block_device) {
+            drop_full_index(dir_inode, block_device);
+            return;
+        }
+    }
+}
+
+/// 所有叶子都为空时删除哈希索引，此后目录中的每个块都是空闲的
+pub fn drop_if_empty(dir_inode: &mut DiskInode, block_device: &Arc<dyn BlockDevice>) {
+    let (_, leaves) = read_root(dir_inode, block_device);
+    let empty = leaves
+        .iter()
+        .all(|&(_, leaf)| word(&read_block(dir_inode, leaf, block_device), 0) == 0);
+    if empty {
+        drop_index(dir_inode, block_device);
+    }
+}
+
+/// 索引放不下全部目录项时删除索引，并记录不再为这个目录建立索引
+fn drop_full_index(dir_inode: &mut DiskInode, block_device: &Arc<dyn BlockDevice>) {
+    drop_index(dir_inode, block_device);
+    dir_inode.set_dir_index_full();
+}
+
+/// 删除哈希索引，索引块变为空闲的目录块
+fn drop_index(dir_inode: &mut DiskInode, block_device: &Arc<dyn BlockDevice>) {
+    let (free_map, leaves) = read_root(dir_inode, block_device);
+    let header = DirEntryHeader {
+        inode_number: 0,
+        rec_len: BLOCK_SZ as u16,
+        name_len: 0,
+        file_type: 0,
+    };
+    let mut empty = [0u8; BLOCK_SZ];
+    empty[..DIRENT_HEADER_SZ].copy_from_slice(&header.to_bytes());
+    let data_blocks = dir_inode.data_blocks();
+    let blocks = leaves.iter().map(|&(_, leaf)| leaf).chain([0, free_map]);
+    for block in blocks.filter(|&block| block < data_blocks) {
+        let data = read_block(dir_inode, block, block_device);
+        if DirEntryHeader::from_bytes(&data).is_dir_index() {
+            dir_inode.write_at(block as usize * BLOCK_SZ, &empty, block_device);
+        }
+    }
+    dir_inode.set_dir_index(false);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_device::{bump_alloc, setup};
+    use crate::{DiskInodeType, get_block_cache};
+    use alloc::format;
+
+    #[test]
+    fn full_index_is_dropped_for_good() {
+        let (_guard, device) = setup(512);
+        let mut next = 1;
+        let mut alloc = bump_alloc(&mut next);
+        let mut empty = [0u8; BLOCK_SZ];
+        let header = DirEntryHeader {
+            inode_number: 0,
+            rec_len: BLOCK_SZ as u16,
+            name_len: 0,
+            file_type: 0,
+        };
+        empty[..DIRENT_HEADER_SZ].copy_from_slice(&header.to_bytes());
+        get_block_cache(0, Arc::clone(&device))
+            .lock()
+            .modify(0, |dir_inode: &mut DiskInode| {
+                dir_inode.initialize(DiskInodeType::Directory, 0);
+                for _ in 0..DIR_INDEX_THRESHOLD {
+                    let block = append_block(dir_inode, &mut alloc, &device);
+                    dir_inode.write_at(block as usize * BLOCK_SZ, &empty, &device);
+                }
+                build_if_large(dir_inode, &mut alloc, &device);
+                assert!(dir_inode.has_dir_index());
+                // 索引块使用目录中原有的空闲块
+                assert_eq!(dir_inode.data_blocks(), DIR_INDEX_THRESHOLD);
+
+                // 只测试索引本身，目录项的偏移不需要指向真正的记录
+                let mut count = 0;
+                while dir_inode.has_dir_index() {
+                    let name = format!("entry-{}", count);
+                    insert(dir_inode, name.as_bytes(), count * 16, &mut alloc, &device);
+                    count += 1;
+                }
+                assert!(dir_inode.dir_index_full());
+                assert!(count > INDEX_ENTRIES * INDEX_ENTRIES / 2);
+                let blocks = dir_inode.data_blocks();
+                assert!(blocks <= DIR_INDEX_THRESHOLD + INDEX_ENTRIES as u32);
+
+                // 之后不再建立索引，删除索引留下的空闲块被重新使用
+                build_if_large(dir_inode, &mut alloc, &device);
+                assert!(!dir_inode.has_dir_index());
+                assert_eq!(dir_inode.data_blocks(), blocks);
+                assert!(take_block(dir_inode, &mut alloc, &device) < blocks);
+                assert_eq!(dir_inode.data_blocks(), blocks);
+            });
+    }
+}
@@ -0,0 +1,453 @@
+use super::{BLOCK_SZ, BlockDevice, get_block_cache};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// inode 中块指针区域的字数，使用区段树时存放树的根节点
+pub const ROOT_WORDS: usize = 22;
+/// 一个树节点块的字数
+const NODE_WORDS: usize = BLOCK_SZ / 4;
+
+type NodeBlock = [u32; NODE_WORDS];
+
+/// 区段：从逻辑块 `logical` 开始的 `len` 个数据块连续存放在从 `start` 开始的磁盘块中
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Extent {
+    pub logical: u32,
+    pub start: u32,
+    pub len: u32,
+}
+
+impl Extent {
+    pub fn end(&self) -> u32 {
+        self.logical + self.len
+    }
+
+    /// 逻辑块 `logical` 所在的磁盘块，不属于该区段时返回 None
+    pub fn get(&self, logical: u32) -> Option<u32> {
+        (self.logical..self.end())
+            .contains(&logical)
+            .then(|| self.start + (logical - self.logical))
+    }
+}
+
+/// 区段树的节点
+///
+/// 编码后的第一个字中，低 16 位是表项数，高 16 位是节点的高度。高度为 0 的叶子节点存放区段，
+/// 每项 3 个字；其余节点存放子树的第一个逻辑块和子节点所在的块，每项 2 个字，子节点的高度比它小 1
+enum Node {
+    Leaf(Vec<Extent>),
+    Index(u32, Vec<(u32, u32)>),
+}
+
+impl Node {
+    /// `words` 个字的节点最多容纳的区段数和索引项数
+    const fn capacity(words: usize) -> (usize, usize) {
+        ((words - 1) / 3, (words - 1) / 2)
+    }
+
+    fn height(&self) -> u32 {
+        match self {
+            Self::Leaf(_) => 0,
+            Self::Index(height, _) => *height,
+        }
+    }
+
+    fn decode(words: &[u32]) -> Self {
+        let count = (words[0] & 0xffff) as usize;
+        let height = words[0] >> 16;
+        if height == 0 {
+            Self::Leaf(
+                words[1..]
+                    .chunks_exact(3)
+                    .take(count)
+                    .map(|entry| Extent {
+                        logical: entry[0],
+                        start: entry[1],
+                        len: entry[2],
+                    })
+                    .collect(),
+            )
+        } else {
+            Self::Index(
+                height,
+                words[1..]
+                    .chunks_exact(2)
+                    .take(count)
+                    .map(|entry| (entry[0], entry[1]))
+                    .collect(),
+            )
+        }
+    }
+
+    fn encode(&self, words: &mut [u32]) {
+        words.fill(0);
+        match self {
+            Self::Leaf(extents) => {
+                words[0] = extents.len() as u32;
+                for (entry, extent) in words[1..].chunks_exact_mut(3).zip(extents) {
+                    entry.copy_from_slice(&[extent.logical, extent.start, extent.len]);
+                }
+            }
+            Self::Index(height, children) => {
+                words[0] = children.len() as u32 | height << 16;
+                for (entry, &(first, block_id)) in words[1..].chunks_exact_mut(2).zip(children) {
+                    entry.copy_from_slice(&[first, block_id]);
+                }
+            }
+        }
+    }
+
+    /// 读出块 `block_id` 中的节点，高度不是 `height` 时说明树已损坏，当作空节点
+    fn read(block_id: u32, height: u32, block_device: &Arc<dyn BlockDevice>) -> Self {
+        let node = get_block_cache(block_id as usize, Arc::clone(block_device))
+            .lock()
+            .read(0, |node_block: &NodeBlock| Self::decode(node_block));
+        if node.height() == height {
+            node
+        } else {
+            Self::Leaf(Vec::new())
+        }
+    }
+
+    /// 把节点写入块 `block_id`，内容没有变化时不修改缓存，避免把它写入日志
+    fn write(&self, block_id: u32, block_device: &Arc<dyn BlockDevice>) {
+        let mut words = [0u32; NODE_WORDS];
+        self.encode(&mut words);
+        let block_cache = get_block_cache(block_id as usize, Arc::clone(block_device));
+        let mut block_cache = block_cache.lock();
+        if block_cache.read(0, |node_block: &NodeBlock| *node_block != words) {
+            block_cache.modify(0, |node_block: &mut NodeBlock| *node_block = words);
+        }
+    }
+}
+
+/// 逻辑块 `logical` 所在的磁盘块，属于空洞时返回 0
+pub fn lookup(root: &[u32; ROOT_WORDS], logical: u32, block_device: &Arc<dyn BlockDevice>) -> u32 {
+    let mut node = Node::decode(root);
+    loop {
+        match node {
+            Node::Leaf(extents) => {
+                return extents
+                    .iter()
+                    .find_map(|extent| extent.get(logical))
+                    .unwrap_or(0);
+            }
+            Node::Index(height, children) => {
+                // 最后一个第一个逻辑块不大于 `logical` 的子树
+                let Some(&(_, block_id)) =
+                    children.iter().rev().find(|(first, _)| *first <= logical)
+                else {
+                    return 0;
+                };
+                node = Node::read(block_id, height - 1, block_device);
+            }
+        }
+    }
+}
+
+/// 按逻辑块顺序读出 `limit` 之前的区段，跨过 `limit` 的区段被截短，同时返回读过的树节点块
+///
+/// 第一个逻辑块不小于 `limit` 的子树不会被读取
+pub fn load(
+    root: &[u32; ROOT_WORDS],
+    limit: u32,
+    block_device: &Arc<dyn BlockDevice>,
+) -> (Vec<Extent>, Vec<u32>) {
+    fn load_node(
+        node: Node,
+        limit: u32,
+        block_device: &Arc<dyn BlockDevice>,
+        extents: &mut Vec<Extent>,
+        blocks: &mut Vec<u32>,
+    ) {
+        match node {
+            Node::Leaf(list) => {
+                for mut extent in list.into_iter().take_while(|extent| extent.logical < limit) {
+                    extent.len = extent.len.min(limit - extent.logical);
+                    extents.push(extent);
+                }
+            }
+            Node::Index(height, children) => {
+                for (_, block_id) in children.into_iter().take_while(|(first, _)| *first < limit) {
+                    blocks.push(block_id);
+                    let child = Node::read(block_id, height - 1, block_device);
+                    load_node(child, limit, block_device, extents, blocks);
+                }
+            }
+        }
+    }
+    let mut extents = Vec::new();
+    let mut blocks = Vec::new();
+    load_node(
+        Node::decode(root),
+        limit,
+        block_device,
+        &mut extents,
+        &mut blocks,
+    );
+    (extents, blocks)
+}
+
+/// 按逻辑块顺序检查区段树引用的块，树节点块在读取之前检查
+///
+/// 遇到 `valid` 返回 false 的块时停止，返回此前检查过的块和停止处的逻辑块，
+/// 所有块都有效时停止处为 `u32::MAX`
+pub fn walk(
+    root: &[u32; ROOT_WORDS],
+    block_device: &Arc<dyn BlockDevice>,
+    valid: &mut impl FnMut(u32) -> bool,
+) -> (Vec<u32>, u32) {
+    fn walk_node(
+        node: Node,
+        block_device: &Arc<dyn BlockDevice>,
+        valid: &mut impl FnMut(u32) -> bool,
+        blocks: &mut Vec<u32>,
+    ) -> Result<(), u32> {
+        match node {
+            Node::Leaf(extents) => {
+                for extent in extents {
+                    for i in 0..extent.len {
+                        let block_id = extent.start.wrapping_add(i);
+                        if !valid(block_id) {
+                            return Err(extent.logical.saturating_add(i));
+                        }
+                        blocks.push(block_id);
+                    }
+                }
+            }
+            Node::Index(height, children) => {
+                for (first, block_id) in children {
+                    if !valid(block_id) {
+                        return Err(first);
+                    }
+                    blocks.push(block_id);
+                    let child = Node::read(block_id, height - 1, block_device);
+                    walk_node(child, block_device, valid, blocks)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    let mut blocks = Vec::new();
+    let stop = walk_node(Node::decode(root), block_device, valid, &mut blocks)
+        .err()
+        .unwrap_or(u32::MAX);
+    (blocks, stop)
+}
+
+/// 为 `range` 中没有被区段覆盖的部分分配数据块，返回是否分配了新的块
+///
+/// 每段空洞都尽量接在它前面的区段之后分配，`alloc` 的含义与 `DiskInode::map_blocks` 相同
+pub fn fill_holes(
+    extents: &mut Vec<Extent>,
+    range: Range<u32>,
+    alloc: &mut impl FnMut(u32, u32) -> (u32, u32),
+) -> bool {
+    let mut logical = range.start;
+    let mut changed = false;
+    while logical < range.end {
+        if let Some(extent) = extents.iter().find(|extent| extent.get(logical).is_some()) {
+            logical = extent.end();
+            continue;
+        }
+        let hole_end = extents
+            .iter()
+            .map(|extent| extent.logical)
+            .filter(|&first| first > logical)
+            .fold(range.end, u32::min);
+        let goal = extents
+            .iter()
+            .find(|extent| extent.end() == logical)
+            .map_or(0, |extent| extent.start + extent.len);
+        let (start, len) = alloc(goal, hole_end - logical);
+        extents.push(Extent {
+            logical,
+            start,
+            len,
+        });
+        logical += len;
+        changed = true;
+    }
+    changed
+}
+
+/// 把区段写成一棵区段树，返回根节点和没有用到的树节点块
+///
+/// 区段先按逻辑块排序，逻辑上和磁盘上都相邻的区段合并为一个。
+/// 树节点块优先复用 `blocks` 中的块，不够时用 `alloc` 分配
+pub fn store(
+    mut extents: Vec<Extent>,
+    blocks: Vec<u32>,
+    alloc: &mut impl FnMut(u32, u32) -> (u32, u32),
+    block_device: &Arc<dyn BlockDevice>,
+) -> ([u32; ROOT_WORDS], Vec<u32>) {
+    extents.retain(|extent| extent.len > 0);
+    extents.sort_by_key(|extent| extent.logical);
+    let mut merged: Vec<Extent> = Vec::with_capacity(extents.len());
+    for extent in extents {
+        match merged.last_mut() {
+            Some(last) if last.end() == extent.logical && last.start + last.len == extent.start => {
+                last.len += extent.len;
+            }
+            _ => merged.push(extent),
+        }
+    }
+
+    let (root_extents, root_children) = Node::capacity(ROOT_WORDS);
+    let (node_extents, node_children) = Node::capacity(NODE_WORDS);
+    let mut blocks = blocks.into_iter();
+    let mut next_block = || blocks.next().unwrap_or_else(|| alloc(0, 1).0);
+    let mut root = [0u32; ROOT_WORDS];
+    if merged.len() <= root_extents {
+        Node::Leaf(merged).encode(&mut root);
+    } else {
+        // 自底向上逐层填满节点，直到剩下的子树能放进根节点
+        let mut level: Vec<(u32, u32)> = merged
+            .chunks(node_extents)
+            .map(|chunk| {
+                let block_id = next_block();
+                Node::Leaf(chunk.to_vec()).write(block_id, block_device);
+                (chunk[0].logical, block_id)
+            })
+            .collect();
+        let mut height = 1;
+        while level.len() > root_children {
+            level = level
+                .chunks(node_children)
+                .map(|chunk| {
+                    let block_id = next_block();
+                    Node::Index(height, chunk.to_vec()).write(block_id, block_device);
+                    (chunk[0].0, block_id)
+                })
+                .collect();
+            height += 1;
+        }
+        Node::Index(height, level).encode(&mut root);
+    }
+    (root, blocks.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_device::{bump_alloc, setup};
+    use alloc::vec;
+
+    /// 逻辑上相邻但磁盘上不相邻的区段，不能合并
+    fn fragmented(count: u32) -> Vec<Extent> {
+        (0..count)
+            .map(|i| Extent {
+                logical: i * 2,
+                start: 1000 + i * 3,
+                len: 2,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn store_merges_adjacent_extents() {
+        let (_guard, device) = setup(16);
+        let mut next = 1;
+        let extents = vec![
+            Extent {
+                logical: 4,
+                start: 104,
+                len: 4,
+            },
+            Extent {
+                logical: 0,
+                start: 100,
+                len: 4,
+            },
+            Extent {
+                logical: 8,
+                start: 200,
+                len: 0,
+            },
+        ];
+        let (root, unused) = store(extents, vec![], &mut bump_alloc(&mut next), &device);
+        assert!(unused.is_empty());
+        assert_eq!(next, 1);
+        let (loaded, nodes) = load(&root, u32::MAX, &device);
+        assert_eq!(
+            loaded,
+            vec![Extent {
+                logical: 0,
+                start: 100,
+                len: 8,
+            }]
+        );
+        assert!(nodes.is_empty());
+    }
+
+    #[test]
+    fn store_splits_extents_into_leaves() {
+        let (_guard, device) = setup(64);
+        let mut next = 1;
+        // 根节点放不下时每个叶子最多 42 个区段，100 个区段占用 3 个叶子
+        let extents = fragmented(100);
+        let (root, unused) = store(extents.clone(), vec![], &mut bump_alloc(&mut next), &device);
+        assert!(unused.is_empty());
+        assert_eq!(next, 4);
+        let (loaded, nodes) = load(&root, u32::MAX, &device);
+        assert_eq!(loaded, extents);
+        assert_eq!(nodes, vec![1, 2, 3]);
+        for extent in extents.iter() {
+            assert_eq!(lookup(&root, extent.logical + 1, &device), extent.start + 1);
+        }
+        assert_eq!(lookup(&root, 200, &device), 0);
+
+        // 区段减少后多余的节点块交还给调用者
+        let (root, unused) = store(fragmented(50), nodes, &mut bump_alloc(&mut next), &device);
+        assert_eq!(unused, vec![3]);
+        assert_eq!(load(&root, u32::MAX, &device).0, fragmented(50));
+    }
+
+    #[test]
+    fn load_cuts_extents_at_limit() {
+        let (_guard, device) = setup(64);
+        let mut next = 1;
+        let (root, _) = store(fragmented(100), vec![], &mut bump_alloc(&mut next), &device);
+        // 跨过 limit 的区段被截短，第一个逻辑块不小于 limit 的叶子不被读取
+        let (loaded, nodes) = load(&root, 101, &device);
+        assert_eq!(loaded.len(), 51);
+        assert_eq!(
+            loaded.last(),
+            Some(&Extent {
+                logical: 100,
+                start: 1150,
+                len: 1,
+            })
+        );
+        assert_eq!(nodes, vec![1, 2]);
+    }
+
+    #[test]
+    fn fill_holes_around_existing_extents() {
+        let mut next = 500;
+        let mut extents = vec![Extent {
+            logical: 10,
+            start: 100,
+            len: 5,
+        }];
+        assert!(fill_holes(&mut extents, 0..20, &mut bump_alloc(&mut next)));
+        // 空洞被已有的区段分成两段，后一段接在已有区段之后分配
+        assert_eq!(
+            extents[1..],
+            [
+                Extent {
+                    logical: 0,
+                    start: 500,
+                    len: 10,
+                },
+                Extent {
+                    logical: 15,
+                    start: 510,
+                    len: 5,
+                },
+            ]
+        );
+        assert!(!fill_holes(&mut extents, 0..20, &mut bump_alloc(&mut next)));
+    }
+}
@@ -1,34 +1,122 @@
 use super::{
-    Bitmap, BlockDevice, DiskInode, DiskInodeType, Inode, SuperBlock, block_cache_sync_all,
-    get_block_cache,
+    Bitmap, BlockDevice, ChecksumTable, DiskInode, DiskInodeType, Inode, InodeCache,
+    JOURNAL_BLOCKS, Journal, LEGACY_NAME_LENGTH_LIMIT, NAME_LENGTH_LIMIT, SuperBlock,
+    forget_checksum, get_block_cache, metadata_errors, set_checksum_table,
 };
 use crate::BLOCK_SZ;
 use alloc::sync::Arc;
-use spin::Mutex;
+use core::ops::Deref;
+use spin::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
+/// 文件系统
+///
+/// 没有全局的锁：每个 `Inode` 有自己的读写锁，两个位图各有一个分配器的锁，
+/// 修改操作通过 `begin` 加入当前事务，提交时等待正在进行的操作全部结束
 pub struct FileSystem {
     pub block_device: Arc<dyn BlockDevice>,
-    pub inode_bitmap: Bitmap,
-    pub data_bitmap: Bitmap,
+    pub inode_bitmap: Mutex<Bitmap>,
+    pub data_bitmap: Mutex<Bitmap>,
     inode_area_start_block: u32,
     data_area_start_block: u32,
+    data_area_blocks: u32,
+    /// 时间戳来源，返回自 Unix 纪元以来的秒数
+    clock: Mutex<fn() -> u32>,
+    /// 旧格式镜像使用定长目录项，只能读取目录
+    legacy_dirents: bool,
+    /// 镜像允许 inode 使用区段树
+    extents: bool,
+    /// 镜像允许目录建立哈希索引
+    dir_index: bool,
+    /// 镜像允许 inode 带有扩展属性
+    xattrs: bool,
+    /// 镜像允许文件压缩存放
+    compression: bool,
+    journal: Journal,
+    /// 每个进行中的修改操作持有读锁，提交时持有写锁，保证只提交完整的操作
+    txn_lock: RwLock<()>,
+    /// 同时修改两个目录的重命名持有这个锁，检查子树期间目录树的结构不变
+    pub(crate) rename_lock: Mutex<()>,
+    inode_cache: InodeCache,
+}
+
+/// 一次修改操作，结束时提交这次操作和同时进行的其他操作的修改
+///
+/// 还有其他操作没有结束时不等待，由最后结束的操作一起提交
+pub struct Transaction<'a> {
+    fs: &'a FileSystem,
+    guard: Option<RwLockReadGuard<'a, ()>>,
+}
+
+impl Transaction<'_> {
+    /// 结束操作并提交，与直接丢弃相同
+    pub fn commit(self) {}
+
+    /// 在较长操作中文件系统处于一致状态的位置调用，未提交的修改快要放不下日志区时先提交已完成的部分
+    ///
+    /// 有其他操作正在进行时不等待，继续积累修改
+    pub fn checkpoint(&mut self) {
+        if !self.fs.journal.nearly_full() {
+            return;
+        }
+        self.guard.take();
+        if let Some(_guard) = self.fs.txn_lock.try_write() {
+            self.fs.journal.commit(&self.fs.block_device);
+        }
+        self.guard = Some(self.fs.txn_lock.read());
+    }
+}
+
+impl Deref for Transaction<'_> {
+    type Target = FileSystem;
+
+    fn deref(&self) -> &FileSystem {
+        self.fs
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        // 先退出事务，获取写锁失败说明还有操作没有结束，或者有人正在提交
+        self.guard.take();
+        if let Some(_guard) = self.fs.txn_lock.try_write() {
+            self.fs.journal.commit(&self.fs.block_device);
+        }
+    }
 }
 
 type DataBlock = [u8; BLOCK_SZ];
 
+/// 文件系统的容量和使用情况，对应 `statfs` 中与文件系统相关的字段
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FsStat {
+    /// 数据区的块数和其中空闲的块数
+    pub blocks: u32,
+    pub free_blocks: u32,
+    /// inode 总数和其中空闲的 inode 数
+    pub inodes: u32,
+    pub free_inodes: u32,
+    /// 目录项名称的最大长度
+    pub name_max: u32,
+}
+
 impl FileSystem {
     pub fn create(
         block_device: Arc<dyn BlockDevice>,
         total_blocks: u32,
         inode_bitmap_blocks: u32,
-    ) -> Arc<Mutex<Self>> {
+    ) -> Arc<Self> {
         let inode_bitmap = Bitmap::new(1, inode_bitmap_blocks as usize);
         let inode_num = inode_bitmap.maximum();
         let inode_area_blocks =
             ((inode_num * core::mem::size_of::<DiskInode>() + BLOCK_SZ - 1) / BLOCK_SZ) as u32;
         let inode_total_blocks = inode_bitmap_blocks + inode_area_blocks;
 
-        let data_total_blocks = total_blocks - 1 - inode_total_blocks;
+        // 校验和表位于数据区和日志区之间
+        let csum_blocks = ChecksumTable::blocks_for(total_blocks);
+        let csum_start = total_blocks - JOURNAL_BLOCKS - csum_blocks;
+        set_checksum_table(Some(ChecksumTable::new(csum_start, csum_blocks)));
+
+        let data_total_blocks = csum_start - 1 - inode_total_blocks;
         let data_bitmap_blocks = (data_total_blocks + 4096) / 4097;
         let data_area_blocks = data_total_blocks - data_bitmap_blocks;
         let data_bitmap = Bitmap::new(
@@ -36,19 +124,30 @@ impl FileSystem {
             data_bitmap_blocks as usize,
         );
 
-        let mut fs = Self {
+        let fs = Self {
             block_device: Arc::clone(&block_device),
-            inode_bitmap,
-            data_bitmap,
+            inode_bitmap: Mutex::new(inode_bitmap),
+            data_bitmap: Mutex::new(data_bitmap),
             inode_area_start_block: 1 + inode_bitmap_blocks,
             data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            data_area_blocks,
+            clock: Mutex::new(no_clock),
+            legacy_dirents: false,
+            extents: true,
+            dir_index: true,
+            xattrs: true,
+            compression: true,
+            journal: Journal::new(total_blocks - JOURNAL_BLOCKS, JOURNAL_BLOCKS),
+            txn_lock: RwLock::new(()),
+            rename_lock: Mutex::new(()),
+            inode_cache: InodeCache::new(),
         };
 
         // 清零
         for i in 0..total_blocks {
             get_block_cache(i as usize, Arc::clone(&block_device))
                 .lock()
-                .modify(0, |data_block: &mut DataBlock| {
+                .modify_data(0, |data_block: &mut DataBlock| {
                     for byte in data_block.iter_mut() {
                         *byte = 0;
                     }
@@ -65,6 +164,7 @@ impl FileSystem {
                     inode_area_blocks,
                     data_bitmap_blocks,
                     data_area_blocks,
+                    JOURNAL_BLOCKS,
                 );
             },
         );
@@ -75,42 +175,214 @@ impl FileSystem {
         get_block_cache(root_inode_block_id as usize, Arc::clone(&block_device))
             .lock()
             .modify(root_inode_offset, |disk_inode: &mut DiskInode| {
-                disk_inode.initialize(DiskInodeType::Directory);
+                disk_inode.initialize(DiskInodeType::Directory, fs.now());
             });
-        block_cache_sync_all();
-        Arc::new(Mutex::new(fs))
+        fs.commit();
+        Arc::new(fs)
     }
 
     /// 在一个已写入了文件系统镜像的块设备上打开文件系统
-    pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
-        // read SuperBlock
-        get_block_cache(0, Arc::clone(&block_device))
+    ///
+    /// 打开前先重放日志中已提交但没有写回的事务。超级块的 magic 不认识或者校验和不符时返回 None
+    pub fn open(block_device: Arc<dyn BlockDevice>) -> Option<Arc<Self>> {
+        let journal = get_block_cache(0, Arc::clone(&block_device)).lock().read(
+            0,
+            |super_block: &SuperBlock| {
+                super_block.is_valid().then(|| {
+                    Journal::new(
+                        super_block.total_blocks - super_block.journal_blocks,
+                        super_block.journal_blocks,
+                    )
+                })
+            },
+        )?;
+        journal.replay(&block_device);
+        let table = get_block_cache(0, Arc::clone(&block_device))
             .lock()
-            .read(0, |super_block: &SuperBlock| {
-                assert!(super_block.is_valid(), "Error loading EFS!");
+            .read(0, |super_block: &SuperBlock| super_block.checksum_table());
+        set_checksum_table(table);
+        // read SuperBlock
+        let (efs, maintained) = get_block_cache(0, Arc::clone(&block_device)).lock().read(
+            0,
+            |super_block: &SuperBlock| {
                 let inode_total_blocks =
                     super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
                 let efs = Self {
                     block_device,
-                    inode_bitmap: Bitmap::new(1, super_block.inode_bitmap_blocks as usize),
-                    data_bitmap: Bitmap::new(
+                    inode_bitmap: Mutex::new(Bitmap::new(
+                        1,
+                        super_block.inode_bitmap_blocks as usize,
+                    )),
+                    data_bitmap: Mutex::new(Bitmap::new(
                         (1 + inode_total_blocks) as usize,
                         super_block.data_bitmap_blocks as usize,
-                    ),
+                    )),
                     inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
                     data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks,
+                    data_area_blocks: super_block.data_area_blocks,
+                    clock: Mutex::new(no_clock),
+                    legacy_dirents: super_block.has_legacy_dirents(),
+                    extents: super_block.supports_extents(),
+                    dir_index: super_block.supports_dir_index(),
+                    xattrs: super_block.supports_xattrs(),
+                    compression: super_block.supports_compression(),
+                    journal,
+                    txn_lock: RwLock::new(()),
+                    rename_lock: Mutex::new(()),
+                    inode_cache: InodeCache::new(),
                 };
-                Arc::new(Mutex::new(efs))
-            })
+                (efs, super_block.maintains_free_counts())
+            },
+        );
+        // 旧格式的镜像可能被不维护空闲计数的内核修改过，计数过期时也扫描位图重新统计
+        if !maintained || efs.free_counts().is_none() {
+            efs.recount_free();
+            efs.commit();
+        }
+        Some(Arc::new(efs))
+    }
+
+    /// 开始一次修改操作，每个 `Inode` 的修改操作在修改元数据之前调用
+    pub fn begin(&self) -> Transaction<'_> {
+        Transaction {
+            fs: self,
+            guard: Some(self.txn_lock.read()),
+        }
+    }
+
+    /// 等待正在进行的修改操作结束，然后提交所有修改
+    pub fn commit(&self) {
+        let _guard = self.txn_lock.write();
+        self.journal.commit(&self.block_device);
+    }
+
+    /// 没有进行中的修改操作时提交所有修改，否则什么也不做并返回 false
+    pub fn try_commit(&self) -> bool {
+        let Some(_guard) = self.txn_lock.try_write() else {
+            return false;
+        };
+        self.journal.commit(&self.block_device);
+        true
+    }
+
+    /// 发现过损坏的元数据或者一次提交放不进日志区后不再提交修改
+    pub fn is_read_only(&self) -> bool {
+        metadata_errors() > 0 || self.journal.overflowed()
+    }
+
+    /// 检查期间在一致的状态调用，未提交的修改快要放不下日志区时先提交，调用者持有 `quiesce` 返回的锁
+    pub(crate) fn checkpoint_quiesced(&self) {
+        if self.journal.nearly_full() {
+            // 检查直接修改位图，提交前先让空闲计数与位图一致
+            self.recount_free();
+            self.journal.commit(&self.block_device);
+        }
+    }
+
+    /// 等待正在进行的修改操作结束，返回期间不会有新的修改操作开始
+    pub(crate) fn quiesce(&self) -> RwLockWriteGuard<'_, ()> {
+        self.txn_lock.write()
+    }
+
+    /// 设置时间戳来源，未设置时所有时间戳都为 0
+    pub fn set_clock(&self, clock: fn() -> u32) {
+        *self.clock.lock() = clock;
+    }
+
+    pub fn now(&self) -> u32 {
+        (self.clock.lock())()
+    }
+
+    pub fn has_legacy_dirents(&self) -> bool {
+        self.legacy_dirents
+    }
+
+    pub fn supports_extents(&self) -> bool {
+        self.extents
+    }
+
+    pub fn supports_dir_index(&self) -> bool {
+        self.dir_index
+    }
+
+    pub fn supports_xattrs(&self) -> bool {
+        self.xattrs
+    }
+
+    pub fn supports_compression(&self) -> bool {
+        self.compression
+    }
+
+    /// 超级块中记录的空闲 inode 数和数据块数，计数过期时返回 None
+    pub fn free_counts(&self) -> Option<(u32, u32)> {
+        get_block_cache(0, Arc::clone(&self.block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| super_block.free_counts())
+    }
+
+    /// 扫描两个位图得到的空闲 inode 数和数据块数
+    pub fn count_free(&self) -> (u32, u32) {
+        let inode_bitmap = self.inode_bitmap.lock();
+        let inodes = inode_bitmap.maximum() - inode_bitmap.count_allocated(&self.block_device);
+        let blocks = (self.data_area_blocks as usize)
+            .saturating_sub(self.data_bitmap.lock().count_allocated(&self.block_device));
+        (inodes as u32, blocks as u32)
+    }
+
+    /// 按位图重新统计空闲计数并写入超级块，由调用者提交
+    pub fn recount_free(&self) {
+        let (free_inodes, free_blocks) = self.count_free();
+        get_block_cache(0, Arc::clone(&self.block_device))
+            .lock()
+            .modify(0, |super_block: &mut SuperBlock| {
+                super_block.set_free_counts(free_inodes, free_blocks);
+            });
+    }
+
+    fn adjust_free(&self, inodes: i32, blocks: i32) {
+        get_block_cache(0, Arc::clone(&self.block_device))
+            .lock()
+            .modify(0, |super_block: &mut SuperBlock| {
+                super_block.adjust_free_counts(inodes, blocks);
+            });
+    }
+
+    pub fn stat(&self) -> FsStat {
+        let (free_inodes, free_blocks) = self.free_counts().unwrap_or_else(|| self.count_free());
+        FsStat {
+            blocks: self.data_area_blocks,
+            free_blocks,
+            inodes: self.inode_bitmap.lock().maximum() as u32,
+            free_inodes,
+            name_max: if self.legacy_dirents {
+                LEGACY_NAME_LENGTH_LIMIT
+            } else {
+                NAME_LENGTH_LIMIT
+            } as u32,
+        }
     }
 
     /// Get the root inode
-    pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Inode {
-        let block_device = Arc::clone(&efs.lock().block_device);
-        // acquire efs lock temporarily
-        let (block_id, block_offset) = efs.lock().get_disk_inode_pos(0);
-        // release efs lock
-        Inode::new(block_id, block_offset, Arc::clone(efs), block_device)
+    pub fn root_inode(efs: &Arc<Self>) -> Arc<Inode> {
+        efs.get_inode(0)
+    }
+
+    /// 编号为 `inode_id` 的 inode，同一个 inode 总是得到同一个对象
+    pub(crate) fn get_inode(self: &Arc<Self>, inode_id: u32) -> Arc<Inode> {
+        self.inode_cache.get(inode_id, || {
+            let (block_id, block_offset) = self.get_disk_inode_pos(inode_id);
+            Inode::new(
+                block_id,
+                block_offset,
+                Arc::clone(self),
+                Arc::clone(&self.block_device),
+            )
+        })
+    }
+
+    /// 释放 inode 后从缓存中移除它
+    pub(crate) fn forget_inode(&self, inode_id: u32, inode: &Inode) {
+        self.inode_cache.remove(inode_id, inode);
     }
 
     /// 从 inode位图 或数据块位图上分配的 bit 编号，来算出各个存储inode和数据块的磁盘块在磁盘上的实际位置
@@ -128,37 +400,67 @@ impl FileSystem {
         let inode_size = core::mem::size_of::<DiskInode>();
         let inodes_per_block = (BLOCK_SZ / inode_size) as u32;
         (block_id - self.inode_area_start_block) * inodes_per_block
-            + block_offset as u32 / size_of::<Inode>() as u32
+            + block_offset as u32 / inode_size as u32
     }
 
     pub fn get_data_block_id(&self, data_block_id: u32) -> u32 {
         self.data_area_start_block + data_block_id
     }
 
-    pub fn alloc_inode(&mut self) -> u32 {
-        self.inode_bitmap.alloc(&self.block_device).unwrap() as u32
+    pub fn alloc_inode(&self) -> u32 {
+        let inode_id = self.inode_bitmap.lock().alloc(&self.block_device).unwrap() as u32;
+        self.adjust_free(-1, 0);
+        inode_id
     }
 
-    pub fn dealloc_inode(&mut self, inode_id: u32) {
+    pub fn dealloc_inode(&self, inode_id: u32) {
         self.inode_bitmap
+            .lock()
             .dealloc(&self.block_device, inode_id as usize);
+        self.adjust_free(1, 0);
+    }
+
+    pub fn alloc_data(&self) -> u32 {
+        let block_id = self.data_bitmap.lock().alloc(&self.block_device).unwrap() as u32
+            + self.data_area_start_block;
+        self.adjust_free(0, -1);
+        block_id
     }
 
-    pub fn alloc_data(&mut self) -> u32 {
-        self.data_bitmap.alloc(&self.block_device).unwrap() as u32 + self.data_area_start_block
+    /// 分配最多 `max_len` 个连续的数据块，`goal` 不为 0 时尽量从块 `goal` 开始，返回第一个块和块数
+    pub fn alloc_data_contiguous(&self, goal: u32, max_len: u32) -> (u32, u32) {
+        let goal = goal.saturating_sub(self.data_area_start_block);
+        let (bit, len) = self
+            .data_bitmap
+            .lock()
+            .alloc_contiguous(
+                &self.block_device,
+                goal as usize,
+                max_len as usize,
+                self.data_area_blocks as usize,
+            )
+            .unwrap();
+        self.adjust_free(0, -(len as i32));
+        (bit as u32 + self.data_area_start_block, len as u32)
     }
 
-    pub fn dealloc_data(&mut self, block_id: u32) {
+    pub fn dealloc_data(&self, block_id: u32) {
+        forget_checksum(block_id as usize, &self.block_device);
         get_block_cache(block_id as usize, Arc::clone(&self.block_device))
             .lock()
-            .modify(0, |data_block: &mut DataBlock| {
+            .modify_data(0, |data_block: &mut DataBlock| {
                 data_block.iter_mut().for_each(|p| {
                     *p = 0;
                 })
             });
-        self.data_bitmap.dealloc(
+        self.data_bitmap.lock().dealloc(
             &self.block_device,
             (block_id - self.data_area_start_block) as usize,
-        )
+        );
+        self.adjust_free(0, 1);
     }
 }
+
+fn no_clock() -> u32 {
+    0
+}
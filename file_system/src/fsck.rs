@@ -0,0 +1,343 @@
+use super::{
+    BLOCK_SZ, DiskInode, FileSystem, SuperBlock, get_block_cache, parse_dir, parse_legacy_dir,
+};
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result};
+
+/// 一致性检查发现的问题
+#[derive(Debug, PartialEq)]
+pub enum FsckProblem {
+    /// 根目录未分配或不是目录，无法继续检查
+    BadRoot,
+    /// 目录项指向未分配的 inode
+    DanglingEntry {
+        dir: u32,
+        name: String,
+        inode_id: u32,
+    },
+    /// inode 引用了数据区之外的块
+    BadBlock { inode_id: u32, block_id: u32 },
+    /// 块已被其他 inode 或同一 inode 的其他位置引用
+    DuplicateBlock { inode_id: u32, block_id: u32 },
+    /// 大小超出了 inode 能够索引的范围
+    BadSize { inode_id: u32, size: u32 },
+    /// 链接数与指向 inode 的目录项数不一致
+    WrongLinkCount {
+        inode_id: u32,
+        found: u16,
+        expected: u16,
+    },
+    /// inode 已分配但从根目录不可达
+    OrphanInode { inode_id: u32 },
+    /// 被引用的块在数据位图中未分配
+    UnmarkedBlock { block_id: u32 },
+    /// 数据位图中已分配但没有被引用的块
+    LeakedBlock { block_id: u32 },
+    /// 超级块中的空闲 inode 数和数据块数与位图不一致，计数过期时 `found` 为 None
+    WrongFreeCounts {
+        found: Option<(u32, u32)>,
+        expected: (u32, u32),
+    },
+}
+
+impl Display for FsckProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::BadRoot => write!(f, "root inode is not an allocated directory"),
+            Self::DanglingEntry {
+                dir,
+                name,
+                inode_id,
+            } => write!(
+                f,
+                "entry '{}' in directory {} points to free inode {}",
+                name, dir, inode_id
+            ),
+            Self::BadBlock { inode_id, block_id } => write!(
+                f,
+                "inode {} references block {} outside the data area",
+                inode_id, block_id
+            ),
+            Self::DuplicateBlock { inode_id, block_id } => write!(
+                f,
+                "inode {} references block {} which is already in use",
+                inode_id, block_id
+            ),
+            Self::BadSize { inode_id, size } => {
+                write!(f, "inode {} has invalid size {}", inode_id, size)
+            }
+            Self::WrongLinkCount {
+                inode_id,
+                found,
+                expected,
+            } => write!(
+                f,
+                "inode {} has link count {}, expected {}",
+                inode_id, found, expected
+            ),
+            Self::OrphanInode { inode_id } => {
+                write!(f, "inode {} is allocated but unreachable", inode_id)
+            }
+            Self::UnmarkedBlock { block_id } => {
+                write!(f, "block {} is in use but marked free", block_id)
+            }
+            Self::LeakedBlock { block_id } => {
+                write!(f, "block {} is marked in use but unreferenced", block_id)
+            }
+            Self::WrongFreeCounts {
+                found: Some((inodes, blocks)),
+                expected: (expected_inodes, expected_blocks),
+            } => write!(
+                f,
+                "free counts are {} inodes and {} blocks, expected {} and {}",
+                inodes, blocks, expected_inodes, expected_blocks
+            ),
+            Self::WrongFreeCounts {
+                found: None,
+                expected: (inodes, blocks),
+            } => write!(
+                f,
+                "free counts are missing, expected {} inodes and {} blocks",
+                inodes, blocks
+            ),
+        }
+    }
+}
+
+impl FileSystem {
+    /// 检查文件系统的一致性，返回发现的问题，`repair` 为真时同时修复并提交
+    ///
+    /// 从根目录出发遍历所有可达的 inode，统计指向每个 inode 的目录项数和每个 inode 占用的块，
+    /// 再与两个位图比较。修复时删除悬空的目录项，把引用了无效块的 inode 截断到第一个无效块之前，
+    /// 丢弃无效的属性块，修正链接数，回收不可达的 inode，使数据位图与实际引用的块一致，
+    /// 最后按位图重新统计空闲计数。
+    /// 旧格式镜像的目录不能修改，悬空的目录项只报告不删除。检查期间其他修改操作等待检查结束，
+    /// 修复的修改较多时在每处修复之后分批提交
+    pub fn check(fs: &Arc<Self>, repair: bool) -> Vec<FsckProblem> {
+        let quiesced = fs.quiesce();
+        let block_device = Arc::clone(&fs.block_device);
+        let data_start = fs.get_data_block_id(0);
+        let data_end = data_start
+            + get_block_cache(0, Arc::clone(&block_device))
+                .lock()
+                .read(0, |super_block: &SuperBlock| super_block.data_area_blocks);
+        let inode_count = fs.inode_bitmap.lock().maximum() as u32;
+        let inode_allocated = |inode_id: u32| {
+            inode_id < inode_count
+                && fs
+                    .inode_bitmap
+                    .lock()
+                    .is_allocated(&block_device, inode_id as usize)
+        };
+        let mut problems = Vec::new();
+
+        let found = fs.free_counts();
+        let expected = fs.count_free();
+        if found != Some(expected) {
+            problems.push(FsckProblem::WrongFreeCounts { found, expected });
+        }
+
+        let (root_block, root_offset) = fs.get_disk_inode_pos(0);
+        let root_is_dir = get_block_cache(root_block as usize, Arc::clone(&block_device))
+            .lock()
+            .read(root_offset, |disk_inode: &DiskInode| disk_inode.is_dir());
+        if !inode_allocated(0) || !root_is_dir {
+            problems.push(FsckProblem::BadRoot);
+            return problems;
+        }
+
+        // 根目录没有目录项指向它，链接数固定为 1
+        let mut links: BTreeMap<u32, u16> = BTreeMap::from([(0, 1)]);
+        let mut used_blocks: BTreeSet<u32> = BTreeSet::new();
+        let mut queue = VecDeque::from([0u32]);
+        while let Some(inode_id) = queue.pop_front() {
+            let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+            let inode_block = get_block_cache(block_id as usize, Arc::clone(&block_device));
+            let mut seen = BTreeSet::new();
+            let mut bad = None;
+            let (blocks, mapped, data_blocks, dir_data) =
+                inode_block
+                    .lock()
+                    .read(block_offset, |disk_inode: &DiskInode| {
+                        let (blocks, mapped) =
+                            disk_inode.mapped_blocks(&block_device, |block_id| {
+                                bad = if !(data_start..data_end).contains(&block_id) {
+                                    Some(FsckProblem::BadBlock { inode_id, block_id })
+                                } else if used_blocks.contains(&block_id) || !seen.insert(block_id)
+                                {
+                                    Some(FsckProblem::DuplicateBlock { inode_id, block_id })
+                                } else {
+                                    return true;
+                                };
+                                false
+                            });
+                        let dir_data = disk_inode.is_dir().then(|| {
+                            let len = (disk_inode.size as usize).min(mapped as usize * BLOCK_SZ);
+                            let mut data = vec![0u8; len];
+                            disk_inode.read_at(0, &mut data, &block_device);
+                            data
+                        });
+                        (blocks, mapped, disk_inode.data_blocks(), dir_data)
+                    });
+            let mut blocks = blocks;
+            if bad.is_some() || mapped < data_blocks {
+                let size = inode_block
+                    .lock()
+                    .read(block_offset, |disk_inode: &DiskInode| disk_inode.size);
+                problems.push(bad.unwrap_or(FsckProblem::BadSize { inode_id, size }));
+                if repair {
+                    blocks =
+                        inode_block
+                            .lock()
+                            .modify(block_offset, |disk_inode: &mut DiskInode| {
+                                // 丢弃的区段树节点块没有被引用，在最后作为未引用的块回收
+                                disk_inode.forget_blocks_from(mapped, &block_device);
+                                disk_inode.size = disk_inode.size.min(mapped * BLOCK_SZ as u32);
+                                disk_inode.mapped_blocks(&block_device, |_| true).0
+                            });
+                }
+            }
+            used_blocks.extend(blocks);
+
+            // 属性块不属于内容，无效时清除属性块的引用
+            let xattr_block = inode_block
+                .lock()
+                .read(block_offset, |disk_inode: &DiskInode| {
+                    disk_inode.xattr_block
+                });
+            if fs.supports_xattrs() && xattr_block != 0 {
+                let bad = if !(data_start..data_end).contains(&xattr_block) {
+                    Some(FsckProblem::BadBlock {
+                        inode_id,
+                        block_id: xattr_block,
+                    })
+                } else if used_blocks.contains(&xattr_block) {
+                    Some(FsckProblem::DuplicateBlock {
+                        inode_id,
+                        block_id: xattr_block,
+                    })
+                } else {
+                    used_blocks.insert(xattr_block);
+                    None
+                };
+                if let Some(problem) = bad {
+                    problems.push(problem);
+                    if repair {
+                        inode_block
+                            .lock()
+                            .modify(block_offset, |disk_inode: &mut DiskInode| {
+                                disk_inode.xattr_block = 0;
+                            });
+                    }
+                }
+            }
+
+            let Some(data) = dir_data else {
+                continue;
+            };
+            let dirents = if fs.has_legacy_dirents() {
+                parse_legacy_dir(&data)
+            } else {
+                parse_dir(&data)
+            };
+            let mut dangling = Vec::new();
+            for (offset, dirent) in dirents {
+                let child = dirent.inode_number();
+                if !inode_allocated(child) {
+                    problems.push(FsckProblem::DanglingEntry {
+                        dir: inode_id,
+                        name: String::from(dirent.name()),
+                        inode_id: child,
+                    });
+                    dangling.push(offset);
+                    continue;
+                }
+                let count = links.entry(child).or_insert(0);
+                if *count == 0 {
+                    queue.push_back(child);
+                }
+                *count = count.saturating_add(1);
+            }
+            if repair && !fs.has_legacy_dirents() {
+                let dir = fs.get_inode(inode_id);
+                // 从后往前删除，前面的目录项偏移保持不变
+                for offset in dangling.into_iter().rev() {
+                    dir.remove_dirent_at(offset, fs);
+                }
+            }
+            if repair {
+                fs.checkpoint_quiesced();
+            }
+        }
+
+        for (&inode_id, &expected) in links.iter() {
+            let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+            let inode_block = get_block_cache(block_id as usize, Arc::clone(&block_device));
+            let found = inode_block
+                .lock()
+                .read(block_offset, |disk_inode: &DiskInode| disk_inode.nlink);
+            if found != expected {
+                problems.push(FsckProblem::WrongLinkCount {
+                    inode_id,
+                    found,
+                    expected,
+                });
+                if repair {
+                    inode_block
+                        .lock()
+                        .modify(block_offset, |disk_inode: &mut DiskInode| {
+                            disk_inode.nlink = expected;
+                        });
+                    fs.checkpoint_quiesced();
+                }
+            }
+        }
+
+        for inode_id in 0..inode_count {
+            if inode_allocated(inode_id) && !links.contains_key(&inode_id) {
+                problems.push(FsckProblem::OrphanInode { inode_id });
+                if repair {
+                    fs.inode_bitmap
+                        .lock()
+                        .dealloc(&block_device, inode_id as usize);
+                    fs.checkpoint_quiesced();
+                }
+            }
+        }
+
+        // 不可达 inode 的块在这里作为未引用的块回收
+        for block_id in data_start..data_end {
+            let bit = (block_id - data_start) as usize;
+            let allocated = fs.data_bitmap.lock().is_allocated(&block_device, bit);
+            match (allocated, used_blocks.contains(&block_id)) {
+                (true, false) => {
+                    problems.push(FsckProblem::LeakedBlock { block_id });
+                    if repair {
+                        fs.dealloc_data(block_id);
+                        fs.checkpoint_quiesced();
+                    }
+                }
+                (false, true) => {
+                    problems.push(FsckProblem::UnmarkedBlock { block_id });
+                    if repair {
+                        fs.data_bitmap.lock().mark_allocated(&block_device, bit);
+                        fs.checkpoint_quiesced();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if repair && !problems.is_empty() {
+            // 上面直接修改了位图，空闲计数在这里统一重新统计
+            fs.recount_free();
+            drop(quiesced);
+            fs.commit();
+        }
+        problems
+    }
+}
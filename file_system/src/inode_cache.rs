@@ -0,0 +1,58 @@
+use super::Inode;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use spin::Mutex;
+
+/// 缓存中的表项数不少于这个值时才清理失效的表项
+const MIN_PRUNE_LEN: usize = 64;
+
+/// 以 inode 编号为键的 inode 缓存，同一个 inode 在内存中只有一个 `Inode` 对象
+///
+/// 缓存只持有弱引用，不影响 `Inode` 的释放。没有被引用的表项在表项数翻倍时统一清理
+pub struct InodeCache {
+    inner: Mutex<InodeCacheInner>,
+}
+
+struct InodeCacheInner {
+    map: BTreeMap<u32, Weak<Inode>>,
+    /// 表项数达到这个值时清理失效的表项
+    prune_len: usize,
+}
+
+impl InodeCache {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(InodeCacheInner {
+                map: BTreeMap::new(),
+                prune_len: MIN_PRUNE_LEN,
+            }),
+        }
+    }
+
+    /// 编号为 `inode_id` 的 inode，不在缓存中或者已经没有引用时用 `create` 创建并加入缓存
+    pub fn get(&self, inode_id: u32, create: impl FnOnce() -> Inode) -> Arc<Inode> {
+        let mut inner = self.inner.lock();
+        if let Some(inode) = inner.map.get(&inode_id).and_then(Weak::upgrade) {
+            return inode;
+        }
+        if inner.map.len() >= inner.prune_len {
+            inner.map.retain(|_, inode| inode.strong_count() > 0);
+            inner.prune_len = (inner.map.len() * 2).max(MIN_PRUNE_LEN);
+        }
+        let inode = Arc::new(create());
+        inner.map.insert(inode_id, Arc::downgrade(&inode));
+        inode
+    }
+
+    /// inode 被释放后移除它的表项，编号被重新分配时不会得到已经释放的对象
+    pub fn remove(&self, inode_id: u32, inode: *const Inode) {
+        let mut inner = self.inner.lock();
+        if inner
+            .map
+            .get(&inode_id)
+            .is_some_and(|cached| cached.as_ptr() == inode)
+        {
+            inner.map.remove(&inode_id);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use super::{
+    BLOCK_SZ, BlockDevice, block_cache_sync_all, get_block_cache, journaled_block_count,
+    metadata_errors, record_checksums, restore_journaled_blocks, take_journaled_blocks,
+};
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// 描述块的 magic，描述块有效说明对应的事务已经提交
+const JOURNAL_MAGIC: u32 = 0x4a524e4c;
+/// 一次提交最多记录的块数，使描述块恰好占满一个块
+const JOURNAL_MAX_BLOCKS: usize = BLOCK_SZ / 4 - 2;
+/// 日志区的大小：一个描述块加上被记录的块的副本
+pub const JOURNAL_BLOCKS: u32 = 1 + JOURNAL_MAX_BLOCKS as u32;
+
+type DataBlock = [u8; BLOCK_SZ];
+
+/// 日志区的描述块
+///
+/// 依次为 magic、记录的块数和每个副本对应的原位置块号，
+/// 副本按顺序存放在描述块之后
+struct JournalHeader {
+    magic: u32,
+    count: u32,
+    home: [u32; JOURNAL_MAX_BLOCKS],
+}
+
+impl JournalHeader {
+    fn empty() -> Self {
+        Self {
+            magic: 0,
+            count: 0,
+            home: [0; JOURNAL_MAX_BLOCKS],
+        }
+    }
+
+    fn from_bytes(bytes: &DataBlock) -> Self {
+        let word = |i: usize| u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+        let mut header = Self::empty();
+        header.magic = word(0);
+        header.count = word(1);
+        for (i, home) in header.home.iter_mut().enumerate() {
+            *home = word(i + 2);
+        }
+        header
+    }
+
+    fn to_bytes(&self) -> DataBlock {
+        let mut bytes = [0u8; BLOCK_SZ];
+        let words = [self.magic, self.count].into_iter().chain(self.home);
+        for (i, word) in words.enumerate() {
+            bytes[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn is_committed(&self) -> bool {
+        self.magic == JOURNAL_MAGIC && self.count as usize <= JOURNAL_MAX_BLOCKS
+    }
+}
+
+/// 元数据的预写日志
+///
+/// 提交时先把普通数据写回，再把所有被修改的元数据块写入日志区，
+/// 最后写描述块完成提交；之后再把元数据写回原位置并清除描述块。
+/// 一次提交必须整体放进日志区，较长的操作在每一步之后用 `nearly_full` 检查，需要时先提交已完成的部分。
+/// 旧镜像没有日志区（`blocks` 为 0），此时直接写回原位置
+pub struct Journal {
+    start_block: u32,
+    blocks: u32,
+    /// 出现过放不进日志区的提交，此后不再提交
+    overflowed: AtomicBool,
+}
+
+impl Journal {
+    pub fn new(start_block: u32, blocks: u32) -> Self {
+        Self {
+            start_block,
+            blocks,
+            overflowed: AtomicBool::new(false),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        (self.blocks as usize)
+            .saturating_sub(1)
+            .min(JOURNAL_MAX_BLOCKS)
+    }
+
+    /// 未提交的块已经占用日志容量的一半，应该在下一个一致的状态提交
+    ///
+    /// 提交时每个块还可能修改一个校验和表块，因此按未提交块数的两倍计算
+    pub fn nearly_full(&self) -> bool {
+        self.capacity() > 0 && journaled_block_count() * 2 >= self.capacity() / 2
+    }
+
+    /// 是否因为一次提交放不进日志区而停止了提交
+    pub fn overflowed(&self) -> bool {
+        self.overflowed.load(Ordering::Relaxed)
+    }
+
+    fn write_header(&self, header: &JournalHeader, block_device: &Arc<dyn BlockDevice>) {
+        block_device.write_block(self.start_block as usize, &header.to_bytes());
+    }
+
+    /// 提交当前事务
+    ///
+    /// 一次提交总是整体写入日志区，不会拆开。修改的块数超过日志容量时不提交，此后也不再提交，
+    /// 这些修改留在内存中；发现过损坏的元数据后同样不再提交，磁盘上保持最后一次提交后的状态
+    pub fn commit(&self, block_device: &Arc<dyn BlockDevice>) {
+        block_cache_sync_all();
+        if metadata_errors() > 0 || self.overflowed() {
+            return;
+        }
+        let mut blocks = take_journaled_blocks();
+        record_checksums(&blocks, block_device);
+        // 记录校验和时修改的表块
+        blocks.extend(take_journaled_blocks());
+        blocks.sort_unstable_by_key(|(block_id, _)| *block_id);
+        if self.capacity() == 0 {
+            for (block_id, data) in blocks.iter() {
+                block_device.write_block(*block_id, data);
+            }
+            return;
+        }
+        if blocks.len() > self.capacity() {
+            self.overflowed.store(true, Ordering::Relaxed);
+            restore_journaled_blocks(blocks);
+            return;
+        }
+        let mut header = JournalHeader::empty();
+        for (i, (block_id, data)) in blocks.iter().enumerate() {
+            block_device.write_block(self.start_block as usize + 1 + i, data);
+            header.home[i] = *block_id as u32;
+        }
+        header.magic = JOURNAL_MAGIC;
+        header.count = blocks.len() as u32;
+        self.write_header(&header, block_device);
+        // checkpoint
+        for (block_id, data) in blocks.iter() {
+            block_device.write_block(*block_id, data);
+        }
+        self.write_header(&JournalHeader::empty(), block_device);
+    }
+
+    /// 重放已提交但没有写回原位置的事务，返回重放的块数
+    pub fn replay(&self, block_device: &Arc<dyn BlockDevice>) -> usize {
+        if self.capacity() == 0 {
+            return 0;
+        }
+        let mut buf = [0u8; BLOCK_SZ];
+        block_device.read_block(self.start_block as usize, &mut buf);
+        let header = JournalHeader::from_bytes(&buf);
+        if !header.is_committed() {
+            return 0;
+        }
+        let count = header.count as usize;
+        for (i, home) in header.home.iter().take(count).enumerate() {
+            block_device.read_block(self.start_block as usize + 1 + i, &mut buf);
+            // 经过缓存写回，使缓存中已有的旧内容同时被更新
+            get_block_cache(*home as usize, Arc::clone(block_device))
+                .lock()
+                .modify_data(0, |data_block: &mut DataBlock| {
+                    data_block.copy_from_slice(&buf)
+                });
+        }
+        block_cache_sync_all();
+        self.write_header(&JournalHeader::empty(), block_device);
+        count
+    }
+}
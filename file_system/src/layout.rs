@@ -1,14 +1,38 @@
-use super::{BLOCK_SZ, BlockDevice, get_block_cache};
+use super::extent::Extent;
+use super::{
+    BLOCK_SZ, BlockDevice, ChecksumTable, ROOT_WORDS, crc32c, extent, get_block_cache, lz4,
+};
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt::{Debug, Formatter, Result};
+use core::ops::Range;
 
-/// Magic number
-const EFS_MAGIC: u32 = 0x3b800001;
+/// Magic number，磁盘格式变化时递增
+const EFS_MAGIC: u32 = 0x3b800009;
+/// 不支持压缩文件的上一个版本，其余格式相同
+const EFS_MAGIC_NO_COMPRESSION: u32 = 0x3b800008;
+/// 不支持扩展属性的上一个版本，其余格式相同
+const EFS_MAGIC_NO_XATTR: u32 = 0x3b800007;
+/// 没有元数据校验和的版本
+const EFS_MAGIC_NO_CSUM: u32 = 0x3b800006;
+/// 不在超级块中维护空闲计数的上一个版本，其余格式相同
+const EFS_MAGIC_NO_FREE_COUNTS: u32 = 0x3b800005;
+/// 不支持目录哈希索引的版本
+const EFS_MAGIC_NO_DIR_INDEX: u32 = 0x3b800004;
+/// 不支持区段树和目录哈希索引的版本
+const EFS_MAGIC_NO_EXTENTS: u32 = 0x3b800003;
+/// 使用定长目录项的上一个版本，只能以只读方式访问目录
+const EFS_MAGIC_FIXED_DIRENT: u32 = 0x3b800002;
+/// 超级块中的空闲计数有效时 `free_counts_valid` 的值，没有统计过的旧镜像中这个字段为 0
+const FREE_COUNTS_VALID: u32 = 0x46524545;
 /// 直接索引节点的最大数量
-const INODE_DIRECT_COUNT: usize = 28;
-/// 索引节点名称的最大长度
-const NAME_LENGTH_LIMIT: usize = 27;
+const INODE_DIRECT_COUNT: usize = 20;
+/// 目录项名称的最大长度
+pub const NAME_LENGTH_LIMIT: usize = 255;
+/// 旧格式定长目录项中名称的最大长度
+pub const LEGACY_NAME_LENGTH_LIMIT: usize = 27;
 /// indirect1索引节点的最大数量
 const INODE_INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
 /// indirect2索引节点的最大数量
@@ -18,7 +42,6 @@ const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
 /// 间接索引节点1索引的上限
 const INDIRECT1_BOUND: usize = DIRECT_BOUND + INODE_INDIRECT1_COUNT;
 /// 间接索引节点2索引的上限
-#[allow(unused)]
 const INDIRECT2_BOUND: usize = INDIRECT1_BOUND + INODE_INDIRECT2_COUNT;
 
 #[repr(C)]
@@ -29,6 +52,16 @@ pub struct SuperBlock {
     pub inode_area_blocks: u32,
     pub data_bitmap_blocks: u32,
     pub data_area_blocks: u32,
+    /// 位于磁盘末尾的日志区大小，旧镜像中为 0
+    pub journal_blocks: u32,
+    /// 空闲的 inode 数和数据块数，与位图在同一个事务中更新
+    free_inodes: u32,
+    free_blocks: u32,
+    free_counts_valid: u32,
+    /// 位于日志区之前的元数据校验和表的大小，为 0 时不校验元数据
+    pub csum_blocks: u32,
+    /// 超级块自身的校验和，覆盖之前的所有字段，由修改超级块的方法更新
+    checksum: u32,
 }
 
 impl Debug for SuperBlock {
@@ -39,6 +72,10 @@ impl Debug for SuperBlock {
             .field("inode_area_blocks", &self.inode_area_blocks)
             .field("data_bitmap_blocks", &self.data_bitmap_blocks)
             .field("data_area_blocks", &self.data_area_blocks)
+            .field("journal_blocks", &self.journal_blocks)
+            .field("free_inodes", &self.free_inodes)
+            .field("free_blocks", &self.free_blocks)
+            .field("csum_blocks", &self.csum_blocks)
             .finish()
     }
 }
@@ -51,6 +88,7 @@ impl SuperBlock {
         inode_area_blocks: u32,
         data_bitmap_blocks: u32,
         data_area_blocks: u32,
+        journal_blocks: u32,
     ) {
         *self = Self {
             magic: EFS_MAGIC,
@@ -59,20 +97,128 @@ impl SuperBlock {
             inode_area_blocks,
             data_bitmap_blocks,
             data_area_blocks,
-        }
+            journal_blocks,
+            free_inodes: inode_bitmap_blocks * (BLOCK_SZ * 8) as u32,
+            free_blocks: data_area_blocks,
+            free_counts_valid: FREE_COUNTS_VALID,
+            csum_blocks: ChecksumTable::blocks_for(total_blocks),
+            checksum: 0,
+        };
+        self.seal();
+    }
+
+    fn compute_checksum(&self) -> u32 {
+        let len = core::mem::offset_of!(SuperBlock, checksum);
+        let bytes = unsafe { core::slice::from_raw_parts(self as *const _ as *const u8, len) };
+        crc32c(bytes)
+    }
+
+    fn seal(&mut self) {
+        self.checksum = self.compute_checksum();
+    }
+
+    /// 空闲的 inode 数和数据块数
+    ///
+    /// 旧镜像没有记录计数，计数超出镜像的容量时也认为已经过期，这两种情况返回 None
+    pub fn free_counts(&self) -> Option<(u32, u32)> {
+        let inodes = self.inode_bitmap_blocks * (BLOCK_SZ * 8) as u32;
+        (self.free_counts_valid == FREE_COUNTS_VALID
+            && self.free_inodes <= inodes
+            && self.free_blocks <= self.data_area_blocks)
+            .then_some((self.free_inodes, self.free_blocks))
     }
 
+    pub fn set_free_counts(&mut self, free_inodes: u32, free_blocks: u32) {
+        self.free_inodes = free_inodes;
+        self.free_blocks = free_blocks;
+        self.free_counts_valid = FREE_COUNTS_VALID;
+        self.seal();
+    }
+
+    /// 分配或释放 inode 和数据块后调整空闲计数
+    pub fn adjust_free_counts(&mut self, inodes: i32, blocks: i32) {
+        self.free_inodes = self.free_inodes.wrapping_add_signed(inodes);
+        self.free_blocks = self.free_blocks.wrapping_add_signed(blocks);
+        self.seal();
+    }
+
+    /// magic 属于支持的版本，带有校验和时校验和也要相符
     pub fn is_valid(&self) -> bool {
+        ((EFS_MAGIC_NO_EXTENTS..=EFS_MAGIC).contains(&self.magic) || self.has_legacy_dirents())
+            && (!self.has_metadata_checksums() || self.checksum == self.compute_checksum())
+    }
+
+    /// 镜像允许 inode 使用区段树，旧版本的内核不认识这种 inode
+    pub fn supports_extents(&self) -> bool {
+        (EFS_MAGIC_NO_DIR_INDEX..=EFS_MAGIC).contains(&self.magic)
+    }
+
+    /// 镜像允许目录建立哈希索引
+    pub fn supports_dir_index(&self) -> bool {
+        (EFS_MAGIC_NO_FREE_COUNTS..=EFS_MAGIC).contains(&self.magic)
+    }
+
+    /// 超级块中的空闲计数在每次分配和释放时都会更新
+    ///
+    /// 旧版本的内核挂载旧格式的镜像时不会更新计数，所以这种镜像每次挂载都要重新统计
+    pub fn maintains_free_counts(&self) -> bool {
+        (EFS_MAGIC_NO_CSUM..=EFS_MAGIC).contains(&self.magic)
+    }
+
+    /// 镜像为元数据块记录了校验和，旧版本的内核修改元数据时不会更新校验和
+    pub fn has_metadata_checksums(&self) -> bool {
+        (EFS_MAGIC_NO_XATTR..=EFS_MAGIC).contains(&self.magic) && self.csum_blocks != 0
+    }
+
+    /// 镜像允许 inode 带有属性块，旧版本的内核释放 inode 时不会回收属性块
+    pub fn supports_xattrs(&self) -> bool {
+        (EFS_MAGIC_NO_COMPRESSION..=EFS_MAGIC).contains(&self.magic)
+    }
+
+    /// 镜像允许文件压缩存放
+    pub fn supports_compression(&self) -> bool {
         self.magic == EFS_MAGIC
     }
+
+    /// 校验和表的位置，没有校验和时返回 None
+    pub fn checksum_table(&self) -> Option<ChecksumTable> {
+        self.has_metadata_checksums().then(|| {
+            ChecksumTable::new(
+                self.total_blocks - self.journal_blocks - self.csum_blocks,
+                self.csum_blocks,
+            )
+        })
+    }
+
+    /// 镜像使用旧的定长目录项格式
+    pub fn has_legacy_dirents(&self) -> bool {
+        self.magic == EFS_MAGIC_FIXED_DIRENT
+    }
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum DiskInodeType {
     File,
     Directory,
+    /// 符号链接，数据区存放目标路径
+    Symlink,
 }
 
+/// inode 使用区段树而不是直接/间接索引映射数据块
+const INODE_FLAG_EXTENTS: u32 = 1;
+/// 目录的第一个块是哈希索引的根节点
+const INODE_FLAG_DIR_INDEX: u32 = 2;
+/// 文件按簇压缩存放，总是和区段树一起使用
+const INODE_FLAG_COMPRESSED: u32 = 4;
+/// 目录的哈希索引曾经放不下全部目录项，不再建立索引；不认识这个标志的版本只会重新建立索引
+const INODE_FLAG_DIR_INDEX_FULL: u32 = 8;
+/// 读取时距上次更新超过这么多秒才再次更新 atime
+const ATIME_INTERVAL: u32 = 24 * 60 * 60;
+/// 压缩文件中每个簇的数据块数
+const CLUSTER_BLOCKS: u32 = 8;
+/// 压缩文件中每个簇的字节数
+const CLUSTER_SZ: usize = CLUSTER_BLOCKS as usize * BLOCK_SZ;
+
 type IndirectBlock = [u32; BLOCK_SZ / 4];
 
 type DataBlock = [u8; BLOCK_SZ];
@@ -84,15 +230,62 @@ pub struct DiskInode {
     pub indirect1: u32,
     pub indirect2: u32,
     type_: DiskInodeType,
+    /// 指向该 inode 的目录项数量
+    pub nlink: u16,
+    /// 权限位，即 `st_mode` 的低 12 位
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+    /// 最近访问、修改内容、修改元数据的时间，单位为秒
+    pub atime: u32,
+    pub mtime: u32,
+    pub ctime: u32,
+    flags: u32,
+    /// 存放扩展属性的块，为 0 时没有扩展属性
+    pub xattr_block: u32,
 }
 
+const _: () = assert!(core::mem::size_of::<DiskInode>() == 128);
+const _: () = assert!(ROOT_WORDS == INODE_DIRECT_COUNT + 2);
+
 impl DiskInode {
-    pub fn initialize(&mut self, type_: DiskInodeType) {
+    pub fn initialize(&mut self, type_: DiskInodeType, now: u32) {
         self.size = 0;
         self.direct.iter_mut().for_each(|v| *v = 0);
         self.indirect1 = 0;
         self.indirect2 = 0;
         self.type_ = type_;
+        self.nlink = 1;
+        self.mode = match type_ {
+            DiskInodeType::File => 0o644,
+            DiskInodeType::Directory => 0o755,
+            DiskInodeType::Symlink => 0o777,
+        };
+        self.uid = 0;
+        self.gid = 0;
+        self.atime = now;
+        self.mtime = now;
+        self.ctime = now;
+        self.flags = 0;
+        self.xattr_block = 0;
+    }
+
+    pub fn type_(&self) -> DiskInodeType {
+        self.type_
+    }
+
+    /// 内容被修改：同时更新 mtime 和 ctime
+    pub fn touch_modify(&mut self, now: u32) {
+        self.mtime = now;
+        self.ctime = now;
+    }
+
+    /// 读取时是否需要更新 atime：与 Linux 的 relatime 类似，只在 atime 早于 mtime 或 ctime，
+    /// 或者已经超过一天没有更新时更新，大多数读取不修改 inode
+    pub fn atime_stale(&self, now: u32) -> bool {
+        self.atime < self.mtime
+            || self.atime < self.ctime
+            || now.saturating_sub(self.atime) >= ATIME_INTERVAL
     }
 
     pub fn is_dir(&self) -> bool {
@@ -104,6 +297,64 @@ impl DiskInode {
         self.type_ == DiskInodeType::File
     }
 
+    pub fn is_symlink(&self) -> bool {
+        self.type_ == DiskInodeType::Symlink
+    }
+
+    pub fn uses_extents(&self) -> bool {
+        self.flags & INODE_FLAG_EXTENTS != 0
+    }
+
+    /// 改用区段树映射数据块，只能在还没有分配任何块时调用
+    pub fn set_extents(&mut self) {
+        self.flags |= INODE_FLAG_EXTENTS;
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.flags & INODE_FLAG_COMPRESSED != 0
+    }
+
+    /// 改为压缩存放，同时使用区段树，只能在还没有分配任何块时调用
+    pub fn set_compressed(&mut self) {
+        self.flags |= INODE_FLAG_EXTENTS | INODE_FLAG_COMPRESSED;
+    }
+
+    pub fn has_dir_index(&self) -> bool {
+        self.flags & INODE_FLAG_DIR_INDEX != 0
+    }
+
+    pub fn set_dir_index(&mut self, indexed: bool) {
+        if indexed {
+            self.flags |= INODE_FLAG_DIR_INDEX;
+        } else {
+            self.flags &= !INODE_FLAG_DIR_INDEX;
+        }
+    }
+
+    pub fn dir_index_full(&self) -> bool {
+        self.flags & INODE_FLAG_DIR_INDEX_FULL != 0
+    }
+
+    /// 记录哈希索引放不下这个目录，之后一直线性查找
+    pub fn set_dir_index_full(&mut self) {
+        self.flags |= INODE_FLAG_DIR_INDEX_FULL;
+    }
+
+    /// 块指针区域，使用区段树时是树的根节点
+    fn extent_root(&self) -> [u32; ROOT_WORDS] {
+        let mut root = [0u32; ROOT_WORDS];
+        root[..INODE_DIRECT_COUNT].copy_from_slice(&self.direct);
+        root[INODE_DIRECT_COUNT] = self.indirect1;
+        root[INODE_DIRECT_COUNT + 1] = self.indirect2;
+        root
+    }
+
+    fn set_extent_root(&mut self, root: [u32; ROOT_WORDS]) {
+        self.direct.copy_from_slice(&root[..INODE_DIRECT_COUNT]);
+        self.indirect1 = root[INODE_DIRECT_COUNT];
+        self.indirect2 = root[INODE_DIRECT_COUNT + 1];
+    }
+
     pub fn data_blocks(&self) -> u32 {
         Self::_data_blocks(self.size)
     }
@@ -111,210 +362,327 @@ impl DiskInode {
         (size + BLOCK_SZ as u32 - 1) / BLOCK_SZ as u32
     }
 
-    pub fn total_blocks(size: u32) -> u32 {
-        let data_blocks = Self::_data_blocks(size) as usize;
-        let mut total = data_blocks as usize;
-        // indirect1
-        if data_blocks > INODE_DIRECT_COUNT {
-            total += 1;
-        }
-        // indirect2
-        if data_blocks > INDIRECT1_BOUND {
-            total += 1;
-            // sub indirect1
-            total +=
-                (data_blocks - INDIRECT1_BOUND + INODE_INDIRECT1_COUNT - 1) / INODE_INDIRECT1_COUNT;
+    /// 能够映射的最大文件大小，使用直接/间接索引时受二级间接索引的容量限制
+    pub fn max_size(&self) -> u32 {
+        if self.uses_extents() {
+            u32::MAX
+        } else {
+            (INDIRECT2_BOUND * BLOCK_SZ) as u32
         }
-        total as u32
-    }
-
-    pub fn blocks_num_needed(&self, new_size: u32) -> u32 {
-        assert!(new_size >= self.size);
-        Self::total_blocks(new_size) - Self::total_blocks(self.size)
     }
 
+    /// 第 `inner_id` 个数据块的块号，属于空洞（没有分配）时返回 0
     pub fn get_block_id(&self, inner_id: u32, block_device: &Arc<dyn BlockDevice>) -> u32 {
+        if self.uses_extents() {
+            return extent::lookup(&self.extent_root(), inner_id, block_device);
+        }
         let inner_id = inner_id as usize;
         if inner_id < INODE_DIRECT_COUNT {
             self.direct[inner_id]
         } else if inner_id < INDIRECT1_BOUND {
-            get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
-                .lock()
-                .read(0, |indirect_block: &IndirectBlock| {
-                    indirect_block[inner_id - INODE_DIRECT_COUNT]
-                })
-        } else {
+            index_entry(self.indirect1, inner_id - INODE_DIRECT_COUNT, block_device)
+        } else if inner_id < INDIRECT2_BOUND {
             let last = inner_id - INDIRECT1_BOUND;
-            let indirect1 = get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
-                .lock()
-                .read(0, |indirect2: &IndirectBlock| {
-                    indirect2[last / INODE_INDIRECT1_COUNT]
-                });
-            get_block_cache(indirect1 as usize, Arc::clone(block_device))
-                .lock()
-                .read(0, |indirect1: &IndirectBlock| {
-                    indirect1[last % INODE_INDIRECT1_COUNT]
-                })
+            let indirect1 = index_entry(self.indirect2, last / INODE_INDIRECT1_COUNT, block_device);
+            index_entry(indirect1, last % INODE_INDIRECT1_COUNT, block_device)
+        } else {
+            // 超出索引能够映射的范围，只能是空洞
+            0
         }
     }
 
-    pub fn increase_size(
+    /// 为 `range` 范围内的空洞分配数据块，以及所需的索引块或区段树节点，返回不再需要的树节点块
+    ///
+    /// `alloc(goal, max_len)` 分配最多 `max_len` 个连续的块并返回第一个块和块数，`goal` 不为 0 时尽量从它开始。
+    /// 分配的块必须已经清零，新的索引块中所有表项都是空洞
+    pub fn map_blocks(
         &mut self,
-        new_size: u32,
-        new_blocks: Vec<u32>,
+        range: Range<u32>,
+        alloc: &mut impl FnMut(u32, u32) -> (u32, u32),
         block_device: &Arc<dyn BlockDevice>,
-    ) {
-        let mut current_blocks = self.data_blocks();
-        self.size = new_size;
-        let mut total_blocks = self.data_blocks();
-        let mut new_blocks = new_blocks.into_iter();
-
-        // 直接存储
-        while current_blocks < total_blocks.min(INODE_DIRECT_COUNT as u32) {
-            self.direct[current_blocks as usize] = new_blocks.next().unwrap();
-            current_blocks += 1;
+    ) -> Vec<u32> {
+        if !self.uses_extents() {
+            for inner_id in range {
+                self.map_block(inner_id, &mut || alloc(0, 1).0, block_device);
+            }
+            return Vec::new();
+        }
+        let (mut extents, blocks) = extent::load(&self.extent_root(), u32::MAX, block_device);
+        if !extent::fill_holes(&mut extents, range, alloc) {
+            return Vec::new();
         }
+        let (root, freed) = extent::store(extents, blocks, alloc, block_device);
+        self.set_extent_root(root);
+        freed
+    }
 
-        // 分配间接存储1
-        if total_blocks > INODE_DIRECT_COUNT as u32 {
-            if current_blocks == INODE_DIRECT_COUNT as u32 {
-                self.indirect1 = new_blocks.next().unwrap();
+    /// 取消区段树中 `range` 范围内的映射，返回被取消映射的数据块和不再需要的树节点块
+    ///
+    /// 拆开区段时树可能需要更多节点块，`alloc` 的含义与 `map_blocks` 相同
+    fn unmap_blocks(
+        &mut self,
+        range: Range<u32>,
+        alloc: &mut impl FnMut(u32, u32) -> (u32, u32),
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Vec<u32> {
+        let (extents, nodes) = extent::load(&self.extent_root(), u32::MAX, block_device);
+        let mut kept = Vec::with_capacity(extents.len());
+        let mut freed = Vec::new();
+        for extent in extents {
+            let first = extent.logical.max(range.start);
+            let last = extent.end().min(range.end);
+            if first >= last {
+                kept.push(extent);
+                continue;
+            }
+            freed.extend((first..last).filter_map(|logical| extent.get(logical)));
+            if extent.logical < first {
+                kept.push(Extent {
+                    len: first - extent.logical,
+                    ..extent
+                });
+            }
+            if last < extent.end() {
+                kept.push(Extent {
+                    logical: last,
+                    start: extent.get(last).unwrap(),
+                    len: extent.end() - last,
+                });
             }
-            current_blocks -= INODE_DIRECT_COUNT as u32;
-            total_blocks -= INODE_DIRECT_COUNT as u32;
-        } else {
-            return;
         }
+        if freed.is_empty() {
+            return freed;
+        }
+        let (root, unused) = extent::store(kept, nodes, alloc, block_device);
+        self.set_extent_root(root);
+        freed.extend(unused);
+        freed
+    }
 
-        // 间接存储1
-        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect1: &mut IndirectBlock| {
-                while current_blocks < total_blocks.min(INODE_INDIRECT1_COUNT as u32) {
-                    indirect1[current_blocks as usize] = new_blocks.next().unwrap();
-                    current_blocks += 1;
-                }
-            });
-
-        // 分配间接存储2
-        if total_blocks > INODE_INDIRECT1_COUNT as u32 {
-            if current_blocks == INODE_INDIRECT1_COUNT as u32 {
-                self.indirect2 = new_blocks.next().unwrap();
+    /// 返回第 `inner_id` 个数据块的块号，数据块或经过的索引块还没有分配时用 `alloc` 分配
+    fn map_block(
+        &mut self,
+        inner_id: u32,
+        alloc: &mut impl FnMut() -> u32,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> u32 {
+        let inner_id = inner_id as usize;
+        if inner_id < INODE_DIRECT_COUNT {
+            if self.direct[inner_id] == 0 {
+                self.direct[inner_id] = alloc();
             }
-            current_blocks -= INODE_INDIRECT1_COUNT as u32;
-            total_blocks -= INODE_INDIRECT1_COUNT as u32;
-        } else {
-            return;
+            return self.direct[inner_id];
         }
+        let (index_block, slot) = if inner_id < INDIRECT1_BOUND {
+            if self.indirect1 == 0 {
+                self.indirect1 = alloc();
+            }
+            (self.indirect1, inner_id - INODE_DIRECT_COUNT)
+        } else {
+            assert!(inner_id < INDIRECT2_BOUND);
+            if self.indirect2 == 0 {
+                self.indirect2 = alloc();
+            }
+            let last = inner_id - INDIRECT1_BOUND;
+            let indirect1 = map_entry(
+                self.indirect2,
+                last / INODE_INDIRECT1_COUNT,
+                alloc,
+                block_device,
+            );
+            (indirect1, last % INODE_INDIRECT1_COUNT)
+        };
+        map_entry(index_block, slot, alloc, block_device)
+    }
 
-        // 间接存储2
-
-        // 当前二级间接块中的一级块索引
-        let mut a0 = current_blocks as usize / INODE_INDIRECT1_COUNT;
-        // 当前一级块内的数据块索引
-        let mut b0 = current_blocks as usize % INODE_INDIRECT1_COUNT;
-
-        // 最终需要的一级块总数
-        let a1 = total_blocks as usize / INODE_INDIRECT1_COUNT;
-        // 最终一级块内的数据块数
-        let b1 = total_blocks as usize % INODE_INDIRECT1_COUNT;
-
-        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect2: &mut IndirectBlock| {
-                // 分配以及间接块
-                while (a0 < a1) || (a0 == a1 && b0 < b1) {
-                    if b0 == 0 {
-                        indirect2[a0] = new_blocks.next().unwrap();
-                    }
-                    // 修改一级块内的数据块
-                    get_block_cache(indirect2[a0] as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            indirect1[b0] = new_blocks.next().unwrap();
-                        });
-                    // 移动到下一个位置
-                    b0 += 1;
-                    if b0 == INODE_INDIRECT1_COUNT {
-                        b0 = 0;
-                        a0 += 1;
-                    }
+    /// 按映射顺序列出前 `data_blocks` 个数据块中已分配的块及所需的索引块，索引块排在它索引的第一个数据块之前
+    ///
+    /// 每个块号先经过 `valid` 检查，遇到无效的块时停止，不会读取无效的索引块；空洞不经过检查。
+    /// 返回可以完整访问的数据块及其索引块，以及这些数据块（包括空洞）的数量。
+    /// 使用区段树时检查树中的所有区段，包括超出大小的部分
+    pub fn mapped_blocks(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        mut valid: impl FnMut(u32) -> bool,
+    ) -> (Vec<u32>, u32) {
+        if self.uses_extents() {
+            let (blocks, stop) = extent::walk(&self.extent_root(), block_device, &mut valid);
+            return (blocks, stop.min(self.data_blocks()));
+        }
+        let read_indirect = |block_id: u32| {
+            if block_id == 0 {
+                return [0u32; INODE_INDIRECT1_COUNT];
+            }
+            get_block_cache(block_id as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect_block: &IndirectBlock| *indirect_block)
+        };
+        let mut blocks = Vec::new();
+        let mut mapped = 0;
+        let mut committed = 0;
+        let mut indirect2 = [0u32; INODE_INDIRECT1_COUNT];
+        let mut indirect1 = [0u32; INODE_INDIRECT1_COUNT];
+        let mut check = |block_id: u32, blocks: &mut Vec<u32>| {
+            if block_id == 0 {
+                return true;
+            }
+            blocks.push(block_id);
+            valid(block_id)
+        };
+        for inner_id in 0..(self.data_blocks() as usize).min(INDIRECT2_BOUND) {
+            if inner_id == DIRECT_BOUND {
+                if !check(self.indirect1, &mut blocks) {
+                    break;
+                }
+                indirect1 = read_indirect(self.indirect1);
+            }
+            if inner_id == INDIRECT1_BOUND {
+                if !check(self.indirect2, &mut blocks) {
+                    break;
+                }
+                indirect2 = read_indirect(self.indirect2);
+            }
+            if inner_id >= INDIRECT1_BOUND
+                && (inner_id - INDIRECT1_BOUND).is_multiple_of(INODE_INDIRECT1_COUNT)
+            {
+                let sub = indirect2[(inner_id - INDIRECT1_BOUND) / INODE_INDIRECT1_COUNT];
+                if !check(sub, &mut blocks) {
+                    break;
                 }
-            });
+                indirect1 = read_indirect(sub);
+            }
+            let block_id = if inner_id < DIRECT_BOUND {
+                self.direct[inner_id]
+            } else if inner_id < INDIRECT1_BOUND {
+                indirect1[inner_id - DIRECT_BOUND]
+            } else {
+                indirect1[(inner_id - INDIRECT1_BOUND) % INODE_INDIRECT1_COUNT]
+            };
+            if !check(block_id, &mut blocks) {
+                break;
+            }
+            mapped = inner_id as u32 + 1;
+            committed = blocks.len();
+        }
+        // 丢弃无效的块和只属于无效数据块的索引块
+        blocks.truncate(committed);
+        (blocks, mapped)
     }
 
-    pub fn clear_size(&mut self, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
-        let mut v: Vec<u32> = Vec::new();
-        let mut data_blocks = self.data_blocks() as usize;
-        self.size = 0;
-        let mut current_blocks = 0usize;
+    /// 已分配的块数，包括索引块，不包括空洞
+    pub fn allocated_blocks(&self, block_device: &Arc<dyn BlockDevice>) -> u32 {
+        self.mapped_blocks(block_device, |_| true).0.len() as u32
+    }
 
-        // 直接存储
-        while current_blocks < data_blocks.min(INODE_DIRECT_COUNT) {
-            v.push(self.direct[current_blocks]);
-            self.direct[current_blocks] = 0;
-            current_blocks += 1;
+    /// 把大小缩小到 `new_size`，返回不再需要的已分配数据块和索引块
+    ///
+    /// 新的末尾所在块中超出 `new_size` 的部分被清零，之后再扩展时读出为 0
+    pub fn decrease_size(
+        &mut self,
+        new_size: u32,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Vec<u32> {
+        assert!(new_size <= self.size);
+        let tail = (new_size.next_multiple_of(BLOCK_SZ as u32)).min(self.size) - new_size;
+        if tail > 0 && self.get_block_id(new_size / BLOCK_SZ as u32, block_device) != 0 {
+            self.write_at(new_size as usize, &vec![0u8; tail as usize], block_device);
         }
 
-        // 一级间接存储
-        if data_blocks > INODE_DIRECT_COUNT {
-            v.push(self.indirect1);
-            data_blocks -= INODE_DIRECT_COUNT;
-            current_blocks = 0;
+        let old_blocks = self.data_blocks() as usize;
+        let new_blocks = Self::_data_blocks(new_size) as usize;
+        let mut v: Vec<u32> = if self.uses_extents() {
+            let (extents, nodes) = extent::load(&self.extent_root(), u32::MAX, block_device);
+            self.forget_blocks_from(new_blocks as u32, block_device);
+            // 不再属于区段树的节点块
+            let (_, kept_nodes) = extent::load(&self.extent_root(), u32::MAX, block_device);
+            extents
+                .iter()
+                .flat_map(|extent| {
+                    (extent.logical.max(new_blocks as u32)..extent.end())
+                        .filter_map(|logical| extent.get(logical))
+                })
+                .chain(nodes.into_iter().filter(|node| !kept_nodes.contains(node)))
+                .collect()
         } else {
-            return v;
-        }
-
-        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect1: &mut IndirectBlock| {
-                while current_blocks < data_blocks.min(INODE_INDIRECT1_COUNT) {
-                    v.push(indirect1[current_blocks]);
-                    //indirect1[current_blocks] = 0;
-                    current_blocks += 1;
-                }
-            });
-        self.indirect1 = 0;
+            let mut v: Vec<u32> = (new_blocks..old_blocks)
+                .map(|inner_id| self.get_block_id(inner_id as u32, block_device))
+                .collect();
+            // 二级间接存储中不再使用的一级索引块
+            if old_blocks > INDIRECT1_BOUND && self.indirect2 != 0 {
+                let first = (new_blocks.max(INDIRECT1_BOUND) - INDIRECT1_BOUND)
+                    .div_ceil(INODE_INDIRECT1_COUNT);
+                let last = (old_blocks - INDIRECT1_BOUND).div_ceil(INODE_INDIRECT1_COUNT);
+                get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+                    .lock()
+                    .read(0, |indirect2: &IndirectBlock| {
+                        v.extend_from_slice(&indirect2[first..last]);
+                    });
+            }
+            if new_blocks <= INDIRECT1_BOUND && old_blocks > INDIRECT1_BOUND {
+                v.push(self.indirect2);
+            }
+            if new_blocks <= DIRECT_BOUND && old_blocks > DIRECT_BOUND {
+                v.push(self.indirect1);
+            }
+            self.forget_blocks_from(new_blocks as u32, block_device);
+            v
+        };
+        self.size = new_size;
+        v.retain(|&block_id| block_id != 0);
+        v
+    }
 
-        // 二级间接存储
-        if data_blocks > INODE_INDIRECT1_COUNT {
-            v.push(self.indirect2);
-            data_blocks -= INODE_INDIRECT1_COUNT;
+    /// 丢弃从第 `data_blocks` 个数据块开始的映射，之后再扩展时是空洞
+    ///
+    /// 被丢弃的数据块、索引块和区段树节点块既不释放也不读取，只读取仍然映射着数据块的索引块，
+    /// 因此可以用来修复引用了无效块的 inode
+    pub fn forget_blocks_from(&mut self, data_blocks: u32, block_device: &Arc<dyn BlockDevice>) {
+        if self.uses_extents() {
+            let (extents, nodes) = extent::load(&self.extent_root(), data_blocks, block_device);
+            // 区段数不会增加，原有的节点块总是够用
+            let (root, _) = extent::store(
+                extents,
+                nodes,
+                &mut |_, _| panic!("extent tree grows while shrinking"),
+                block_device,
+            );
+            self.set_extent_root(root);
+            return;
+        }
+        let data_blocks = data_blocks as usize;
+        for block_id in self.direct.iter_mut().skip(data_blocks) {
+            *block_id = 0;
+        }
+        if data_blocks <= DIRECT_BOUND {
+            self.indirect1 = 0;
         } else {
-            return v;
-        }
-
-        assert!(data_blocks <= INODE_INDIRECT2_COUNT);
-        let a1 = data_blocks / INODE_INDIRECT1_COUNT;
-        let b1 = data_blocks % INODE_INDIRECT1_COUNT;
-        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect2: &mut IndirectBlock| {
-                for entry in indirect2.iter_mut().take(a1) {
-                    v.push(*entry);
-                    get_block_cache(*entry as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            for entry in indirect1.iter() {
-                                v.push(*entry);
-                            }
-                        });
-                }
-
-                if b1 > 0 {
-                    v.push(indirect2[a1]);
-                    get_block_cache(indirect2[a1] as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            for entry in indirect1.iter().take(b1) {
-                                v.push(*entry);
-                            }
-                        });
-                    //indirect2[a1] = 0;
-                }
-            });
-        self.indirect2 = 0;
-        v
+            clear_entries(
+                self.indirect1,
+                data_blocks.min(INDIRECT1_BOUND) - DIRECT_BOUND,
+                INODE_INDIRECT1_COUNT,
+                block_device,
+            );
+        }
+        if data_blocks <= INDIRECT1_BOUND {
+            self.indirect2 = 0;
+        } else if data_blocks < INDIRECT2_BOUND {
+            let last = data_blocks - INDIRECT1_BOUND;
+            if !last.is_multiple_of(INODE_INDIRECT1_COUNT) {
+                let sub = index_entry(self.indirect2, last / INODE_INDIRECT1_COUNT, block_device);
+                clear_entries(
+                    sub,
+                    last % INODE_INDIRECT1_COUNT,
+                    INODE_INDIRECT1_COUNT,
+                    block_device,
+                );
+            }
+            clear_entries(
+                self.indirect2,
+                last.div_ceil(INODE_INDIRECT1_COUNT),
+                INODE_INDIRECT1_COUNT,
+                block_device,
+            );
+        }
     }
 
     pub fn read_at(
@@ -323,6 +691,9 @@ impl DiskInode {
         buf: &mut [u8],
         block_device: &Arc<dyn BlockDevice>,
     ) -> usize {
+        if self.is_compressed() {
+            return self.read_compressed(offset, buf, block_device);
+        }
         let mut start = offset;
         let end = (offset + buf.len()).min(self.size as usize);
         if start >= end {
@@ -337,15 +708,23 @@ impl DiskInode {
             // read and update read size
             let block_read_size = end_current_block - start;
             let dst = &mut buf[read_size..read_size + block_read_size];
-            get_block_cache(
-                self.get_block_id(start_block as u32, block_device) as usize,
-                Arc::clone(block_device),
-            )
-            .lock()
-            .read(0, |data_block: &DataBlock| {
-                let src = &data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_read_size];
-                dst.copy_from_slice(src);
-            });
+            let block_id = self.get_block_id(start_block as u32, block_device);
+            if block_id == 0 {
+                // 空洞读出为 0
+                dst.fill(0);
+            } else {
+                let block_cache = get_block_cache(block_id as usize, Arc::clone(block_device));
+                let copy = |data_block: &DataBlock| {
+                    let src = &data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_read_size];
+                    dst.copy_from_slice(src);
+                };
+                // 只有目录的内容属于元数据，需要校验
+                if self.is_dir() {
+                    block_cache.lock().read(0, copy);
+                } else {
+                    block_cache.lock().read_data(0, copy);
+                }
+            }
             read_size += block_read_size;
             // move to next block
             if end_current_block == end {
@@ -366,6 +745,9 @@ impl DiskInode {
         let mut start = offset;
         let end = (offset + buf.len()).min(self.size as usize);
         assert!(start <= end);
+        if start == end {
+            return 0;
+        }
         let mut start_block = start / BLOCK_SZ;
         let mut write_size = 0usize;
         loop {
@@ -374,16 +756,21 @@ impl DiskInode {
             end_current_block = end_current_block.min(end);
             // write and update write size
             let block_write_size = end_current_block - start;
-            get_block_cache(
-                self.get_block_id(start_block as u32, block_device) as usize,
-                Arc::clone(block_device),
-            )
-            .lock()
-            .modify(0, |data_block: &mut DataBlock| {
+            let block_id = self.get_block_id(start_block as u32, block_device);
+            // 写入的范围必须已经用 map_block 分配
+            assert_ne!(block_id, 0);
+            let block_cache = get_block_cache(block_id as usize, Arc::clone(block_device));
+            let copy = |data_block: &mut DataBlock| {
                 let src = &buf[write_size..write_size + block_write_size];
                 let dst = &mut data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_write_size];
                 dst.copy_from_slice(src);
-            });
+            };
+            // 目录的内容属于元数据，需要经过日志
+            if self.is_dir() {
+                block_cache.lock().modify(0, copy);
+            } else {
+                block_cache.lock().modify_data(0, copy);
+            }
             write_size += block_write_size;
             // move to next block
             if end_current_block == end {
@@ -394,46 +781,372 @@ impl DiskInode {
         }
         write_size
     }
+
+    /// 第 `cluster` 个簇在文件大小以内的字节数
+    fn cluster_len(&self, cluster: usize) -> usize {
+        (self.size as usize)
+            .saturating_sub(cluster * CLUSTER_SZ)
+            .min(CLUSTER_SZ)
+    }
+
+    /// 读出第 `cluster` 个簇的内容，超出文件大小的部分为 0
+    ///
+    /// 簇从第一个块开始连续映射，映射的块数足够存放簇中的数据时按原样存放，
+    /// 否则依次存放压缩后的长度和 LZ4 压缩数据；没有映射任何块的簇全部为 0。
+    /// 压缩数据损坏时整个簇读出为 0
+    fn read_cluster(&self, cluster: usize, block_device: &Arc<dyn BlockDevice>) -> Vec<u8> {
+        let first = cluster as u32 * CLUSTER_BLOCKS;
+        let block_ids: Vec<u32> = (first..first + CLUSTER_BLOCKS)
+            .map(|inner_id| self.get_block_id(inner_id, block_device))
+            .take_while(|&block_id| block_id != 0)
+            .collect();
+        let len = self.cluster_len(cluster);
+        let mut stored = vec![0u8; block_ids.len() * BLOCK_SZ];
+        for (chunk, &block_id) in stored.chunks_mut(BLOCK_SZ).zip(&block_ids) {
+            get_block_cache(block_id as usize, Arc::clone(block_device))
+                .lock()
+                .read_data(0, |data_block: &DataBlock| {
+                    chunk.copy_from_slice(data_block)
+                });
+        }
+        let mut data = vec![0u8; CLUSTER_SZ];
+        if block_ids.len() >= len.div_ceil(BLOCK_SZ) {
+            let raw = stored.len().min(len);
+            data[..raw].copy_from_slice(&stored[..raw]);
+        } else if let Some(compressed) = stored.get(4..).and_then(|rest| {
+            let compressed_len = u32::from_le_bytes(stored[..4].try_into().unwrap()) as usize;
+            rest.get(..compressed_len)
+        }) && lz4::decompress(compressed, &mut data[..len]).is_none()
+        {
+            data.fill(0);
+        }
+        data
+    }
+
+    /// 按当前的文件大小重新存放第 `cluster` 个簇，返回不再需要的块
+    ///
+    /// 压缩后占用的块更少时压缩存放，全部为 0 的簇不占用块
+    fn store_cluster(
+        &mut self,
+        cluster: usize,
+        data: &[u8],
+        alloc: &mut impl FnMut(u32, u32) -> (u32, u32),
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Vec<u32> {
+        let data = &data[..self.cluster_len(cluster)];
+        let mut stored = if data.iter().all(|&byte| byte == 0) {
+            Vec::new()
+        } else {
+            let compressed = lz4::compress(data);
+            let mut stored = Vec::with_capacity(4 + compressed.len());
+            stored.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+            stored.extend_from_slice(&compressed);
+            if stored.len().div_ceil(BLOCK_SZ) < data.len().div_ceil(BLOCK_SZ) {
+                stored
+            } else {
+                data.to_vec()
+            }
+        };
+        let blocks = stored.len().div_ceil(BLOCK_SZ) as u32;
+        stored.resize(blocks as usize * BLOCK_SZ, 0);
+        let first = cluster as u32 * CLUSTER_BLOCKS;
+        let mut freed =
+            self.unmap_blocks(first + blocks..first + CLUSTER_BLOCKS, alloc, block_device);
+        freed.extend(self.map_blocks(first..first + blocks, alloc, block_device));
+        for (inner_id, chunk) in (first..).zip(stored.chunks(BLOCK_SZ)) {
+            let block_id = self.get_block_id(inner_id, block_device);
+            get_block_cache(block_id as usize, Arc::clone(block_device))
+                .lock()
+                .modify_data(0, |data_block: &mut DataBlock| {
+                    data_block.copy_from_slice(chunk)
+                });
+        }
+        freed
+    }
+
+    fn read_compressed(
+        &self,
+        offset: usize,
+        buf: &mut [u8],
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> usize {
+        let end = (offset + buf.len()).min(self.size as usize);
+        if offset >= end {
+            return 0;
+        }
+        for cluster in offset / CLUSTER_SZ..end.div_ceil(CLUSTER_SZ) {
+            let data = self.read_cluster(cluster, block_device);
+            let first = (cluster * CLUSTER_SZ).max(offset);
+            let last = ((cluster + 1) * CLUSTER_SZ).min(end);
+            buf[first - offset..last - offset]
+                .copy_from_slice(&data[first - cluster * CLUSTER_SZ..last - cluster * CLUSTER_SZ]);
+        }
+        end - offset
+    }
+
+    /// 向压缩文件写入 `buf`，必要时扩大文件，返回不再需要的块
+    ///
+    /// 涉及的每个簇都先解压，写入后重新压缩存放，`alloc` 的含义与 `map_blocks` 相同
+    pub fn write_compressed(
+        &mut self,
+        offset: usize,
+        buf: &[u8],
+        alloc: &mut impl FnMut(u32, u32) -> (u32, u32),
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Vec<u32> {
+        let end = offset + buf.len();
+        let mut freed = Vec::new();
+        if end > self.size as usize {
+            freed = self.resize_compressed(end as u32, alloc, block_device);
+        }
+        for cluster in offset / CLUSTER_SZ..end.div_ceil(CLUSTER_SZ) {
+            let mut data = self.read_cluster(cluster, block_device);
+            let first = (cluster * CLUSTER_SZ).max(offset);
+            let last = ((cluster + 1) * CLUSTER_SZ).min(end);
+            data[first - cluster * CLUSTER_SZ..last - cluster * CLUSTER_SZ]
+                .copy_from_slice(&buf[first - offset..last - offset]);
+            freed.extend(self.store_cluster(cluster, &data, alloc, block_device));
+        }
+        freed
+    }
+
+    /// 把压缩文件截断或扩展到 `new_size`，返回不再需要的块
+    ///
+    /// 簇的存放方式取决于它在文件大小以内的长度，因此末尾不完整的簇要按新的大小重新存放，
+    /// 截断时其中超出新大小的部分被清零
+    pub fn resize_compressed(
+        &mut self,
+        new_size: u32,
+        alloc: &mut impl FnMut(u32, u32) -> (u32, u32),
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Vec<u32> {
+        let tail = self.size.min(new_size) as usize;
+        let mut freed = Vec::new();
+        if !tail.is_multiple_of(CLUSTER_SZ) {
+            let cluster = tail / CLUSTER_SZ;
+            let mut data = self.read_cluster(cluster, block_device);
+            data[tail % CLUSTER_SZ..].fill(0);
+            self.size = new_size;
+            freed = self.store_cluster(cluster, &data, alloc, block_device);
+        }
+        self.size = new_size;
+        let clusters = (new_size as usize).div_ceil(CLUSTER_SZ) as u32;
+        freed.extend(self.unmap_blocks(clusters * CLUSTER_BLOCKS..u32::MAX, alloc, block_device));
+        freed
+    }
 }
 
-pub struct DirEntry {
-    name: [u8; NAME_LENGTH_LIMIT + 1],
-    inode_number: u32,
+/// 索引块中第 `slot` 项指向的块，索引块本身是空洞时返回 0
+fn index_entry(index_block: u32, slot: usize, block_device: &Arc<dyn BlockDevice>) -> u32 {
+    if index_block == 0 {
+        return 0;
+    }
+    get_block_cache(index_block as usize, Arc::clone(block_device))
+        .lock()
+        .read(0, |indirect_block: &IndirectBlock| indirect_block[slot])
 }
 
-pub const DIRENT_SZ: usize = 32;
+/// 索引块中第 `slot` 项指向的块，还没有分配时用 `alloc` 分配
+fn map_entry(
+    index_block: u32,
+    slot: usize,
+    alloc: &mut impl FnMut() -> u32,
+    block_device: &Arc<dyn BlockDevice>,
+) -> u32 {
+    let block_id = index_entry(index_block, slot, block_device);
+    if block_id != 0 {
+        return block_id;
+    }
+    let block_id = alloc();
+    get_block_cache(index_block as usize, Arc::clone(block_device))
+        .lock()
+        .modify(0, |indirect_block: &mut IndirectBlock| {
+            indirect_block[slot] = block_id;
+        });
+    block_id
+}
 
-impl DirEntry {
-    pub fn empty() -> Self {
+/// 把索引块中 `[from, to)` 范围内的表项清零，这些表项已经都是 0 时不修改索引块
+fn clear_entries(index_block: u32, from: usize, to: usize, block_device: &Arc<dyn BlockDevice>) {
+    if index_block == 0 || from >= to {
+        return;
+    }
+    let block_cache = get_block_cache(index_block as usize, Arc::clone(block_device));
+    let mut block_cache = block_cache.lock();
+    if block_cache.read(0, |indirect_block: &IndirectBlock| {
+        indirect_block[from..to].iter().all(|&entry| entry == 0)
+    }) {
+        return;
+    }
+    block_cache.modify(0, |indirect_block: &mut IndirectBlock| {
+        indirect_block[from..to]
+            .iter_mut()
+            .for_each(|entry| *entry = 0);
+    });
+}
+
+/// 变长目录项的头部，后面紧跟 `name_len` 字节的名称，整条记录占 `rec_len` 字节
+///
+/// 与 ext2 相同，记录长度按 4 字节对齐且不跨越数据块。删除目录项时把它的空间并入
+/// 前一条记录，块中的第一条记录被删除时只把 `name_len` 置零，表示该记录未使用
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DirEntryHeader {
+    pub inode_number: u32,
+    pub rec_len: u16,
+    pub name_len: u8,
+    pub file_type: u8,
+}
+
+pub const DIRENT_HEADER_SZ: usize = core::mem::size_of::<DirEntryHeader>();
+
+/// 旧格式的定长目录项大小
+const LEGACY_DIRENT_SZ: usize = 32;
+/// 目录索引块开头记录的 `file_type`，这样的记录占满整个块，不能存放目录项
+pub const DIR_INDEX_MARK: u8 = 0xff;
+
+impl DirEntryHeader {
+    pub fn from_bytes(bytes: &[u8]) -> Self {
         Self {
-            name: [0u8; NAME_LENGTH_LIMIT + 1],
-            inode_number: 0,
+            inode_number: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            rec_len: u16::from_le_bytes(bytes[4..6].try_into().unwrap()),
+            name_len: bytes[6],
+            file_type: bytes[7],
         }
     }
 
-    pub fn new(name: &str, inode_number: u32) -> Self {
-        let mut bytes = [0u8; NAME_LENGTH_LIMIT + 1];
-        bytes[..name.len()].copy_from_slice(name.as_bytes());
+    pub fn to_bytes(self) -> [u8; DIRENT_HEADER_SZ] {
+        let mut bytes = [0u8; DIRENT_HEADER_SZ];
+        bytes[0..4].copy_from_slice(&self.inode_number.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.rec_len.to_le_bytes());
+        bytes[6] = self.name_len;
+        bytes[7] = self.file_type;
+        bytes
+    }
+
+    /// 记录中实际被目录项占用的长度，未使用的记录为 0，索引块的记录没有空闲空间
+    pub fn used_len(&self) -> usize {
+        if self.is_dir_index() {
+            self.rec_len as usize
+        } else if self.name_len == 0 {
+            0
+        } else {
+            DirEntry::rec_len_for(self.name_len as usize)
+        }
+    }
+
+    pub fn is_dir_index(&self) -> bool {
+        self.name_len == 0 && self.file_type == DIR_INDEX_MARK
+    }
+}
+
+/// 内存中的目录项
+pub struct DirEntry {
+    name: String,
+    inode_number: u32,
+    file_type: u8,
+}
+
+impl DirEntry {
+    pub fn new(name: &str, inode_number: u32, type_: DiskInodeType) -> Self {
+        // 取值与 ext2 的 file_type 相同
+        let file_type = match type_ {
+            DiskInodeType::File => 1,
+            DiskInodeType::Directory => 2,
+            DiskInodeType::Symlink => 7,
+        };
         Self {
-            name: bytes,
+            name: String::from(name),
             inode_number,
+            file_type,
         }
     }
 
-    pub fn as_bytes(&self) -> &[u8] {
-        unsafe { core::slice::from_raw_parts(self as *const _ as usize as *const u8, DIRENT_SZ) }
+    /// 名称长度为 `name_len` 的目录项所需的最小记录长度
+    pub fn rec_len_for(name_len: usize) -> usize {
+        (DIRENT_HEADER_SZ + name_len + 3) & !3
+    }
+
+    pub fn rec_len(&self) -> usize {
+        Self::rec_len_for(self.name.len())
     }
 
-    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
-        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as usize as *mut u8, DIRENT_SZ) }
+    /// 编码为记录长度为 `rec_len` 的目录项，记录中名称之后的部分不写入
+    pub fn to_bytes(&self, rec_len: usize) -> Vec<u8> {
+        let header = DirEntryHeader {
+            inode_number: self.inode_number,
+            rec_len: rec_len as u16,
+            name_len: self.name.len() as u8,
+            file_type: self.file_type,
+        };
+        let mut bytes = Vec::from(header.to_bytes());
+        bytes.extend_from_slice(self.name.as_bytes());
+        bytes
     }
 
     pub fn name(&self) -> &str {
-        let len = (0usize..).find(|i| self.name[*i] == 0).unwrap();
-        core::str::from_utf8(&self.name[..len]).unwrap()
+        &self.name
     }
 
     pub fn inode_number(&self) -> u32 {
         self.inode_number
     }
 }
+
+/// 按块遍历目录内容中的每条记录（包括未使用的记录），返回记录的偏移和头部
+///
+/// 遇到损坏的记录时跳过该块剩余的部分
+pub fn dir_records(data: &[u8]) -> Vec<(usize, DirEntryHeader)> {
+    let mut records = Vec::new();
+    for block_start in (0..data.len()).step_by(BLOCK_SZ) {
+        let block_end = (block_start + BLOCK_SZ).min(data.len());
+        let mut offset = block_start;
+        while offset + DIRENT_HEADER_SZ <= block_end {
+            let header = DirEntryHeader::from_bytes(&data[offset..]);
+            let rec_len = header.rec_len as usize;
+            if rec_len < DIRENT_HEADER_SZ
+                || offset + rec_len > block_end
+                || DIRENT_HEADER_SZ + header.name_len as usize > rec_len
+            {
+                break;
+            }
+            records.push((offset, header));
+            offset += rec_len;
+        }
+    }
+    records
+}
+
+/// 解析目录内容，返回每个有效目录项的偏移和目录项
+pub fn parse_dir(data: &[u8]) -> Vec<(usize, DirEntry)> {
+    dir_records(data)
+        .into_iter()
+        .filter(|(_, header)| header.name_len > 0)
+        .map(|(offset, header)| {
+            let name = &data[offset + DIRENT_HEADER_SZ..][..header.name_len as usize];
+            let entry = DirEntry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                inode_number: header.inode_number,
+                file_type: header.file_type,
+            };
+            (offset, entry)
+        })
+        .collect()
+}
+
+/// 解析旧格式的目录内容：每条记录 32 字节，以 `\0` 结尾的名称之后是 inode 编号
+pub fn parse_legacy_dir(data: &[u8]) -> Vec<(usize, DirEntry)> {
+    data.chunks_exact(LEGACY_DIRENT_SZ)
+        .enumerate()
+        .map(|(i, record)| {
+            let name = &record[..LEGACY_NAME_LENGTH_LIMIT + 1];
+            let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            let entry = DirEntry {
+                name: String::from_utf8_lossy(&name[..len]).into_owned(),
+                inode_number: u32::from_le_bytes(record[28..32].try_into().unwrap()),
+                file_type: 0,
+            };
+            (i * LEGACY_DIRENT_SZ, entry)
+        })
+        .collect()
+}
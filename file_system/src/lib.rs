@@ -3,15 +3,40 @@ extern crate alloc;
 mod bitmap;
 mod block_cache;
 mod block_dev;
+mod checksum;
+mod dir_index;
+mod extent;
 mod fs;
+mod fsck;
+mod inode_cache;
+mod journal;
 mod layout;
+mod lz4;
+#[cfg(test)]
+mod test_device;
 mod vfs;
+mod xattr;
 
 pub const BLOCK_SZ: usize = 512;
 use bitmap::Bitmap;
-use block_cache::{block_cache_sync_all, get_block_cache};
+use block_cache::{
+    block_cache_read_ahead, block_cache_sync_all, get_block_cache, journaled_block_count,
+    restore_journaled_blocks, take_journaled_blocks,
+};
+pub use block_cache::{BlockCacheStats, block_cache_stats, init_block_cache};
 pub use block_dev::BlockDevice;
-pub use fs::FileSystem;
+use checksum::{
+    ChecksumTable, forget_checksum, record_checksums, record_context_error, set_checksum_table,
+    verify_block,
+};
+pub use checksum::{crc32c, metadata_errors, set_error_context, take_context_errors};
+use extent::ROOT_WORDS;
+pub use fs::{FileSystem, FsStat, Transaction};
+pub use fsck::FsckProblem;
+use inode_cache::InodeCache;
+use journal::{JOURNAL_BLOCKS, Journal};
 use layout::*;
-pub use vfs::Inode;
+pub use vfs::{Inode, Metadata, RenameError, XattrError};
+use xattr::{Xattrs, decode_xattrs, encode_xattrs, valid_xattr_name};
+pub use xattr::XATTR_NAME_MAX;
 pub use layout::DiskInodeType;
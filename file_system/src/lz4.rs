@@ -0,0 +1,204 @@
+//! LZ4 块格式的压缩和解压
+//!
+//! 每个序列依次为 token、字面量长度的扩展字节、字面量、匹配偏移和匹配长度的扩展字节。
+//! token 的高 4 位是字面量长度，低 4 位是匹配长度减 4，取 15 时后面跟着扩展字节。
+//! 最后一个序列只有字面量
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// 最短的匹配长度
+const MIN_MATCH: usize = 4;
+/// 最后这么多字节总是作为字面量
+const LAST_LITERALS: usize = 5;
+/// 距离末尾不足这么多字节时不再开始新的匹配
+const MF_LIMIT: usize = 12;
+/// 匹配偏移用 16 位存放
+const MAX_OFFSET: usize = u16::MAX as usize;
+const HASH_BITS: u32 = 12;
+
+fn hash(seq: u32) -> usize {
+    (seq.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+fn read_u32(src: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(src[pos..pos + 4].try_into().unwrap())
+}
+
+fn write_len(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        out.push(255);
+        len -= 255;
+    }
+    out.push(len as u8);
+}
+
+/// 写出一个序列，`matched` 为匹配的偏移和长度，最后一个序列没有匹配
+fn write_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push(((literals.len().min(15) as u8) << 4) | match_len.min(15) as u8);
+    if literals.len() >= 15 {
+        write_len(out, literals.len() - 15);
+    }
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_len >= 15 {
+            write_len(out, match_len - 15);
+        }
+    }
+}
+
+/// 用贪心的哈希匹配压缩 `src`
+pub fn compress(src: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    // 每个哈希值对应最近一次出现的位置加 1，0 表示没有出现过
+    let mut table = vec![0usize; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut pos = 0;
+    while pos + MF_LIMIT < src.len() {
+        let seq = read_u32(src, pos);
+        let slot = &mut table[hash(seq)];
+        let candidate = slot.checked_sub(1);
+        *slot = pos + 1;
+        let Some(candidate) = candidate
+            .filter(|&candidate| pos - candidate <= MAX_OFFSET && read_u32(src, candidate) == seq)
+        else {
+            pos += 1;
+            continue;
+        };
+        let max_len = src.len() - LAST_LITERALS - pos;
+        let mut len = MIN_MATCH;
+        while len < max_len && src[candidate + len] == src[pos + len] {
+            len += 1;
+        }
+        write_sequence(&mut out, &src[anchor..pos], Some((pos - candidate, len)));
+        pos += len;
+        anchor = pos;
+    }
+    write_sequence(&mut out, &src[anchor..], None);
+    out
+}
+
+fn read_len(src: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut len = 0;
+    loop {
+        let byte = *src.get(*pos)?;
+        *pos += 1;
+        len += byte as usize;
+        if byte != 255 {
+            return Some(len);
+        }
+    }
+}
+
+/// 把 `src` 解压到 `dst` 中，返回解压出的字节数，数据损坏或者 `dst` 放不下时返回 None
+pub fn decompress(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+    let mut pos = 0;
+    let mut out = 0;
+    loop {
+        let token = *src.get(pos)?;
+        pos += 1;
+        let mut literal_len = (token >> 4) as usize;
+        if literal_len == 15 {
+            literal_len += read_len(src, &mut pos)?;
+        }
+        let literals = src.get(pos..pos + literal_len)?;
+        dst.get_mut(out..out + literal_len)?
+            .copy_from_slice(literals);
+        pos += literal_len;
+        out += literal_len;
+        if pos == src.len() {
+            return Some(out);
+        }
+        let offset = u16::from_le_bytes(src.get(pos..pos + 2)?.try_into().unwrap()) as usize;
+        pos += 2;
+        if offset == 0 || offset > out {
+            return None;
+        }
+        let mut match_len = (token & 0xf) as usize + MIN_MATCH;
+        if token & 0xf == 0xf {
+            match_len += read_len(src, &mut pos)?;
+        }
+        if out + match_len > dst.len() {
+            return None;
+        }
+        // 匹配可能与正在写出的部分重叠，逐字节复制
+        for i in out..out + match_len {
+            dst[i] = dst[i - offset];
+        }
+        out += match_len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(src: &[u8]) -> Vec<u8> {
+        let compressed = compress(src);
+        let mut dst = vec![0u8; src.len()];
+        assert_eq!(decompress(&compressed, &mut dst), Some(src.len()));
+        assert_eq!(dst, src);
+        compressed
+    }
+
+    /// 不可压缩的伪随机数据（xorshift32）
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545f491u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn short_inputs_are_literals() {
+        for len in [0, 1, MF_LIMIT, MF_LIMIT + 1] {
+            round_trip(&vec![7u8; len]);
+        }
+    }
+
+    #[test]
+    fn repeated_data_shrinks() {
+        // 长匹配和长字面量都需要扩展字节，匹配与正在写出的部分重叠
+        let mut src = noise(300);
+        src.extend_from_slice(&[0x5a; 4000]);
+        src.extend_from_slice(&noise(20));
+        let compressed = round_trip(&src);
+        assert!(compressed.len() < 400);
+        let text: Vec<u8> = (0..2000)
+            .flat_map(|i| alloc::format!("line {} of some text\n", i % 97).into_bytes())
+            .collect();
+        assert!(round_trip(&text).len() < text.len() / 4);
+    }
+
+    #[test]
+    fn incompressible_data_round_trips() {
+        let src = noise(4096);
+        assert!(round_trip(&src).len() >= src.len());
+        // 超过 16 位偏移的重复内容不能作为匹配
+        let mut far = noise(MAX_OFFSET + 100);
+        far.extend_from_within(..1000);
+        round_trip(&far);
+    }
+
+    #[test]
+    fn corrupt_input_is_rejected() {
+        let src = [1u8, 2, 3, 4].repeat(100);
+        let compressed = compress(&src);
+        let mut dst = vec![0u8; src.len() - 1];
+        assert_eq!(decompress(&compressed, &mut dst), None);
+        let mut dst = vec![0u8; src.len()];
+        assert_eq!(
+            decompress(&compressed[..compressed.len() - 1], &mut dst),
+            None
+        );
+        // 偏移指向输出开始之前
+        assert_eq!(decompress(&[0x10, b'a', 2, 0], &mut dst), None);
+    }
+}
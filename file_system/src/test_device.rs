@@ -0,0 +1,46 @@
+//! 单元测试使用的内存块设备
+//!
+//! 块缓存、日志中未提交的块和校验和表全局只有一份，使用块缓存的测试通过 `setup` 串行执行
+
+use super::{BLOCK_SZ, BlockDevice, init_block_cache, set_checksum_table, take_journaled_blocks};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::{Mutex, MutexGuard};
+
+pub struct MemDevice(Mutex<Vec<[u8; BLOCK_SZ]>>);
+
+impl BlockDevice for MemDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.0.lock()[block_id]);
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.0.lock()[block_id].copy_from_slice(buf);
+    }
+
+    fn handle_irq(&self) {
+        unimplemented!();
+    }
+}
+
+static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+/// 取得测试锁，清空上一个测试留下的块缓存，返回一个有 `blocks` 个全零块的设备
+pub fn setup(blocks: usize) -> (MutexGuard<'static, ()>, Arc<dyn BlockDevice>) {
+    let guard = TEST_LOCK.lock();
+    init_block_cache(256);
+    take_journaled_blocks();
+    set_checksum_table(None);
+    let device = MemDevice(Mutex::new(vec![[0u8; BLOCK_SZ]; blocks]));
+    (guard, Arc::new(device))
+}
+
+/// 从 `next` 开始依次分配块，总是分配请求的全部块数，`goal` 还没有分配出去时从它开始
+pub fn bump_alloc(next: &mut u32) -> impl FnMut(u32, u32) -> (u32, u32) + '_ {
+    move |goal, len| {
+        let start = goal.max(*next);
+        *next = start + len;
+        (start, len)
+    }
+}
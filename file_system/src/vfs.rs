@@ -1,24 +1,90 @@
 use super::{
-    BlockDevice, DIRENT_SZ, DirEntry, DiskInode, DiskInodeType, FileSystem, block_cache_sync_all,
-    get_block_cache,
+    BLOCK_SZ, BlockDevice, DIRENT_HEADER_SZ, DirEntry, DirEntryHeader, DiskInode, DiskInodeType,
+    FileSystem, FsStat, NAME_LENGTH_LIMIT, Transaction, Xattrs, block_cache_read_ahead,
+    decode_xattrs, dir_index, dir_records, encode_xattrs, get_block_cache, parse_dir,
+    parse_legacy_dir, valid_xattr_name,
 };
 use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
-use spin::{Mutex, MutexGuard};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::{RwLock, RwLockWriteGuard};
 
+/// `Inode::rename` 失败的原因
+#[derive(Debug, PartialEq)]
+pub enum RenameError {
+    /// 源目录项不存在，或者源/目标父节点不是目录
+    NotFound,
+    /// 试图把目录移动到它自己的子树中
+    IntoSubtree,
+    /// 目标已存在且不能被替换
+    TargetExists,
+    /// 镜像使用旧的目录项格式，目录不能修改
+    ReadOnly,
+}
+
+/// 扩展属性操作失败的原因
+#[derive(Debug, PartialEq)]
+pub enum XattrError {
+    /// 镜像不支持扩展属性
+    Unsupported,
+    /// 名称为空、过长或者包含 NUL
+    InvalidName,
+    /// 属性不存在
+    NotFound,
+    /// 所有属性加起来放不进一个属性块
+    NoSpace,
+}
+
+/// inode 的元数据，对应 `stat` 中与文件系统相关的字段
+#[derive(Clone, Copy, Debug)]
+pub struct Metadata {
+    pub inode_id: u32,
+    pub type_: DiskInodeType,
+    /// 权限位
+    pub mode: u16,
+    pub nlink: u16,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+    /// 实际分配的磁盘块数，包括索引块和属性块，空洞不占用磁盘块
+    pub blocks: u32,
+    pub atime: u32,
+    pub mtime: u32,
+    pub ctime: u32,
+}
+
+/// 顺序读时预读的块数
+const READ_AHEAD_BLOCKS: usize = 8;
+/// 一次写入中每提交一次最多写入的字节数，使每次提交的元数据块数不超过日志容量
+const WRITE_CHUNK: usize = 64 * BLOCK_SZ;
+
+/// 内存中的 inode，同一个 inode 只有一个对象
+///
+/// 读操作持有 `lock` 的读锁，修改操作在 `FileSystem::begin` 之后持有写锁。
+/// 同时锁住目录和其中的目录项指向的 inode 时先锁目录；
+/// 重命名要锁住两个目录，由 `lock_all` 避免与按这个顺序加锁的操作互相等待
 pub struct Inode {
     block_id: usize,
     block_offset: usize,
-    fs: Arc<Mutex<FileSystem>>,
+    fs: Arc<FileSystem>,
     block_device: Arc<dyn BlockDevice>,
+    lock: RwLock<()>,
+    /// 上一次读结束的位置，下一次读从这里开始时认为是顺序读
+    next_read: AtomicUsize,
+    /// 链接数已经减为零，最后一个引用释放时回收 inode
+    orphan: AtomicBool,
+    /// 已经调用过 `release`
+    released: AtomicBool,
 }
 
 impl Inode {
-    pub fn new(
+    /// 只由 inode 缓存调用，其他地方通过 `FileSystem::get_inode` 得到共享的对象
+    pub(crate) fn new(
         block_id: u32,
         block_offset: usize,
-        fs: Arc<Mutex<FileSystem>>,
+        fs: Arc<FileSystem>,
         block_device: Arc<dyn BlockDevice>,
     ) -> Self {
         Self {
@@ -26,6 +92,10 @@ impl Inode {
             block_offset,
             fs,
             block_device,
+            lock: RwLock::new(()),
+            next_read: AtomicUsize::new(0),
+            orphan: AtomicBool::new(false),
+            released: AtomicBool::new(false),
         }
     }
 
@@ -41,53 +111,65 @@ impl Inode {
             .modify(self.block_offset, f)
     }
 
-    fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> Option<u32> {
-        // assert it is a directory
-        assert!(disk_inode.is_dir());
-        let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-        let mut dirent = DirEntry::empty();
-        for i in 0..file_count {
-            assert_eq!(
-                disk_inode.read_at(DIRENT_SZ * i, dirent.as_bytes_mut(), &self.block_device,),
-                DIRENT_SZ,
-            );
-            if dirent.name() == name {
-                return Some(dirent.inode_number() as u32);
-            }
+    /// 读出目录的全部内容
+    fn dir_data(&self, disk_inode: &DiskInode) -> Vec<u8> {
+        let mut data = vec![0u8; disk_inode.size as usize];
+        disk_inode.read_at(0, &mut data, &self.block_device);
+        data
+    }
+
+    /// 目录中的所有有效目录项及其偏移，按镜像的目录项格式解析
+    fn dirents(&self, disk_inode: &DiskInode, fs: &FileSystem) -> Vec<(usize, DirEntry)> {
+        // 损坏的 inode 被读出为 0，不再是目录
+        if !disk_inode.is_dir() {
+            return Vec::new();
+        }
+        let data = self.dir_data(disk_inode);
+        if fs.has_legacy_dirents() {
+            parse_legacy_dir(&data)
+        } else {
+            parse_dir(&data)
+        }
+    }
+
+    fn find_inode_id(&self, name: &str, disk_inode: &DiskInode, fs: &FileSystem) -> Option<u32> {
+        if disk_inode.has_dir_index() {
+            return dir_index::lookup(disk_inode, name, &self.block_device)
+                .map(|(_, inode_id)| inode_id);
         }
-        None
+        self.dirents(disk_inode, fs)
+            .into_iter()
+            .find(|(_, dirent)| dirent.name() == name)
+            .map(|(_, dirent)| dirent.inode_number())
     }
 
     pub fn find(&self, path: &str) -> Option<Arc<Inode>> {
-        let fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| {
-            self.find_inode_id(path, disk_inode).map(|inode_id| {
-                let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
-                Arc::new(Self::new(
-                    block_id,
-                    block_offset,
-                    self.fs.clone(),
-                    self.block_device.clone(),
-                ))
-            })
-        })
+        let _guard = self.lock.read();
+        let inode_id =
+            self.read_disk_inode(|disk_inode| self.find_inode_id(path, disk_inode, &self.fs))?;
+        Some(self.inode_at(inode_id))
     }
 
-    fn increase_size(
-        &self,
-        new_size: u32,
-        disk_inode: &mut DiskInode,
-        fs: &mut MutexGuard<FileSystem>,
-    ) {
+    /// 为 `[start, end)` 范围内的空洞分配数据块和所需的索引块
+    fn alloc_blocks(&self, start: usize, end: usize, disk_inode: &mut DiskInode, fs: &FileSystem) {
+        let range = (start / BLOCK_SZ) as u32..end.div_ceil(BLOCK_SZ) as u32;
+        let freed = disk_inode.map_blocks(
+            range,
+            &mut |goal, max_len| fs.alloc_data_contiguous(goal, max_len),
+            &self.block_device,
+        );
+        for block_id in freed {
+            fs.dealloc_data(block_id);
+        }
+    }
+
+    /// 扩大到 `new_size` 并分配新增范围内的所有块，用于不允许空洞的目录和符号链接
+    fn increase_size(&self, new_size: u32, disk_inode: &mut DiskInode, fs: &FileSystem) {
         if new_size < disk_inode.size {
             return;
         }
-        let blocks_needed = disk_inode.blocks_num_needed(new_size);
-        let mut v: Vec<u32> = Vec::new();
-        for _ in 0..blocks_needed {
-            v.push(fs.alloc_data());
-        }
-        disk_inode.increase_size(new_size, v, &self.block_device);
+        self.alloc_blocks(disk_inode.size as usize, new_size as usize, disk_inode, fs);
+        disk_inode.size = new_size;
     }
 
     pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
@@ -98,16 +180,56 @@ impl Inode {
         self.create_inode(name, DiskInodeType::Directory)
     }
 
+    /// 在当前目录中创建名为 `name` 的符号链接，内容为 `target`
+    pub fn symlink(&self, name: &str, target: &str) -> Option<Arc<Inode>> {
+        let fs = self.fs.begin();
+        let _guard = self.lock.write();
+        let inode = self.create_child(name, DiskInodeType::Symlink, &fs)?;
+        inode.modify_disk_inode(|disk_inode| {
+            inode.increase_size(target.len() as u32, disk_inode, &fs);
+            disk_inode.write_at(0, target.as_bytes(), &self.block_device);
+        });
+        fs.commit();
+        Some(inode)
+    }
+
+    /// 读取符号链接的目标路径，不是符号链接时返回 None
+    pub fn read_link(&self) -> Option<String> {
+        let _guard = self.lock.read();
+        self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_symlink() {
+                return None;
+            }
+            let mut buf = vec![0u8; disk_inode.size as usize];
+            disk_inode.read_at(0, &mut buf, &self.block_device);
+            String::from_utf8(buf).ok()
+        })
+    }
+
     pub fn create_inode(&self, name: &str, inode_type: DiskInodeType) -> Option<Arc<Inode>> {
-        let mut fs = self.fs.lock();
+        let fs = self.fs.begin();
+        let _guard = self.lock.write();
+        let inode = self.create_child(name, inode_type, &fs)?;
+        fs.commit();
+        Some(inode)
+    }
+
+    /// 分配并初始化一个新 inode，在当前目录中加入指向它的目录项，由调用者锁住目录并提交
+    fn create_child(
+        &self,
+        name: &str,
+        inode_type: DiskInodeType,
+        fs: &FileSystem,
+    ) -> Option<Arc<Inode>> {
+        if fs.has_legacy_dirents() || name.is_empty() || name.len() > NAME_LENGTH_LIMIT {
+            return None;
+        }
 
         // 检查文件是否存在
         let op = |root_inode: &DiskInode| {
-            assert!(root_inode.is_dir());
-
-            self.find_inode_id(name, root_inode)
+            !root_inode.is_dir() || self.find_inode_id(name, root_inode, fs).is_some()
         };
-        if self.read_disk_inode(op).is_some() {
+        if self.read_disk_inode(op) {
             return None;
         }
 
@@ -117,85 +239,205 @@ impl Inode {
         get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
             .lock()
             .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-                new_inode.initialize(inode_type);
+                new_inode.initialize(inode_type, fs.now());
             });
 
         // 将新文件的目录项插入根目录
-        self.modify_disk_inode(|root_inode| {
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-
-            let dirent = DirEntry::new(name, new_inode_id);
-            root_inode.write_at(
-                file_count * DIRENT_SZ,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
-        });
-
-        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
-        block_cache_sync_all();
+        self.add_dirent(name, new_inode_id, inode_type, fs);
 
-        Some(Arc::new(Self::new(
-            block_id,
-            block_offset,
-            self.fs.clone(),
-            self.block_device.clone(),
-        )))
+        Some(self.inode_at(new_inode_id))
     }
 
     pub fn ls(&self) -> Vec<String> {
-        let _fs = self.fs.lock();
+        let _guard = self.lock.read();
         self.read_disk_inode(|disk_inode| {
-            let mut v: Vec<String> = Vec::new();
-            if disk_inode.is_file() {
-                return v;
-            }
-
-            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-            for i in 0..file_count {
-                let mut dirent = DirEntry::empty();
-                assert_eq!(
-                    disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device,),
-                    DIRENT_SZ,
-                );
-                v.push(String::from(dirent.name()));
+            if !disk_inode.is_dir() {
+                return Vec::new();
             }
-            v
+            self.dirents(disk_inode, &self.fs)
+                .into_iter()
+                .map(|(_, dirent)| String::from(dirent.name()))
+                .collect()
         })
     }
 
+    /// 从 `offset` 开始读出数据，返回读出的字节数
+    ///
+    /// 需要更新 atime 时和其他修改操作一样在事务中进行，提交后才写回
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
-        let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device))
+        let now = self.fs.now();
+        let stale = self.read_disk_inode(|disk_inode| disk_inode.atime_stale(now));
+        let _fs = stale.then(|| self.fs.begin());
+        let _guard = self.lock.read();
+        if stale {
+            self.modify_disk_inode(|disk_inode| disk_inode.atime = now);
+        }
+        self.read_disk_inode(|disk_inode| {
+            let read = disk_inode.read_at(offset, buf, &self.block_device);
+            // 顺序读跨过块边界时预读之后的块
+            let end = offset + read;
+            let sequential = self.next_read.swap(end, Ordering::Relaxed) == offset;
+            if sequential && end / BLOCK_SZ > offset / BLOCK_SZ {
+                let first = end.div_ceil(BLOCK_SZ);
+                let last = (first + READ_AHEAD_BLOCKS).min(disk_inode.data_blocks() as usize);
+                let block_ids: Vec<usize> = (first..last)
+                    .map(|inner_id| {
+                        disk_inode.get_block_id(inner_id as u32, &self.block_device) as usize
+                    })
+                    .filter(|&block_id| block_id != 0)
+                    .collect();
+                block_cache_read_ahead(&block_ids, Arc::clone(&self.block_device));
+            }
+            read
+        })
     }
 
+    /// 从 `offset` 开始写入 `buf`，返回写入的字节数
+    ///
+    /// 超出文件最大大小的部分不写入，这时返回的字节数少于 `buf` 的长度
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
-        let mut fs = self.fs.lock();
-        let size = self.modify_disk_inode(|disk_inode| {
+        let mut fs = self.fs.begin();
+        let _guard = self.lock.write();
+        let mut written = 0;
+        // 分段写入，每段之后文件都是一致的，可以提交已经写入的部分
+        for chunk in buf.chunks(WRITE_CHUNK) {
+            let size = self.write_chunk(offset + written, chunk, &fs);
+            written += size;
+            if size < chunk.len() {
+                break;
+            }
+            fs.checkpoint();
+        }
+        fs.commit();
+        written
+    }
+
+    fn write_chunk(&self, offset: usize, buf: &[u8], fs: &FileSystem) -> usize {
+        self.modify_disk_inode(|disk_inode| {
             assert!(disk_inode.is_file());
+            let max_size = disk_inode.max_size() as usize;
+            if offset >= max_size {
+                return 0;
+            }
+            let buf = &buf[..buf.len().min(max_size - offset)];
 
-            self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
+            disk_inode.touch_modify(fs.now());
+            if disk_inode.is_compressed() {
+                let freed = disk_inode.write_compressed(
+                    offset,
+                    buf,
+                    &mut |goal, max_len| fs.alloc_data_contiguous(goal, max_len),
+                    &self.block_device,
+                );
+                for block_id in freed {
+                    fs.dealloc_data(block_id);
+                }
+                return buf.len();
+            }
+            // 只为写入的范围分配块，跳过的部分保留为空洞
+            let end = offset + buf.len();
+            self.alloc_blocks(offset, end, disk_inode, fs);
+            disk_inode.size = disk_inode.size.max(end as u32);
             disk_inode.write_at(offset, buf, &self.block_device)
-        });
-        block_cache_sync_all();
-        size
+        })
     }
 
     pub fn clear(&self) {
-        let mut fs = self.fs.lock();
-        self.modify_disk_inode(|disk_inode| {
+        self.truncate(0);
+    }
+
+    /// 把文件截断或扩展到 `new_size` 字节，扩展出的部分读出为 0
+    ///
+    /// `new_size` 超出文件的最大大小时什么也不做，返回 false
+    pub fn truncate(&self, new_size: u32) -> bool {
+        let fs = self.fs.begin();
+        let _guard = self.lock.write();
+        if new_size > self.read_disk_inode(|disk_inode| disk_inode.max_size()) {
+            return false;
+        }
+        let data_blocks_dealloc = self.modify_disk_inode(|disk_inode| {
             assert!(disk_inode.is_file());
-            let size = disk_inode.size;
-            let data_blocks_dealloc = disk_inode.clear_size(&self.block_device);
-            assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
-            for data_block in data_blocks_dealloc.into_iter() {
-                fs.dealloc_data(data_block);
+            disk_inode.touch_modify(fs.now());
+            if disk_inode.is_compressed() {
+                return disk_inode.resize_compressed(
+                    new_size,
+                    &mut |goal, max_len| fs.alloc_data_contiguous(goal, max_len),
+                    &self.block_device,
+                );
             }
+            if new_size >= disk_inode.size {
+                // 扩展出的部分是空洞，不分配块
+                disk_inode.size = new_size;
+                return Vec::new();
+            }
+            disk_inode.decrease_size(new_size, &self.block_device)
         });
-        block_cache_sync_all();
+        for data_block in data_blocks_dealloc.into_iter() {
+            fs.dealloc_data(data_block);
+        }
+        fs.commit();
+        true
+    }
+
+    /// 让还没有分配任何块的普通文件改用区段树映射数据块
+    ///
+    /// 区段树用连续的块存放顺序写入的数据，大文件需要的元数据块更少，大小也不再受间接索引的限制。
+    /// 已经分配了块、不是普通文件或者镜像不支持区段树时返回 false
+    pub fn set_extents(&self) -> bool {
+        let fs = self.fs.begin();
+        let _guard = self.lock.write();
+        let empty = self.read_disk_inode(|disk_inode| {
+            disk_inode.is_file() && disk_inode.allocated_blocks(&self.block_device) == 0
+        });
+        if !fs.supports_extents() || !empty {
+            return false;
+        }
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.set_extents();
+            disk_inode.ctime = fs.now();
+        });
+        fs.commit();
+        true
+    }
+
+    /// 让还没有分配任何块的普通文件压缩存放，之后的读写透明地解压和压缩
+    ///
+    /// 文件按 4 KiB 的簇压缩，每次写入都要重新压缩涉及的簇，适合写入后很少修改的文本和程序。
+    /// 已经分配了块、不是普通文件或者镜像不支持压缩时返回 false
+    pub fn set_compressed(&self) -> bool {
+        let fs = self.fs.begin();
+        let _guard = self.lock.write();
+        let empty = self.read_disk_inode(|disk_inode| {
+            disk_inode.is_file() && disk_inode.allocated_blocks(&self.block_device) == 0
+        });
+        if !fs.supports_compression() || !empty {
+            return false;
+        }
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.set_compressed();
+            disk_inode.ctime = fs.now();
+        });
+        fs.commit();
+        true
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.read_disk_inode(|disk_inode| disk_inode.is_compressed())
+    }
+
+    /// 提交文件系统中所有未提交的修改，并把缓存中修改过的块写回磁盘
+    pub fn sync(&self) {
+        self.fs.commit();
+    }
+
+    /// 与 `sync` 相同，但有修改操作正在进行时不等待，直接返回 false
+    pub fn try_sync(&self) -> bool {
+        self.fs.try_commit()
+    }
+
+    /// 文件系统是否已经不再提交修改
+    pub fn is_read_only(&self) -> bool {
+        self.fs.is_read_only()
     }
 
     pub fn get_file_size(&self) -> u32 {
@@ -204,10 +446,158 @@ impl Inode {
 
     pub fn get_inode_id(&self) -> u32 {
         self.fs
-            .lock()
             .get_disk_inode_id(self.block_id as u32, self.block_offset)
     }
 
+    pub fn metadata(&self) -> Metadata {
+        let inode_id = self.get_inode_id();
+        self.read_disk_inode(|disk_inode| Metadata {
+            inode_id,
+            type_: disk_inode.type_(),
+            mode: disk_inode.mode,
+            nlink: disk_inode.nlink,
+            uid: disk_inode.uid,
+            gid: disk_inode.gid,
+            size: disk_inode.size,
+            blocks: disk_inode.allocated_blocks(&self.block_device)
+                + (self.fs.supports_xattrs() && disk_inode.xattr_block != 0) as u32,
+            atime: disk_inode.atime,
+            mtime: disk_inode.mtime,
+            ctime: disk_inode.ctime,
+        })
+    }
+
+    /// inode 所在文件系统的容量和使用情况
+    pub fn fs_stat(&self) -> FsStat {
+        self.fs.stat()
+    }
+
+    /// 设置权限位，ctime 更新为当前时间
+    pub fn set_mode(&self, mode: u16) {
+        let fs = self.fs.begin();
+        let _guard = self.lock.write();
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.mode = mode & 0o7777;
+            disk_inode.ctime = fs.now();
+        });
+        fs.commit();
+    }
+
+    /// 设置所有者，ctime 更新为当前时间
+    pub fn set_owner(&self, uid: u32, gid: u32) {
+        let fs = self.fs.begin();
+        let _guard = self.lock.write();
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.uid = uid;
+            disk_inode.gid = gid;
+            disk_inode.ctime = fs.now();
+        });
+        fs.commit();
+    }
+
+    /// 设置访问和修改时间，ctime 更新为当前时间
+    pub fn set_times(&self, atime: u32, mtime: u32) {
+        let fs = self.fs.begin();
+        let _guard = self.lock.write();
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.atime = atime;
+            disk_inode.mtime = mtime;
+            disk_inode.ctime = fs.now();
+        });
+        fs.commit();
+    }
+
+    /// 读出属性块中的所有扩展属性，没有属性块时为空
+    fn xattrs(&self, disk_inode: &DiskInode) -> Xattrs {
+        if !self.fs.supports_xattrs() || disk_inode.xattr_block == 0 {
+            return Xattrs::new();
+        }
+        get_block_cache(
+            disk_inode.xattr_block as usize,
+            Arc::clone(&self.block_device),
+        )
+        .lock()
+        .read(0, decode_xattrs)
+    }
+
+    /// 写回所有扩展属性，属性为空时回收属性块，由调用者锁住 inode 并提交
+    fn write_xattrs(&self, xattrs: &Xattrs, fs: &FileSystem) -> Result<(), XattrError> {
+        let block = encode_xattrs(xattrs).ok_or(XattrError::NoSpace)?;
+        self.modify_disk_inode(|disk_inode| {
+            if xattrs.is_empty() {
+                fs.dealloc_data(disk_inode.xattr_block);
+                disk_inode.xattr_block = 0;
+            } else {
+                if disk_inode.xattr_block == 0 {
+                    disk_inode.xattr_block = fs.alloc_data();
+                }
+                get_block_cache(
+                    disk_inode.xattr_block as usize,
+                    Arc::clone(&self.block_device),
+                )
+                .lock()
+                .modify(0, |data_block: &mut [u8; BLOCK_SZ]| *data_block = block);
+            }
+            disk_inode.ctime = fs.now();
+        });
+        Ok(())
+    }
+
+    /// 扩展属性 `name` 的值
+    pub fn get_xattr(&self, name: &str) -> Result<Vec<u8>, XattrError> {
+        if !self.fs.supports_xattrs() {
+            return Err(XattrError::Unsupported);
+        }
+        if !valid_xattr_name(name) {
+            return Err(XattrError::InvalidName);
+        }
+        let _guard = self.lock.read();
+        self.read_disk_inode(|disk_inode| self.xattrs(disk_inode))
+            .remove(name)
+            .ok_or(XattrError::NotFound)
+    }
+
+    /// 所有扩展属性的名称，按名称排序
+    pub fn list_xattr(&self) -> Vec<String> {
+        let _guard = self.lock.read();
+        self.read_disk_inode(|disk_inode| self.xattrs(disk_inode))
+            .into_keys()
+            .collect()
+    }
+
+    /// 设置扩展属性，已有同名属性时替换它的值，ctime 更新为当前时间
+    pub fn set_xattr(&self, name: &str, value: &[u8]) -> Result<(), XattrError> {
+        let fs = self.fs.begin();
+        if !fs.supports_xattrs() {
+            return Err(XattrError::Unsupported);
+        }
+        if !valid_xattr_name(name) {
+            return Err(XattrError::InvalidName);
+        }
+        let _guard = self.lock.write();
+        let mut xattrs = self.read_disk_inode(|disk_inode| self.xattrs(disk_inode));
+        xattrs.insert(String::from(name), Vec::from(value));
+        self.write_xattrs(&xattrs, &fs)?;
+        fs.commit();
+        Ok(())
+    }
+
+    /// 删除扩展属性，ctime 更新为当前时间
+    pub fn remove_xattr(&self, name: &str) -> Result<(), XattrError> {
+        let fs = self.fs.begin();
+        if !fs.supports_xattrs() {
+            return Err(XattrError::Unsupported);
+        }
+        let _guard = self.lock.write();
+        let mut xattrs = self.read_disk_inode(|disk_inode| self.xattrs(disk_inode));
+        if xattrs.remove(name).is_none() {
+            return Err(XattrError::NotFound);
+        }
+        self.write_xattrs(&xattrs, &fs)?;
+        fs.commit();
+        Ok(())
+    }
+
     pub fn is_dir(&self) -> bool {
         self.read_disk_inode(|disk_inode| disk_inode.is_dir())
     }
@@ -216,111 +606,542 @@ impl Inode {
         self.read_disk_inode(|disk_inode| disk_inode.is_file())
     }
 
-    pub fn delete(&self, name: &str) -> bool {
-        let mut fs = self.fs.lock();
+    pub fn is_symlink(&self) -> bool {
+        self.read_disk_inode(|disk_inode| disk_inode.is_symlink())
+    }
+
+    /// 在当前目录中插入一个目录项
+    ///
+    /// 优先使用已有记录尾部的空闲空间，没有足够的空间时追加一个数据块。
+    /// 有哈希索引的目录通过空闲空间表找到有足够空间的块，不需要读出整个目录
+    fn add_dirent(&self, name: &str, inode_id: u32, type_: DiskInodeType, fs: &FileSystem) {
+        let dirent = DirEntry::new(name, inode_id, type_);
+        let needed = dirent.rec_len();
+        self.modify_disk_inode(|dir_inode| {
+            let fits = |(_, header): &(usize, DirEntryHeader)| {
+                header.rec_len as usize - header.used_len() >= needed
+            };
+            let slot = if dir_inode.has_dir_index() {
+                dir_index::find_free(dir_inode, needed, &self.block_device).and_then(|block| {
+                    let start = block as usize * BLOCK_SZ;
+                    let mut data = [0u8; BLOCK_SZ];
+                    let len = dir_inode.read_at(start, &mut data, &self.block_device);
+                    dir_records(&data[..len])
+                        .into_iter()
+                        .find(fits)
+                        .map(|(offset, header)| (start + offset, header))
+                })
+            } else {
+                let data = self.dir_data(dir_inode);
+                dir_records(&data).into_iter().find(fits)
+            };
+            let offset = match slot {
+                Some((offset, header)) if header.name_len == 0 => {
+                    let bytes = dirent.to_bytes(header.rec_len as usize);
+                    dir_inode.write_at(offset, &bytes, &self.block_device);
+                    offset
+                }
+                Some((offset, mut header)) => {
+                    // 把记录尾部的空闲空间分给新目录项
+                    let used = header.used_len();
+                    let bytes = dirent.to_bytes(header.rec_len as usize - used);
+                    header.rec_len = used as u16;
+                    dir_inode.write_at(offset, &header.to_bytes(), &self.block_device);
+                    dir_inode.write_at(offset + used, &bytes, &self.block_device);
+                    offset + used
+                }
+                None => {
+                    let offset = dir_inode.size as usize;
+                    self.increase_size((offset + BLOCK_SZ) as u32, dir_inode, fs);
+                    let bytes = dirent.to_bytes(BLOCK_SZ);
+                    dir_inode.write_at(offset, &bytes, &self.block_device);
+                    offset
+                }
+            };
+            let now = fs.now();
+            let indexable = fs.supports_dir_index();
+            let alloc = &mut |goal, max_len| fs.alloc_data_contiguous(goal, max_len);
+            if dir_inode.has_dir_index() {
+                let block = (offset / BLOCK_SZ) as u32;
+                dir_index::update_free(dir_inode, block, &self.block_device);
+                dir_index::insert(
+                    dir_inode,
+                    name.as_bytes(),
+                    offset,
+                    alloc,
+                    &self.block_device,
+                );
+            } else if indexable {
+                dir_index::build_if_large(dir_inode, alloc, &self.block_device);
+            }
+            dir_inode.touch_modify(now);
+        });
+    }
+
+    /// 从当前目录中移除名为 `name` 的目录项
+    ///
+    /// 记录的空间并入同一块中的前一条记录，是块中第一条记录时标记为未使用
+    fn remove_dirent(&self, name: &str, fs: &FileSystem) {
+        let offset = self.read_disk_inode(|dir_inode| {
+            if dir_inode.has_dir_index() {
+                return dir_index::lookup(dir_inode, name, &self.block_device)
+                    .map(|(offset, _)| offset);
+            }
+            let data = self.dir_data(dir_inode);
+            dir_records(&data)
+                .into_iter()
+                .find(|(offset, header)| {
+                    header.name_len > 0
+                        && &data[offset + DIRENT_HEADER_SZ..][..header.name_len as usize]
+                            == name.as_bytes()
+                })
+                .map(|(offset, _)| offset)
+        });
+        if let Some(offset) = offset {
+            self.remove_dirent_at(offset, fs);
+        }
+    }
+
+    /// 移除偏移为 `offset` 的目录项，只需要读出它所在的块
+    pub(crate) fn remove_dirent_at(&self, offset: usize, fs: &FileSystem) {
+        self.modify_disk_inode(|dir_inode| {
+            let start = offset - offset % BLOCK_SZ;
+            let mut data = [0u8; BLOCK_SZ];
+            let len = dir_inode.read_at(start, &mut data, &self.block_device);
+            let records = dir_records(&data[..len]);
+            let Some(index) = records
+                .iter()
+                .position(|(record, header)| start + record == offset && header.name_len > 0)
+            else {
+                return;
+            };
+            let (record, mut header) = records[index];
+            if dir_inode.has_dir_index() {
+                let name = &data[record + DIRENT_HEADER_SZ..][..header.name_len as usize];
+                dir_index::remove(dir_inode, name, offset, &self.block_device);
+            }
+            if record == 0 {
+                header.name_len = 0;
+                dir_inode.write_at(offset, &header.to_bytes(), &self.block_device);
+            } else {
+                let (prev_offset, mut prev) = records[index - 1];
+                prev.rec_len += header.rec_len;
+                dir_inode.write_at(start + prev_offset, &prev.to_bytes(), &self.block_device);
+            }
+            if dir_inode.has_dir_index() {
+                dir_index::update_free(dir_inode, (start / BLOCK_SZ) as u32, &self.block_device);
+            }
+            dir_inode.touch_modify(fs.now());
+        });
+    }
+
+    /// 释放目录末尾不再包含目录项的块
+    ///
+    /// 目录项的偏移在删除其他目录项时保持不变，所以只能回收末尾的块。
+    /// 有哈希索引的目录变空时先删除索引，之后整个目录都可以回收
+    fn shrink_dir(&self, fs: &FileSystem) {
+        let data_blocks_dealloc = self.modify_disk_inode(|dir_inode| {
+            if dir_inode.has_dir_index() {
+                dir_index::drop_if_empty(dir_inode, &self.block_device);
+            }
+            let old_blocks = dir_inode.data_blocks();
+            let mut blocks = old_blocks;
+            while blocks > 0 {
+                let mut bytes = [0u8; DIRENT_HEADER_SZ];
+                dir_inode.read_at(
+                    (blocks - 1) as usize * BLOCK_SZ,
+                    &mut bytes,
+                    &self.block_device,
+                );
+                let header = DirEntryHeader::from_bytes(&bytes);
+                if header.name_len > 0
+                    || header.rec_len as usize != BLOCK_SZ
+                    || header.is_dir_index()
+                {
+                    break;
+                }
+                blocks -= 1;
+            }
+            if blocks == old_blocks {
+                return Vec::new();
+            }
+            let freed = dir_inode.decrease_size(blocks * BLOCK_SZ as u32, &self.block_device);
+            if dir_inode.has_dir_index() {
+                // 回收的块在空闲空间表中记为没有空闲空间，之后追加的块会重新更新
+                for block in blocks..old_blocks {
+                    dir_index::update_free(dir_inode, block, &self.block_device);
+                }
+            }
+            freed
+        });
+        for data_block in data_blocks_dealloc.into_iter() {
+            fs.dealloc_data(data_block);
+        }
+    }
+
+    /// 链接数减一并更新 ctime，返回链接数是否减为零
+    ///
+    /// 链接数减为零的 inode 在最后一个引用释放时回收，除非此前已经调用了 `release`
+    fn drop_link(&self, fs: &FileSystem) -> bool {
+        let orphan = self.modify_disk_inode(|disk_inode| {
+            disk_inode.nlink = disk_inode.nlink.saturating_sub(1);
+            disk_inode.ctime = fs.now();
+            disk_inode.nlink == 0
+        });
+        if orphan {
+            self.orphan.store(true, Ordering::Relaxed);
+        }
+        orphan
+    }
+
+    /// 当前目录是否不包含任何目录项
+    fn is_empty_dir(&self, disk_inode: &DiskInode, fs: &FileSystem) -> bool {
+        self.dirents(disk_inode, fs).is_empty()
+    }
+
+    /// 在当前目录中创建名为 `name` 的硬链接，指向 `target`
+    ///
+    /// 不允许对目录创建硬链接，`name` 已存在或者 `target` 的链接数已经减为零时失败
+    pub fn link(&self, name: &str, target: &Inode) -> bool {
+        let fs = self.fs.begin();
+        if fs.has_legacy_dirents() || name.is_empty() || name.len() > NAME_LENGTH_LIMIT {
+            return false;
+        }
+        let _guard = self.lock.write();
+        let exists = self.read_disk_inode(|disk_inode| {
+            !disk_inode.is_dir() || self.find_inode_id(name, disk_inode, &fs).is_some()
+        });
+        let target_type = target.read_disk_inode(|disk_inode| disk_inode.type_());
+        if exists || target_type == DiskInodeType::Directory {
+            return false;
+        }
+        let _target_guard = target.lock.write();
+        if target.nlink() == 0 {
+            return false;
+        }
+        let target_inode_id = target.get_inode_id();
+        self.add_dirent(name, target_inode_id, target_type, &fs);
+        target.modify_disk_inode(|disk_inode| {
+            disk_inode.nlink += 1;
+            disk_inode.ctime = fs.now();
+        });
+        fs.commit();
+        true
+    }
+
+    /// 删除目录项 `name` 并减少目标的链接数，返回目标 inode
+    ///
+    /// 链接数减为零时，目标在最后一个引用释放时才被回收，
+    /// 仍被打开的文件在关闭前可以继续使用。调用者也可以用 `release` 立即释放。目录必须为空才能删除
+    pub fn unlink(&self, name: &str) -> Option<Arc<Inode>> {
+        let fs = self.fs.begin();
+        if fs.has_legacy_dirents() {
+            return None;
+        }
+        let _guard = self.lock.write();
 
         // 首先检查当前 inode 是否为目录
         let is_current_dir = self.read_disk_inode(|disk_inode| disk_inode.is_dir());
         if !is_current_dir {
-            return false; // 只能在目录中删除文件
+            return None; // 只能在目录中删除文件
         }
 
         // 查找要删除的文件/目录的 inode_id
         let target_inode_id =
-            self.read_disk_inode(|disk_inode| self.find_inode_id(name, disk_inode));
+            self.read_disk_inode(|disk_inode| self.find_inode_id(name, disk_inode, &fs))?;
+
+        // 获取目标 inode 并检查其类型，锁住目标使目录在检查后不会再加入目录项
+        let target = self.inode_at(target_inode_id);
+        let target_guard = target.lock.write();
+
+        // 检查目标是否为目录，如果是目录则检查是否为空
+        let target_is_empty_dir = target.read_disk_inode(|target_disk_inode| {
+            if target_disk_inode.is_dir() {
+                // 目录必须为空才能删除
+                target.is_empty_dir(target_disk_inode, &fs)
+            } else {
+                true // 文件可以直接删除
+            }
+        });
+        if !target_is_empty_dir {
+            return None; // 目录不为空，不能删除
+        }
+
+        // 从当前目录中移除目录项
+        self.remove_dirent(name, &fs);
+        self.shrink_dir(&fs);
+        target.drop_link(&fs);
+        drop(target_guard);
+
+        // 同步所有缓存
+        fs.commit();
+        Some(target)
+    }
+
+    /// 释放链接数已经为零的 inode 及其数据块，重复调用时什么也不做
+    ///
+    /// 调用者需要保证此后不再通过其他引用访问这个 inode
+    pub fn release(&self) {
+        if self.released.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let fs = self.fs.begin();
+        let _guard = self.lock.write();
+        self.modify_disk_inode(|disk_inode| {
+            assert_eq!(disk_inode.nlink, 0);
+            // 目录在删除前已经为空，但旧镜像中的空目录可能还留有数据块
+            let data_blocks_dealloc = disk_inode.decrease_size(0, &self.block_device);
+            for data_block in data_blocks_dealloc.into_iter() {
+                fs.dealloc_data(data_block);
+            }
+            if fs.supports_xattrs() && disk_inode.xattr_block != 0 {
+                fs.dealloc_data(disk_inode.xattr_block);
+                disk_inode.xattr_block = 0;
+            }
+        });
+        let inode_id = self.get_inode_id();
+        fs.dealloc_inode(inode_id);
+        fs.forget_inode(inode_id, self);
+        fs.commit();
+    }
+
+    pub fn nlink(&self) -> u16 {
+        self.read_disk_inode(|disk_inode| disk_inode.nlink)
+    }
 
-        let target_inode_id = match target_inode_id {
-            Some(id) => id,
-            None => return false, // 文件不存在
+    fn inode_at(&self, inode_id: u32) -> Arc<Inode> {
+        self.fs.get_inode(inode_id)
+    }
+
+    /// 当前目录的子树中是否包含编号为 `inode_id` 的 inode，每次只锁住一个目录
+    fn subtree_contains(&self, inode_id: u32) -> bool {
+        let children: Vec<u32> = {
+            let _guard = self.lock.read();
+            self.read_disk_inode(|disk_inode| {
+                self.dirents(disk_inode, &self.fs)
+                    .into_iter()
+                    .map(|(_, dirent)| dirent.inode_number())
+                    .collect()
+            })
         };
+        children.into_iter().any(|child_id| {
+            if child_id == inode_id {
+                return true;
+            }
+            let child = self.inode_at(child_id);
+            child.is_dir() && child.subtree_contains(inode_id)
+        })
+    }
 
-        // 获取目标 inode 的位置并检查其类型
-        let (target_block_id, target_block_offset) = fs.get_disk_inode_pos(target_inode_id);
+    /// 将当前目录中的 `old_name` 移动到 `new_parent` 目录下并命名为 `new_name`
+    ///
+    /// 目标已存在时，文件可以替换文件，目录只能替换空目录。
+    /// 成功时返回被替换的 inode，其链接数已经减一，与 `unlink` 相同在最后一个引用释放时回收
+    pub fn rename(
+        &self,
+        old_name: &str,
+        new_parent: &Inode,
+        new_name: &str,
+    ) -> Result<Option<Arc<Inode>>, RenameError> {
+        let fs = self.fs.begin();
+        if fs.has_legacy_dirents() {
+            return Err(RenameError::ReadOnly);
+        }
+        if new_name.is_empty() || new_name.len() > NAME_LENGTH_LIMIT {
+            return Err(RenameError::NotFound);
+        }
 
-        // 检查目标是否为目录，如果是目录则检查是否为空
-        let target_is_empty_dir =
-            get_block_cache(target_block_id as usize, Arc::clone(&self.block_device))
-                .lock()
-                .read(target_block_offset, |target_disk_inode: &DiskInode| {
-                    if target_disk_inode.is_dir() {
-                        // 目录必须为空才能删除
-                        target_disk_inode.size == 0
+        // 目录不能移动到自己或自己的子目录中。持有 `rename_lock` 时已有的目录不会被移动，
+        // 加锁后 `old_name` 即使指向了新建的目录，它的子树也不会包含 `new_parent`
+        let _rename_guard = if core::ptr::eq(self, new_parent) {
+            None
+        } else {
+            let guard = self.fs.rename_lock.lock();
+            let new_parent_id = new_parent.get_inode_id();
+            if self.is_dir()
+                && self.find(old_name).is_some_and(|src| {
+                    src.is_dir()
+                        && (src.get_inode_id() == new_parent_id
+                            || src.subtree_contains(new_parent_id))
+                })
+            {
+                return Err(RenameError::IntoSubtree);
+            }
+            Some(guard)
+        };
+
+        loop {
+            let guards = lock_all(&[self, new_parent]);
+            if !self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+                || !new_parent.read_disk_inode(|disk_inode| disk_inode.is_dir())
+            {
+                return Err(RenameError::NotFound);
+            }
+            let src_id = self
+                .read_disk_inode(|disk_inode| self.find_inode_id(old_name, disk_inode, &fs))
+                .ok_or(RenameError::NotFound)?;
+            let src = self.inode_at(src_id);
+            let src_type = src.read_disk_inode(|disk_inode| disk_inode.type_());
+            let src_is_dir = src_type == DiskInodeType::Directory;
+
+            let mut replaced = None;
+            if let Some(dst_id) = new_parent
+                .read_disk_inode(|disk_inode| new_parent.find_inode_id(new_name, disk_inode, &fs))
+            {
+                // 新旧名字指向同一个 inode 时什么也不做
+                if dst_id == src_id {
+                    return Ok(None);
+                }
+                let dst = self.inode_at(dst_id);
+                // 目标是当前目录时它包含源目录项，不为空
+                if core::ptr::eq(&*dst, self) {
+                    return Err(RenameError::TargetExists);
+                }
+                // 目标可能是当前目录的祖先，不能在持有目录的锁时等待它
+                let Some(dst_guard) = dst.lock.try_write() else {
+                    drop(guards);
+                    core::hint::spin_loop();
+                    continue;
+                };
+                let replaceable = dst.read_disk_inode(|disk_inode| {
+                    if src_is_dir {
+                        disk_inode.is_dir() && dst.is_empty_dir(disk_inode, &fs)
                     } else {
-                        true // 文件可以直接删除
+                        !disk_inode.is_dir()
                     }
                 });
+                if !replaceable {
+                    return Err(RenameError::TargetExists);
+                }
+                new_parent.remove_dirent(new_name, &fs);
+                dst.drop_link(&fs);
+                drop(dst_guard);
+                replaced = Some(dst);
+            }
 
-        if !target_is_empty_dir {
-            return false; // 目录不为空，不能删除
+            self.remove_dirent(old_name, &fs);
+            new_parent.add_dirent(new_name, src_id, src_type, &fs);
+            self.shrink_dir(&fs);
+            src.modify_disk_inode(|disk_inode| disk_inode.ctime = fs.now());
+            drop(guards);
+            fs.commit();
+            return Ok(replaced);
         }
+    }
 
-        // 清理目标 inode 的数据块
-        get_block_cache(target_block_id as usize, Arc::clone(&self.block_device))
-            .lock()
-            .modify(target_block_offset, |target_disk_inode: &mut DiskInode| {
-                if target_disk_inode.is_file() {
-                    // 释放文件的所有数据块
-                    let size = target_disk_inode.size;
-                    let data_blocks_dealloc = target_disk_inode.clear_size(&self.block_device);
-                    assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
-                    for data_block in data_blocks_dealloc.into_iter() {
-                        fs.dealloc_data(data_block);
-                    }
-                }
-                // 目录的话，由于已经检查为空，所以不需要释放数据块
-            });
+    /// 删除目录项 `name` 及其指向的整棵子树，返回链接数减为零的 inode，子节点排在父节点之前
+    ///
+    /// 删除前先遍历子树，用 `may_modify` 检查每个需要删除目录项的目录，
+    /// 有一个目录不允许修改时什么也不删除，返回 None。
+    /// 与 `unlink` 相同，由调用者决定何时调用 `release` 释放返回的 inode
+    pub fn remove_tree(
+        &self,
+        name: &str,
+        may_modify: impl Fn(&Inode) -> bool,
+    ) -> Option<Vec<Arc<Inode>>> {
+        // 检查时不持有任何锁，`may_modify` 可以读取 inode 的元数据
+        let target = self.find(name)?;
+        if target.is_dir() && !target.subtree_modifiable(&may_modify) {
+            return None;
+        }
 
-        // 释放目标 inode
-        fs.dealloc_inode(target_inode_id);
+        let mut fs = self.fs.begin();
+        if fs.has_legacy_dirents() || !self.is_dir() {
+            return None;
+        }
+        let _guard = self.lock.write();
+        let target_id =
+            self.read_disk_inode(|disk_inode| self.find_inode_id(name, disk_inode, &fs))?;
+        let target = self.inode_at(target_id);
+        let target_guard = target.lock.write();
+        let mut removed = Vec::new();
+        if target.is_dir() {
+            target.remove_children(&mut fs, &mut removed);
+        }
+        self.remove_dirent(name, &fs);
+        self.shrink_dir(&fs);
+        let orphan = target.drop_link(&fs);
+        drop(target_guard);
+        if orphan {
+            removed.push(target);
+        }
+        fs.commit();
+        Some(removed)
+    }
 
-        // 从当前目录中移除目录项
-        self.modify_disk_inode(|root_inode| {
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let mut found_index = None;
-
-            // 找到要删除的目录项的索引
-            for i in 0..file_count {
-                let mut dirent = DirEntry::empty();
-                assert_eq!(
-                    root_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device),
-                    DIRENT_SZ,
-                );
-                if dirent.name() == name {
-                    found_index = Some(i);
-                    break;
-                }
-            }
+    /// 子树中所有非空目录是否都允许删除目录项
+    fn subtree_modifiable(&self, may_modify: &impl Fn(&Inode) -> bool) -> bool {
+        let names = self.ls();
+        names.is_empty()
+            || may_modify(self)
+                && names.iter().all(|name| match self.find(name) {
+                    Some(child) if child.is_dir() => child.subtree_modifiable(may_modify),
+                    _ => true,
+                })
+    }
 
-            if let Some(index) = found_index {
-                // 将最后一个目录项移动到被删除项的位置
-                if index < file_count - 1 {
-                    let mut last_dirent = DirEntry::empty();
-                    assert_eq!(
-                        root_inode.read_at(
-                            (file_count - 1) * DIRENT_SZ,
-                            last_dirent.as_bytes_mut(),
-                            &self.block_device
-                        ),
-                        DIRENT_SZ,
-                    );
-                    root_inode.write_at(
-                        index * DIRENT_SZ,
-                        last_dirent.as_bytes(),
-                        &self.block_device,
-                    );
-                }
+    /// 删除目录中的所有目录项，子目录先递归清空，链接数减为零的 inode 加入 `removed`
+    ///
+    /// 调用者持有当前目录的锁，每个子节点在处理期间被锁住
+    fn remove_children(&self, fs: &mut Transaction<'_>, removed: &mut Vec<Arc<Inode>>) {
+        let children = self.read_disk_inode(|disk_inode| self.dirents(disk_inode, fs));
+        for (offset, dirent) in children.into_iter().rev() {
+            let child = self.inode_at(dirent.inode_number());
+            let child_guard = child.lock.write();
+            if child.is_dir() {
+                child.remove_children(fs, removed);
+            }
+            self.remove_dirent_at(offset, fs);
+            let orphan = child.drop_link(fs);
+            drop(child_guard);
+            if orphan {
+                removed.push(child);
+            }
+            // 每删除一个目录项后目录树都是一致的
+            fs.checkpoint();
+        }
+        self.shrink_dir(fs);
+    }
 
-                // 减少目录大小
-                let new_size = (file_count - 1) * DIRENT_SZ;
-                root_inode.size = new_size as u32;
+    /// 删除目录项，链接数减为零且没有其他引用时立即释放目标
+    pub fn delete(&self, name: &str) -> bool {
+        self.unlink(name).is_some()
+    }
+}
 
-                // 如果目录变小了很多，可以考虑释放一些数据块
-                // 这里简化处理，不释放已分配的块
+/// 锁住 `inodes` 中的所有 inode，重复出现的只锁一次
+///
+/// 只等待第一个 inode 的锁，其余的锁被占用时全部放开重试，
+/// 不会与先锁目录、再锁目录项指向的 inode 的操作互相等待
+fn lock_all<'a>(inodes: &[&'a Inode]) -> Vec<RwLockWriteGuard<'a, ()>> {
+    'retry: loop {
+        let mut guards = Vec::new();
+        for (i, inode) in inodes.iter().enumerate() {
+            if inodes[..i]
+                .iter()
+                .any(|locked| core::ptr::eq(*locked, *inode))
+            {
+                continue;
             }
-        });
+            if i == 0 {
+                guards.push(inode.lock.write());
+            } else if let Some(guard) = inode.lock.try_write() {
+                guards.push(guard);
+            } else {
+                drop(guards);
+                core::hint::spin_loop();
+                continue 'retry;
+            }
+        }
+        return guards;
+    }
+}
 
-        // 同步所有缓存
-        block_cache_sync_all();
-        true
+impl Drop for Inode {
+    /// 最后一个引用释放时回收链接数已经为零的 inode
+    fn drop(&mut self) {
+        if self.orphan.load(Ordering::Relaxed) {
+            self.release();
+        }
     }
 }
@@ -0,0 +1,74 @@
+use super::BLOCK_SZ;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// 属性块开头的 magic，不符时认为块中没有属性
+const XATTR_MAGIC: u32 = 0x58415454;
+/// 属性块头部：magic 和属性个数
+const XATTR_HEADER_SZ: usize = 6;
+/// 每个属性的头部：名称长度（u8）和值的长度（u16）
+const XATTR_ENTRY_HEADER_SZ: usize = 3;
+/// 属性名的最大长度
+pub const XATTR_NAME_MAX: usize = 255;
+
+type DataBlock = [u8; BLOCK_SZ];
+
+/// 一个 inode 的所有扩展属性，按名称排序
+pub type Xattrs = BTreeMap<String, Vec<u8>>;
+
+pub fn valid_xattr_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= XATTR_NAME_MAX && !name.contains('\0')
+}
+
+/// 解析属性块，遇到损坏的属性时丢弃它和之后的所有属性
+///
+/// 属性块依次为 magic、属性个数和紧密排列的属性，每个属性是名称长度、值的长度、名称和值
+pub fn decode_xattrs(block: &DataBlock) -> Xattrs {
+    let mut xattrs = Xattrs::new();
+    if u32::from_le_bytes(block[0..4].try_into().unwrap()) != XATTR_MAGIC {
+        return xattrs;
+    }
+    let count = u16::from_le_bytes(block[4..6].try_into().unwrap());
+    let mut offset = XATTR_HEADER_SZ;
+    for _ in 0..count {
+        if offset + XATTR_ENTRY_HEADER_SZ > BLOCK_SZ {
+            break;
+        }
+        let name_len = block[offset] as usize;
+        let value_len = u16::from_le_bytes(block[offset + 1..offset + 3].try_into().unwrap());
+        let name_start = offset + XATTR_ENTRY_HEADER_SZ;
+        let value_start = name_start + name_len;
+        let end = value_start + value_len as usize;
+        if end > BLOCK_SZ {
+            break;
+        }
+        let Ok(name) = core::str::from_utf8(&block[name_start..value_start]) else {
+            break;
+        };
+        xattrs.insert(String::from(name), Vec::from(&block[value_start..end]));
+        offset = end;
+    }
+    xattrs
+}
+
+/// 编码为属性块，放不进一个块时返回 None
+pub fn encode_xattrs(xattrs: &Xattrs) -> Option<DataBlock> {
+    let mut block = [0u8; BLOCK_SZ];
+    block[0..4].copy_from_slice(&XATTR_MAGIC.to_le_bytes());
+    block[4..6].copy_from_slice(&(xattrs.len() as u16).to_le_bytes());
+    let mut offset = XATTR_HEADER_SZ;
+    for (name, value) in xattrs {
+        let end = offset + XATTR_ENTRY_HEADER_SZ + name.len() + value.len();
+        if end > BLOCK_SZ {
+            return None;
+        }
+        block[offset] = name.len() as u8;
+        block[offset + 1..offset + 3].copy_from_slice(&(value.len() as u16).to_le_bytes());
+        let value_start = offset + XATTR_ENTRY_HEADER_SZ + name.len();
+        block[offset + XATTR_ENTRY_HEADER_SZ..value_start].copy_from_slice(name.as_bytes());
+        block[value_start..end].copy_from_slice(value);
+        offset = end;
+    }
+    Some(block)
+}
@@ -1,9 +1,11 @@
-use clap::{App, Arg};
-use file_system::{BlockDevice, DiskInodeType, FileSystem};
+use clap::{App, Arg, ArgMatches};
+use file_system::{BlockDevice, DiskInodeType, FileSystem, init_block_cache};
+use std::ffi::OsString;
 use std::fs::{File, OpenOptions, read_dir};
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 const BLOCK_SZ: usize = 512;
 
@@ -29,11 +31,34 @@ impl BlockDevice for BlockFile {
     }
 }
 
-fn main() {
-    fs_pack().expect("Error when packing fs!");
+fn unix_secs(time: SystemTime) -> u32 {
+    time.duration_since(UNIX_EPOCH).unwrap().as_secs() as u32
+}
+
+fn host_now() -> u32 {
+    unix_secs(SystemTime::now())
+}
+
+/// 读取宿主机文件的扩展属性，宿主机的文件系统不支持扩展属性时当作没有属性
+fn host_xattrs(path: &str) -> std::io::Result<Vec<(OsString, Vec<u8>)>> {
+    let names = match xattr::list(path) {
+        Ok(names) => names,
+        Err(err) if err.kind() == ErrorKind::Unsupported => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut attrs = Vec::new();
+    for name in names {
+        match xattr::get(path, &name) {
+            Ok(Some(value)) => attrs.push((name, value)),
+            Ok(None) => {}
+            Err(err) if err.kind() == ErrorKind::Unsupported => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(attrs)
 }
 
-fn fs_pack() -> std::io::Result<()> {
+fn main() {
     let matches = App::new("FileSystem packer")
         .arg(
             Arg::with_name("source")
@@ -49,7 +74,67 @@ fn fs_pack() -> std::io::Result<()> {
                 .takes_value(true)
                 .help("Executable target dir(with backslash)"),
         )
+        .arg(
+            Arg::with_name("alias")
+                .short("a")
+                .long("alias")
+                .takes_value(true)
+                .multiple(true)
+                .help("Create /bin/<alias> as a symlink to an app, e.g. sh=user_shell"),
+        )
+        .arg(
+            Arg::with_name("compress")
+                .short("c")
+                .long("compress")
+                .help("Store apps compressed, decompressed transparently when read"),
+        )
+        .arg(
+            Arg::with_name("fsck")
+                .long("fsck")
+                .takes_value(true)
+                .conflicts_with_all(&["source", "target"])
+                .help("Check the consistency of an existing image instead of packing"),
+        )
+        .arg(
+            Arg::with_name("repair")
+                .long("repair")
+                .requires("fsck")
+                .help("Fix the problems found by --fsck"),
+        )
         .get_matches();
+    if let Some(image) = matches.value_of("fsck") {
+        let code = fs_check(image, matches.is_present("repair")).expect("Error when checking fs!");
+        std::process::exit(code);
+    }
+    fs_pack(&matches).expect("Error when packing fs!");
+}
+
+/// 检查镜像的一致性，`repair` 为真时修复发现的问题，返回进程的退出码
+fn fs_check(image: &str, repair: bool) -> std::io::Result<i32> {
+    let block_file = Arc::new(BlockFile(Mutex::new(
+        OpenOptions::new().read(true).write(true).open(image)?,
+    )));
+    let Some(efs) = FileSystem::open(block_file) else {
+        println!("{}: invalid or corrupted superblock", image);
+        return Ok(1);
+    };
+    let problems = FileSystem::check(&efs, repair);
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+    if problems.is_empty() {
+        println!("{}: clean", image);
+        Ok(0)
+    } else if repair {
+        println!("{}: {} problems repaired", image, problems.len());
+        Ok(0)
+    } else {
+        println!("{}: {} problems found", image, problems.len());
+        Ok(1)
+    }
+}
+
+fn fs_pack(matches: &ArgMatches) -> std::io::Result<()> {
     let src_path = matches.value_of("source").unwrap();
     let target_path = matches.value_of("target").unwrap();
     println!("src_path = {}\ntarget_path = {}", src_path, target_path);
@@ -64,8 +149,10 @@ fn fs_pack() -> std::io::Result<()> {
         f
     })));
     // 16MiB, at most 4095 files
+    init_block_cache(1024);
     let efs = FileSystem::create(block_file, 16 * 2048, 1);
-    let root_inode = Arc::new(FileSystem::root_inode(&efs));
+    efs.set_clock(host_now);
+    let root_inode = FileSystem::root_inode(&efs);
 
     let bin_inode = root_inode.create_dir("bin").unwrap();
 
@@ -80,13 +167,38 @@ fn fs_pack() -> std::io::Result<()> {
         .collect();
     for app in apps {
         // load app data from host file system
-        let mut host_file = File::open(format!("{}{}", target_path, app)).unwrap();
+        let host_path = format!("{}{}", target_path, app);
+        let mut host_file = File::open(&host_path).unwrap();
         let mut all_data: Vec<u8> = Vec::new();
         host_file.read_to_end(&mut all_data).unwrap();
         // create a file in fs
         let inode = bin_inode.create(app.as_str()).unwrap();
+        // 应用一次性整体写入，用区段树连续存放，或者按簇压缩存放
+        if matches.is_present("compress") {
+            inode.set_compressed();
+        } else {
+            inode.set_extents();
+        }
         // write data to fs
         inode.write_at(0, all_data.as_slice());
+        inode.set_mode(0o755);
+        // 保留宿主机上的扩展属性，放不进属性块的属性被跳过
+        for (name, value) in host_xattrs(&host_path)? {
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if let Err(err) = inode.set_xattr(name, &value) {
+                println!("skip xattr {} of {}: {:?}", name, app, err);
+            }
+        }
+        // 保留宿主机上的修改时间
+        let mtime = unix_secs(host_file.metadata()?.modified()?);
+        inode.set_times(mtime, mtime);
+    }
+    // 以符号链接的形式为应用创建别名
+    for alias in matches.values_of("alias").into_iter().flatten() {
+        let (name, app) = alias.split_once('=').expect("alias must be <name>=<app>");
+        bin_inode.symlink(name, app).unwrap();
     }
     // list apps
     // for app in root_inode.ls() {
@@ -95,33 +207,97 @@ fn fs_pack() -> std::io::Result<()> {
     Ok(())
 }
 
-#[test]
-fn efs_test() -> std::io::Result<()> {
+/// 块缓存和校验和表全局只有一份，使用文件系统的测试不能并行执行
+#[cfg(test)]
+static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+/// 在 `target/<name>.img` 上新建一个文件系统，测试结束前一直持有测试锁
+#[cfg(test)]
+fn test_fs(
+    name: &str,
+) -> std::io::Result<(
+    std::sync::MutexGuard<'static, ()>,
+    Arc<BlockFile>,
+    Arc<FileSystem>,
+)> {
+    let guard = TEST_LOCK.lock().unwrap_or_else(|err| err.into_inner());
     let block_file = Arc::new(BlockFile(Mutex::new({
         let f = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
-            .open("target/fs.img")?;
+            .truncate(true)
+            .open(format!("target/{}.img", name))?;
         f.set_len(8192 * 512).unwrap();
         f
     })));
+    init_block_cache(4096);
     FileSystem::create(block_file.clone(), 4096, 1);
-    let efs = FileSystem::open(block_file.clone());
+    let efs = FileSystem::open(block_file.clone()).unwrap();
+    efs.set_clock(|| 1000);
+    Ok((guard, block_file, efs))
+}
+
+#[test]
+fn inode_metadata() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("inode_metadata")?;
     let root_inode = FileSystem::root_inode(&efs);
     root_inode.create("filea");
     root_inode.create("fileb");
-    for name in root_inode.ls() {
-        println!("{}", name);
-    }
+
+    // 新建的 inode 带有默认权限和创建时间
+    let metadata = root_inode.find("fileb").unwrap().metadata();
+    assert_eq!(metadata.type_, DiskInodeType::File);
+    assert_eq!((metadata.mode, metadata.uid, metadata.gid), (0o644, 0, 0));
+    assert_eq!(
+        (metadata.atime, metadata.mtime, metadata.ctime),
+        (1000, 1000, 1000)
+    );
+    assert_eq!(root_inode.metadata().mtime, 1000);
+    let fileb = root_inode.find("fileb").unwrap();
+    fileb.set_mode(0o4750);
+    fileb.set_owner(1000, 100);
+    let metadata = fileb.metadata();
+    assert_eq!(
+        (metadata.mode, metadata.uid, metadata.gid),
+        (0o4750, 1000, 100)
+    );
+    assert_eq!(root_inode.ls(), vec!["filea", "fileb"]);
     let filea = root_inode.find("filea").unwrap();
     let greet_str = "Hello, world!";
     filea.write_at(0, greet_str.as_bytes());
-    //let mut buffer = [0u8; 512];
     let mut buffer = [0u8; 233];
     let len = filea.read_at(0, &mut buffer);
     assert_eq!(greet_str, core::str::from_utf8(&buffer[..len]).unwrap(),);
 
+    // 读写分别更新 atime 和 mtime/ctime，set_times 只把 ctime 设为当前时间
+    filea.set_times(1, 2);
+    efs.set_clock(|| 2000);
+    filea.read_at(0, &mut buffer);
+    let metadata = filea.metadata();
+    assert_eq!(
+        (metadata.atime, metadata.mtime, metadata.ctime),
+        (2000, 2, 1000)
+    );
+    // atime 晚于 mtime 和 ctime 且不到一天时读取不再更新
+    efs.set_clock(|| 3000);
+    filea.read_at(0, &mut buffer);
+    assert_eq!(filea.metadata().atime, 2000);
+    efs.set_clock(|| 2000);
+    filea.write_at(0, greet_str.as_bytes());
+    let metadata = filea.metadata();
+    assert_eq!((metadata.mtime, metadata.ctime), (2000, 2000));
+    assert_eq!(metadata.blocks, 1);
+    Ok(())
+}
+
+#[test]
+fn read_write() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("read_write")?;
+    let root_inode = FileSystem::root_inode(&efs);
+    let filea = root_inode.create("filea").unwrap();
+    let mut buffer = [0u8; 233];
+
     let mut random_str_test = |len: usize| {
         filea.clear();
         assert_eq!(filea.read_at(0, &mut buffer), 0,);
@@ -155,5 +331,663 @@ fn efs_test() -> std::io::Result<()> {
     random_str_test(1000 * BLOCK_SZ);
     random_str_test(2000 * BLOCK_SZ);
 
+    // 缓存能容纳整个文件时，再次顺序读不会产生缺失
+    use file_system::block_cache_stats;
+    let stats = block_cache_stats();
+    let mut offset = 0;
+    loop {
+        let len = filea.read_at(offset, &mut buffer);
+        if len == 0 {
+            break;
+        }
+        offset += len;
+    }
+    assert_eq!(offset, 2000 * BLOCK_SZ);
+    assert_eq!(block_cache_stats().misses, stats.misses);
+    assert!(block_cache_stats().hits > stats.hits + 2000);
+    Ok(())
+}
+
+#[test]
+fn truncate() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("truncate")?;
+    let root_inode = FileSystem::root_inode(&efs);
+    let filea = root_inode.create("filea").unwrap();
+    let mut buffer = [0u8; 233];
+
+    // 截断：缩小时释放末尾的块和不再需要的索引块，扩展出的部分是空洞
+    let pattern: Vec<u8> = (0..300 * BLOCK_SZ).map(|i| (i % 251) as u8).collect();
+    filea.write_at(0, &pattern);
+    let shrunk = 150 * BLOCK_SZ + 7;
+    filea.truncate(shrunk as u32);
+    // 151 个数据块，加上一级、二级索引块和二级索引下的一个一级索引块
+    assert_eq!(filea.metadata().blocks, 154);
+    filea.truncate((200 * BLOCK_SZ) as u32);
+    assert_eq!(filea.metadata().blocks, 154);
+    let mut content = vec![0u8; 200 * BLOCK_SZ];
+    assert_eq!(filea.read_at(0, &mut content), content.len());
+    assert_eq!(content[..shrunk], pattern[..shrunk]);
+    assert!(content[shrunk..].iter().all(|&b| b == 0));
+    filea.truncate(10);
+    assert_eq!(filea.metadata().blocks, 1);
+    assert_eq!(filea.read_at(0, &mut buffer), 10);
+    Ok(())
+}
+
+#[test]
+fn sparse_file() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("sparse_file")?;
+    let root_inode = FileSystem::root_inode(&efs);
+    let greet_str = "Hello, world!";
+    let mut buffer = [0u8; 233];
+
+    // 稀疏文件：跳过的范围是空洞，不占用磁盘块，读出为 0
+    let sparse = root_inode.create("sparse").unwrap();
+    let offset = 1000 * BLOCK_SZ + 3;
+    sparse.write_at(offset, greet_str.as_bytes());
+    let metadata = sparse.metadata();
+    assert_eq!(metadata.size as usize, offset + greet_str.len());
+    // 一个数据块，加上二级索引块和它下面的一个一级索引块
+    assert_eq!(metadata.blocks, 3);
+    let mut content = vec![0xffu8; 2 * BLOCK_SZ];
+    assert_eq!(sparse.read_at(500 * BLOCK_SZ, &mut content), content.len());
+    assert!(content.iter().all(|&b| b == 0));
+    assert_eq!(sparse.read_at(offset, &mut buffer), greet_str.len());
+    assert_eq!(&buffer[..greet_str.len()], greet_str.as_bytes());
+    sparse.write_at(0, greet_str.as_bytes());
+    assert_eq!(sparse.metadata().blocks, 4);
+    sparse.truncate((20 * BLOCK_SZ) as u32);
+    assert_eq!(sparse.metadata().blocks, 1);
+    // 超出二级间接索引能够映射的范围的部分不写入
+    let max_size = (20 + 128 + 128 * 128) * BLOCK_SZ;
+    assert_eq!(sparse.write_at(max_size - 2, greet_str.as_bytes()), 2);
+    assert_eq!(sparse.write_at(10 << 20, greet_str.as_bytes()), 0);
+    assert_eq!(sparse.get_file_size() as usize, max_size);
+    assert!(!sparse.truncate(20 << 20));
+    assert_eq!(sparse.get_file_size() as usize, max_size);
+    drop(sparse);
+    assert!(root_inode.delete("sparse"));
+    Ok(())
+}
+
+#[test]
+fn extents() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("extents")?;
+    let root_inode = FileSystem::root_inode(&efs);
+
+    // 区段树：顺序写入的数据连续存放，不需要额外的索引块
+    let extents = root_inode.create("extents").unwrap();
+    assert!(extents.set_extents());
+    let pattern: Vec<u8> = (0..1500 * BLOCK_SZ).map(|i| (i % 253) as u8).collect();
+    extents.write_at(0, &pattern);
+    assert_eq!(extents.metadata().blocks, 1500);
+    assert!(!extents.set_extents());
+    let mut content = vec![0u8; pattern.len()];
+    assert_eq!(extents.read_at(0, &mut content), content.len());
+    assert_eq!(content, pattern);
+    // 两个文件交替写入时各自的块不连续，区段放不进 inode，改用树节点块存放
+    let (frag_a, frag_b) = (
+        root_inode.create("frag-a").unwrap(),
+        root_inode.create("frag-b").unwrap(),
+    );
+    assert!(frag_a.set_extents() && frag_b.set_extents());
+    for i in 0..100 {
+        frag_a.write_at(i * BLOCK_SZ, &[i as u8; BLOCK_SZ]);
+        frag_b.write_at(i * BLOCK_SZ, &[!i as u8; BLOCK_SZ]);
+    }
+    // 100 个区段分别占用 3 个叶子节点块
+    assert_eq!(frag_a.metadata().blocks, 103);
+    let mut block = [0u8; BLOCK_SZ];
+    for i in [0, 41, 42, 99] {
+        frag_a.read_at(i * BLOCK_SZ, &mut block);
+        assert_eq!(block, [i as u8; BLOCK_SZ]);
+        frag_b.read_at(i * BLOCK_SZ, &mut block);
+        assert_eq!(block, [!i as u8; BLOCK_SZ]);
+    }
+    frag_a.truncate((50 * BLOCK_SZ) as u32);
+    assert_eq!(frag_a.metadata().blocks, 52);
+    frag_a.truncate((5 * BLOCK_SZ) as u32);
+    assert_eq!(frag_a.metadata().blocks, 5);
+    drop((frag_a, extents));
+    assert!(root_inode.delete("frag-a"));
+    assert!(root_inode.delete("extents"));
+    Ok(())
+}
+
+#[test]
+fn links() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("links")?;
+    let root_inode = FileSystem::root_inode(&efs);
+    let filea = root_inode.create("filea").unwrap();
+    filea.write_at(0, &[1u8; BLOCK_SZ]);
+    root_inode.create("fileb");
+    let mut buffer = [0u8; 233];
+
+    // 硬链接：删除其中一个目录项后，另一个仍然可以访问文件内容
+    assert!(root_inode.link("filec", &filea));
+    assert!(!root_inode.link("fileb", &filea));
+    assert_eq!(filea.nlink(), 2);
+    assert!(root_inode.delete("filea"));
+    let filec = root_inode.find("filec").unwrap();
+    assert_eq!(filec.nlink(), 1);
+    assert_eq!(filec.read_at(0, &mut buffer), buffer.len());
+
+    // 符号链接只保存目标路径，删除链接不影响目标文件
+    let linkd = root_inode.symlink("linkd", "filec").unwrap();
+    assert!(linkd.is_symlink());
+    assert_eq!(linkd.read_link().as_deref(), Some("filec"));
+    assert_eq!(filec.read_link(), None);
+    drop(linkd);
+    assert!(root_inode.delete("linkd"));
+    assert_eq!(filec.nlink(), 1);
+    Ok(())
+}
+
+#[test]
+fn rename() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("rename")?;
+    let root_inode = FileSystem::root_inode(&efs);
+    root_inode
+        .create("filec")
+        .unwrap()
+        .write_at(0, &[1u8; BLOCK_SZ]);
+    root_inode.create("fileb");
+    let mut buffer = [0u8; 233];
+
+    // 跨目录重命名，目录不能移动到自己的子树中
+    use file_system::RenameError;
+    let dir = root_inode.create_dir("dir").unwrap();
+    let subdir = dir.create_dir("subdir").unwrap();
+    assert!(root_inode.rename("filec", &dir, "filee").unwrap().is_none());
+    assert!(root_inode.find("filec").is_none());
+    assert_eq!(
+        dir.find("filee").unwrap().read_at(0, &mut buffer),
+        buffer.len()
+    );
+    assert_eq!(
+        root_inode.rename("dir", &subdir, "dir").err(),
+        Some(RenameError::IntoSubtree)
+    );
+    assert_eq!(
+        dir.rename("filee", &root_inode, "dir").err(),
+        Some(RenameError::TargetExists)
+    );
+    let replaced = dir.rename("filee", &root_inode, "fileb").unwrap().unwrap();
+    assert_eq!(replaced.nlink(), 0);
+    replaced.release();
+    Ok(())
+}
+
+#[test]
+fn variable_length_dirents() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("variable_length_dirents")?;
+    let root_inode = FileSystem::root_inode(&efs);
+    let dir = root_inode.create_dir("dir").unwrap();
+
+    // 变长目录项：名称最长 255 字节，删除后的空间可以被新目录项复用
+    let long_name = "n".repeat(255);
+    assert!(dir.create(&long_name).is_some());
+    assert!(dir.create(&"n".repeat(256)).is_none());
+    for i in 0..40 {
+        dir.create(&format!("{}-{}", "entry".repeat(i % 8 + 1), i))
+            .unwrap();
+    }
+    assert_eq!(dir.ls().len(), 41);
+    assert!(dir.find(&long_name).is_some());
+    for i in (0..40).step_by(2) {
+        assert!(dir.delete(&format!("{}-{}", "entry".repeat(i % 8 + 1), i)));
+    }
+    let size = dir.get_file_size();
+    for i in 0..20 {
+        dir.create(&format!("reuse-{}", i)).unwrap();
+    }
+    assert_eq!(dir.get_file_size(), size);
+    assert_eq!(dir.ls().len(), 41);
+    assert!(dir.find("entry-0").is_none());
+    assert!(dir.find("entryentry-1").is_some());
+    Ok(())
+}
+
+#[test]
+fn dir_index() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("dir_index")?;
+    let root_inode = FileSystem::root_inode(&efs);
+
+    // 目录增长到几个块后建立哈希索引，查找和插入不再扫描整个目录
+    let many = root_inode.create_dir("many").unwrap();
+    for i in 0..600 {
+        many.create(&format!("file-{}", i)).unwrap();
+    }
+    assert!(many.create("file-123").is_none());
+    for i in (0..600).step_by(3) {
+        assert!(many.delete(&format!("file-{}", i)));
+    }
+    assert_eq!(many.ls().len(), 400);
+    let size = many.get_file_size();
+    for i in 0..200 {
+        many.create(&format!("n-{}", i)).unwrap();
+    }
+    // 新目录项复用删除后留下的空间，只有索引叶子分裂时才追加块
+    assert!(many.get_file_size() < size + 200 * 16);
+    assert!(many.find("file-0").is_none());
+    assert!((0..600).all(|i| many.find(&format!("file-{}", i)).is_some() == (i % 3 != 0)));
+    assert!(many.find("n-199").is_some());
+    Ok(())
+}
+
+#[test]
+fn dir_index_overflow() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("dir_index_overflow")?;
+    let root_inode = FileSystem::root_inode(&efs);
+
+    // 索引放不下全部目录项后删除索引并一直线性查找，不会在之后每次插入时重新建立
+    let huge = root_inode.create_dir("huge").unwrap();
+    let target = huge.create("target").unwrap();
+    for i in 0..4200 {
+        assert!(huge.link(&format!("l-{}", i), &target));
+    }
+    // 每个目录项占 16 字节，删除索引留下的块被重新使用
+    assert!(huge.get_file_size() < (4201 * 16 + 70 * BLOCK_SZ) as u32);
+    assert_eq!(target.nlink(), 4201);
+    assert!(huge.find("l-0").is_some() && huge.find("l-4199").is_some());
+    drop(target);
+    assert!(root_inode.remove_tree("huge", |_| true).is_some());
+    Ok(())
+}
+
+#[test]
+fn dir_shrink() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("dir_shrink")?;
+    let root_inode = FileSystem::root_inode(&efs);
+
+    // 删除目录项后回收目录末尾的空块，目录清空后不再占用数据块
+    let shrink = root_inode.create_dir("shrink").unwrap();
+    for i in 0..60 {
+        shrink.create(&format!("f-{}", i)).unwrap();
+    }
+    let blocks = shrink.metadata().blocks;
+    for i in 30..60 {
+        assert!(shrink.delete(&format!("f-{}", i)));
+    }
+    assert!(shrink.metadata().blocks < blocks);
+    for i in 0..30 {
+        assert!(shrink.delete(&format!("f-{}", i)));
+    }
+    assert_eq!(shrink.get_file_size(), 0);
+    assert_eq!(shrink.metadata().blocks, 0);
+    drop(shrink);
+    assert!(root_inode.delete("shrink"));
+    Ok(())
+}
+
+#[test]
+fn remove_tree() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("remove_tree")?;
+    let root_inode = FileSystem::root_inode(&efs);
+
+    // 递归删除整棵子树，子树外还有链接的文件保留下来
+    let tree = root_inode.create_dir("tree").unwrap();
+    let sub = tree.create_dir("sub").unwrap();
+    sub.create("file")
+        .unwrap()
+        .write_at(0, &[1u8; 3 * BLOCK_SZ]);
+    sub.create_dir("empty").unwrap();
+    let top = tree.create("top").unwrap();
+    assert!(tree.link("top-link", &top));
+    assert!(root_inode.link("outside", &top));
+    assert!(root_inode.unlink("tree").is_none());
+    assert!(root_inode.remove_tree("tree", |_| false).is_none());
+    assert!(tree.find("sub").unwrap().find("file").is_some());
+    let removed = root_inode.remove_tree("tree", |_| true).unwrap();
+    assert_eq!(removed.len(), 4);
+    for inode in removed {
+        inode.release();
+    }
+    assert!(root_inode.find("tree").is_none());
+    assert_eq!(top.nlink(), 1);
+    assert!(Arc::ptr_eq(&root_inode.find("outside").unwrap(), &top));
+    Ok(())
+}
+
+#[test]
+fn inode_cache() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("inode_cache")?;
+    let root_inode = FileSystem::root_inode(&efs);
+    // 根目录中还有其他目录项，删除 outside 后目录的块不会被回收
+    root_inode.create("filea");
+    let greet_str = "Hello, world!";
+    let mut buffer = [0u8; 233];
+
+    // 同一个 inode 在内存中只有一个对象，删除最后一个链接后等到引用全部释放才回收
+    let top = root_inode.create("outside").unwrap();
+    assert!(Arc::ptr_eq(&root_inode.find("outside").unwrap(), &top));
+    top.write_at(0, greet_str.as_bytes());
+    let free = root_inode.fs_stat();
+    assert!(root_inode.delete("outside"));
+    assert_eq!(top.nlink(), 0);
+    assert!(root_inode.find("outside").is_none());
+    assert_eq!(top.read_at(0, &mut buffer), greet_str.len());
+    assert_eq!(root_inode.fs_stat(), free);
+    drop(top);
+    let after = root_inode.fs_stat();
+    assert_eq!(after.free_inodes, free.free_inodes + 1);
+    assert_eq!(after.free_blocks, free.free_blocks + 1);
+    Ok(())
+}
+
+#[test]
+fn concurrent_access() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("concurrent_access")?;
+    let root_inode = FileSystem::root_inode(&efs);
+
+    // 多个线程同时读写各自的文件，并在各自的目录和共享目录之间移动文件
+    let shared = root_inode.create_dir("shared").unwrap();
+    std::thread::scope(|scope| {
+        for t in 0..4u8 {
+            let (root_inode, shared) = (&root_inode, &shared);
+            scope.spawn(move || {
+                let dir = root_inode.create_dir(&format!("worker-{}", t)).unwrap();
+                let mut block = [0u8; BLOCK_SZ];
+                for i in 0..20u8 {
+                    let name = format!("f-{}-{}", t, i);
+                    let file = dir.create(&name).unwrap();
+                    file.write_at(0, &[t ^ i; 2 * BLOCK_SZ]);
+                    assert_eq!(file.read_at(BLOCK_SZ, &mut block), BLOCK_SZ);
+                    assert_eq!(block, [t ^ i; BLOCK_SZ]);
+                    assert!(dir.rename(&name, shared, &name).unwrap().is_none());
+                    if i % 2 == 0 {
+                        assert!(shared.delete(&name));
+                    }
+                }
+                assert!(root_inode.delete(&format!("worker-{}", t)));
+            });
+        }
+    });
+    assert_eq!(shared.ls().len(), 40);
+    assert!(root_inode.remove_tree("shared", |_| true).is_some());
+    drop(shared);
+    assert_eq!(FileSystem::check(&efs, false), vec![]);
+    Ok(())
+}
+
+#[test]
+fn journal_batches() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("journal_batches")?;
+    let root_inode = FileSystem::root_inode(&efs);
+
+    // 修改的元数据超过日志容量的操作在中途分批提交，不会停止提交
+    let bulk = root_inode.create_dir("bulk").unwrap();
+    for i in 0..600 {
+        bulk.create(&format!("b-{}", i))
+            .unwrap()
+            .write_at(0, &[i as u8; BLOCK_SZ]);
+    }
+    let big = bulk.create("big").unwrap();
+    assert_eq!(big.write_at(0, &[7u8; 600 * BLOCK_SZ]), 600 * BLOCK_SZ);
+    drop(big);
+    let removed = root_inode.remove_tree("bulk", |_| true).unwrap();
+    assert_eq!(removed.len(), 602);
+    for inode in removed {
+        inode.release();
+    }
+    drop(bulk);
+    assert!(!root_inode.is_read_only());
+    assert_eq!(FileSystem::check(&efs, false), vec![]);
+    Ok(())
+}
+
+#[test]
+fn atime_transactions() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("atime_transactions")?;
+    let root_inode = FileSystem::root_inode(&efs);
+
+    // 只读不写的操作更新 atime 也经过事务提交，读取大量文件后仍然可以提交
+    let reads = root_inode.create_dir("reads").unwrap();
+    for i in 0..800 {
+        let file = reads.create(&format!("r-{}", i)).unwrap();
+        file.write_at(0, b"r");
+        // atime 早于 mtime，读取时需要更新
+        file.set_times(0, 1);
+    }
+    root_inode.sync();
+    let mut byte = [0u8; 1];
+    for i in 0..800 {
+        let file = reads.find(&format!("r-{}", i)).unwrap();
+        assert_eq!(file.read_at(0, &mut byte), 1);
+    }
+    root_inode.sync();
+    assert!(!root_inode.is_read_only());
+    assert!(root_inode.remove_tree("reads", |_| true).is_some());
+    Ok(())
+}
+
+#[test]
+fn free_counts() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("free_counts")?;
+    let root_inode = FileSystem::root_inode(&efs);
+    root_inode.create("filea").unwrap().write_at(0, b"data");
+
+    // 空闲计数随分配和释放更新，与位图保持一致
+    let before = root_inode.fs_stat();
+    assert_eq!((before.inodes, before.name_max), (4096, 255));
+    assert!(before.free_blocks < before.blocks);
+    let statfs = root_inode.create("statfs").unwrap();
+    statfs.write_at(0, &[1u8; 3 * BLOCK_SZ]);
+    drop(statfs);
+    let after = root_inode.fs_stat();
+    assert_eq!(after.free_inodes, before.free_inodes - 1);
+    assert_eq!(after.free_blocks, before.free_blocks - 3);
+    assert!(root_inode.delete("statfs"));
+    assert_eq!(root_inode.fs_stat(), before);
+    assert_eq!(efs.count_free(), (before.free_inodes, before.free_blocks));
+    Ok(())
+}
+
+#[test]
+fn xattrs() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("xattrs")?;
+    let root_inode = FileSystem::root_inode(&efs);
+    let before = root_inode.fs_stat();
+
+    // 扩展属性：所有属性放在一个属性块中，删除最后一个属性或者文件时回收属性块
+    use file_system::XattrError;
+    let attrs = root_inode.create("attrs").unwrap();
+    assert_eq!(attrs.get_xattr("user.type"), Err(XattrError::NotFound));
+    attrs.set_xattr("user.type", b"text/plain").unwrap();
+    attrs.set_xattr("user.label", b"red").unwrap();
+    attrs.set_xattr("user.type", b"text/markdown").unwrap();
+    assert_eq!(attrs.get_xattr("user.type").unwrap(), b"text/markdown");
+    assert_eq!(attrs.list_xattr(), vec!["user.label", "user.type"]);
+    assert_eq!(attrs.metadata().blocks, 1);
+    assert_eq!(
+        attrs.set_xattr("user.big", &[0u8; BLOCK_SZ]),
+        Err(XattrError::NoSpace)
+    );
+    assert_eq!(attrs.set_xattr("", b""), Err(XattrError::InvalidName));
+    assert_eq!(attrs.remove_xattr("user.big"), Err(XattrError::NotFound));
+    attrs.remove_xattr("user.label").unwrap();
+    attrs.remove_xattr("user.type").unwrap();
+    assert!(attrs.list_xattr().is_empty());
+    assert_eq!(attrs.metadata().blocks, 0);
+    attrs.set_xattr("user.type", b"text/plain").unwrap();
+    drop(attrs);
+    assert!(root_inode.delete("attrs"));
+    assert_eq!(root_inode.fs_stat(), before);
+    Ok(())
+}
+
+#[test]
+fn compression() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("compression")?;
+    let root_inode = FileSystem::root_inode(&efs);
+    let before = root_inode.fs_stat();
+
+    // 压缩文件：可压缩的簇占用更少的块，改写、截断和扩展后读出的内容不变
+    let text: Vec<u8> = (0..20000u32)
+        .flat_map(|i| format!("line {} of some text\n", i % 97).into_bytes())
+        .take(5 * 4096 + 1000)
+        .collect();
+    let packed = root_inode.create("packed").unwrap();
+    assert!(packed.set_compressed() && packed.is_compressed());
+    packed.write_at(0, &text);
+    assert_eq!(packed.get_file_size(), text.len() as u32);
+    assert!(packed.metadata().blocks < (text.len() / BLOCK_SZ) as u32 / 2);
+    let mut read_buf = vec![0u8; text.len() + 100];
+    assert_eq!(packed.read_at(0, &mut read_buf), text.len());
+    assert_eq!(&read_buf[..text.len()], &text[..]);
+    let mut expected = text.clone();
+    // 不可压缩的数据按原样存放
+    let noise: Vec<u8> = (0..6000u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+        .collect();
+    packed.write_at(3000, &noise);
+    expected[3000..9000].copy_from_slice(&noise);
+    assert_eq!(packed.read_at(0, &mut read_buf), expected.len());
+    assert_eq!(&read_buf[..expected.len()], &expected[..]);
+    packed.truncate(10000);
+    packed.truncate(12000);
+    expected.truncate(10000);
+    expected.resize(12000, 0);
+    packed.write_at(30000, b"tail");
+    expected.resize(30000, 0);
+    expected.extend_from_slice(b"tail");
+    let mut read_buf = vec![0u8; expected.len()];
+    assert_eq!(packed.read_at(0, &mut read_buf), expected.len());
+    assert_eq!(read_buf, expected);
+    assert!(!packed.set_compressed());
+    drop(packed);
+    let plain = root_inode.create("plain").unwrap();
+    plain.write_at(0, b"data");
+    assert!(!plain.set_compressed());
+    drop(plain);
+    assert_eq!(FileSystem::check(&efs, false), vec![]);
+    assert!(root_inode.delete("packed") && root_inode.delete("plain"));
+    assert_eq!(root_inode.fs_stat(), before);
+    Ok(())
+}
+
+#[test]
+fn journal_replay() -> std::io::Result<()> {
+    let (_guard, block_file, efs) = test_fs("journal_replay")?;
+    let root_inode = FileSystem::root_inode(&efs);
+    root_inode.create_dir("dir").unwrap();
+    let before = root_inode.fs_stat();
+
+    // 模拟提交后、写回前崩溃：日志区位于磁盘末尾，描述块之后是块的副本
+    let journal_start = 4096 - (1 + BLOCK_SZ / 4 - 2);
+    let home = journal_start - 1;
+    let mut header = [0u8; BLOCK_SZ];
+    header[0..4].copy_from_slice(&0x4a524e4cu32.to_le_bytes());
+    header[4..8].copy_from_slice(&1u32.to_le_bytes());
+    header[8..12].copy_from_slice(&(home as u32).to_le_bytes());
+    block_file.write_block(journal_start + 1, &[0x5a; BLOCK_SZ]);
+    block_file.write_block(journal_start, &header);
+    let efs = FileSystem::open(block_file.clone()).unwrap();
+    let mut block = [0u8; BLOCK_SZ];
+    block_file.read_block(home, &mut block);
+    assert_eq!(block, [0x5a; BLOCK_SZ]);
+    block_file.read_block(journal_start, &mut block);
+    assert_eq!(block, [0u8; BLOCK_SZ]);
+    let root_inode = FileSystem::root_inode(&efs);
+    assert!(root_inode.find("dir").is_some());
+    assert_eq!(root_inode.fs_stat(), before);
+    Ok(())
+}
+
+#[test]
+fn fsck() -> std::io::Result<()> {
+    let (_guard, _, efs) = test_fs("fsck")?;
+    let root_inode = FileSystem::root_inode(&efs);
+    // 带有哈希索引的目录
+    let many = root_inode.create_dir("many").unwrap();
+    for i in 0..200 {
+        many.create(&format!("n-{}", i)).unwrap();
+    }
+    drop(many);
+
+    // 一致性检查：注入悬空目录项和泄漏的块后能够发现并修复
+    use file_system::FsckProblem;
+    let tagged = root_inode.create("tagged").unwrap();
+    tagged.set_xattr("user.tag", b"fsck").unwrap();
+    drop(tagged);
+    assert_eq!(FileSystem::check(&efs, false), vec![]);
+    let fileg = root_inode.create("fileg").unwrap();
+    fileg.write_at(0, &[1u8; 3 * BLOCK_SZ]);
+    let fileg_id = fileg.get_inode_id();
+    efs.dealloc_inode(fileg_id);
+    let leaked = efs.alloc_data();
+    let problems = FileSystem::check(&efs, false);
+    assert!(problems.contains(&FsckProblem::DanglingEntry {
+        dir: 0,
+        name: String::from("fileg"),
+        inode_id: fileg_id,
+    }));
+    assert!(problems.contains(&FsckProblem::LeakedBlock { block_id: leaked }));
+    assert_eq!(problems.len(), 5);
+    assert_eq!(FileSystem::check(&efs, true), problems);
+    assert_eq!(FileSystem::check(&efs, false), vec![]);
+    assert_eq!(efs.free_counts(), Some(efs.count_free()));
+    assert!(root_inode.find("fileg").is_none());
+    assert!(root_inode.find("many").unwrap().find("n-0").is_some());
+    Ok(())
+}
+
+#[test]
+fn metadata_checksums() -> std::io::Result<()> {
+    let (_guard, block_file, efs) = test_fs("metadata_checksums")?;
+    let root_inode = FileSystem::root_inode(&efs);
+    root_inode.create_dir("dir").unwrap();
+    // 一个块存放 4 个 inode，让 csum 与根目录不在同一个块中
+    for i in 0..3 {
+        root_inode.create(&format!("file-{}", i));
+    }
+
+    // 元数据校验和：磁盘上损坏的 inode 块在第一次读取时被发现，内容读出为 0
+    use file_system::{metadata_errors, take_context_errors};
+    let csum = root_inode.create("csum").unwrap();
+    csum.write_at(0, b"checksum");
+    let (csum_block, _) = efs.get_disk_inode_pos(csum.get_inode_id());
+    assert_ne!(csum_block, efs.get_disk_inode_pos(0).0);
+    drop((csum, root_inode, efs));
+    let mut block = [0u8; BLOCK_SZ];
+    block_file.read_block(csum_block as usize, &mut block);
+    block[BLOCK_SZ - 1] ^= 0xff;
+    block_file.write_block(csum_block as usize, &block);
+    init_block_cache(4096);
+    let efs = FileSystem::open(block_file.clone()).unwrap();
+    let root_inode = FileSystem::root_inode(&efs);
+    let csum = root_inode.find("csum").unwrap();
+    assert_eq!(metadata_errors(), 0);
+    take_context_errors();
+    assert_eq!(csum.get_file_size(), 0);
+    assert_eq!(metadata_errors(), 1);
+    assert_eq!(take_context_errors(), 1);
+    // 之后每次读取损坏的块都记录到读取者名下，与损坏无关的读取不受影响
+    assert!(root_inode.find("dir").is_some());
+    assert_eq!(take_context_errors(), 0);
+    assert_eq!(csum.get_file_size(), 0);
+    assert_eq!(take_context_errors(), 1);
+    assert_eq!(metadata_errors(), 1);
+    drop((csum, root_inode, efs));
+
+    // 超级块的校验和不符时拒绝挂载，而不是 panic
+    block_file.read_block(0, &mut block);
+    let super_block = block;
+    block[4] ^= 0xff;
+    block_file.write_block(0, &block);
+    init_block_cache(4096);
+    assert!(FileSystem::open(block_file.clone()).is_none());
+    block_file.write_block(0, &super_block);
+
+    // 没有校验和的旧格式镜像仍然可以挂载，也不校验元数据
+    init_block_cache(4096);
+    block_file.read_block(0, &mut block);
+    block[0..4].copy_from_slice(&0x3b800006u32.to_le_bytes());
+    block_file.write_block(0, &block);
+    init_block_cache(4096);
+    let efs = FileSystem::open(block_file.clone()).unwrap();
+    let root_inode = FileSystem::root_inode(&efs);
+    assert!(root_inode.find("csum").unwrap().get_file_size() > 0);
+    assert_eq!(metadata_errors(), 0);
     Ok(())
 }
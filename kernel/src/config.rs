@@ -6,6 +6,12 @@ pub const USER_STACK_SIZE: usize = 4096 * 2;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
 /// 内核堆大小
 pub const KERNEL_HEAP_SIZE: usize = 0x100_0000;
+/// 文件系统块缓存的块数
+pub const BLOCK_CACHE_SIZE: usize = 1024;
+/// 线程的默认优先级，数值越大优先级越高
+pub const DEFAULT_PRIORITY: usize = 16;
+/// 线程可以设置的最高优先级
+pub const MAX_PRIORITY: usize = 31;
 
 /// page size : 4KB
 pub const PAGE_SIZE: usize = 0x1000;
@@ -26,6 +32,7 @@ pub const MMIO: &[(usize, usize)] = &[
     (0x10000000, 0x9000),  // VIRT_UART0 with GPU  in virt machine
 ];
 
+pub const VIRT_RTC: usize = 0x10_1000;
 pub const VIRT_PLIC: usize = 0xC00_0000;
 pub const VIRT_UART: usize = 0x1000_0000;
 #[allow(unused)]
@@ -1,14 +1,17 @@
-use super::File;
+use super::{FS_COMPR_FL, File, S_IFDIR, S_IFLNK, S_IFREG, Stat, StatFs};
+use crate::config::BLOCK_CACHE_SIZE;
+use crate::drivers::BLOCK_DEVICE;
 use crate::mm::UserBuffer;
 use crate::sync::UPIntrFreeCell;
-use crate::{
-    drivers::BLOCK_DEVICE,
-    fs::{DIR, LNK, REG},
-};
+use crate::task::{Credentials, current_task};
+use crate::timer::{get_time_ms, get_wall_time_sec};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use bitflags::*;
-use file_system::{FileSystem, Inode};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use file_system::{
+    BLOCK_SZ, DiskInodeType, FileSystem, FsStat, Inode, init_block_cache, set_error_context,
+};
 use lazy_static::*;
 
 pub struct OSInode {
@@ -48,32 +51,126 @@ impl OSInode {
 
 lazy_static! {
     pub static ref ROOT_INODE: Arc<Inode> = {
-        let efs = FileSystem::open(BLOCK_DEVICE.clone());
-        Arc::new(FileSystem::root_inode(&efs))
+        init_block_cache(BLOCK_CACHE_SIZE);
+        let efs = FileSystem::open(BLOCK_DEVICE.clone())
+            .expect("[kernel] invalid or corrupted file system image");
+        efs.set_clock(get_wall_time_sec);
+        set_error_context(current_task_id);
+        FileSystem::root_inode(&efs)
+    };
+}
+
+/// 读到损坏的元数据时按线程记录，每个系统调用只看到自己读到的错误
+fn current_task_id() -> usize {
+    current_task().map_or(0, |task| Arc::as_ptr(&task) as usize)
+}
+
+/// 定期刷新文件系统的间隔，单位为毫秒
+const FLUSH_INTERVAL_MS: usize = 5000;
+
+/// 提交文件系统中所有未提交的修改，并写回块缓存中修改过的块
+pub fn sync_fs() {
+    ROOT_INODE.sync();
+}
+
+/// 文件系统是否已经不再提交修改，发现损坏的元数据或者一次提交放不进日志区之后为真
+pub fn fs_read_only() -> bool {
+    ROOT_INODE.is_read_only()
+}
+
+/// 距离上次刷新超过 `FLUSH_INTERVAL_MS` 时刷新文件系统，在系统调用返回前调用
+///
+/// 有修改操作正在进行时跳过这次刷新，留给之后的系统调用
+pub fn flush_if_due() {
+    static LAST_FLUSH: AtomicUsize = AtomicUsize::new(0);
+    let now = get_time_ms();
+    if now - LAST_FLUSH.load(Ordering::Relaxed) >= FLUSH_INTERVAL_MS && ROOT_INODE.try_sync() {
+        LAST_FLUSH.store(now, Ordering::Relaxed);
+    }
+}
+
+/// 路径解析时最多跟随的符号链接数，超过时认为出现了循环
+const MAX_SYMLINK_DEPTH: usize = 8;
+
+bitflags! {
+    /// 访问权限，取值与 `access(2)` 的 mode 参数相同
+    pub struct Access: u16 {
+        const READ = 4;
+        const WRITE = 2;
+        const EXEC = 1;
+    }
+}
+
+/// 当前进程的身份，内核初始化阶段（如加载 initproc）按 root 处理
+pub fn current_cred() -> Credentials {
+    current_task()
+        .and_then(|task| task.process.upgrade())
+        .map(|process| process.inner_exclusive_access().cred)
+        .unwrap_or_default()
+}
+
+/// 按所有者、组、其他用户的顺序选择权限位，检查 `cred` 能否以 `access` 访问 `inode`
+pub fn check_access(inode: &Inode, cred: &Credentials, access: Access) -> bool {
+    let metadata = inode.metadata();
+    if cred.euid == 0 {
+        // root 不受读写权限限制，但执行普通文件至少需要一个执行位
+        return !access.contains(Access::EXEC)
+            || metadata.type_ == DiskInodeType::Directory
+            || metadata.mode & 0o111 != 0;
+    }
+    let granted = if cred.euid == metadata.uid {
+        metadata.mode >> 6
+    } else if cred.egid == metadata.gid {
+        metadata.mode >> 3
+    } else {
+        metadata.mode
     };
+    Access::from_bits_truncate(granted & 0o7).contains(access)
 }
 
+/// 路径上的每个目录都需要搜索权限，否则返回 None
 pub fn find_inode(path: &str) -> Option<Arc<Inode>> {
-    // println!("find_inode: {}", path);
-    let root_inode = ROOT_INODE.clone();
-
-    let componects: Vec<&str> = path.split('/').collect();
-    // println!("split componects: {:?}", componects);
-
-    componects.into_iter().fold(Some(root_inode), |res, name| {
-        if let Some(node) = res {
-            if !name.is_empty() {
-                // println!("  ↳ Looking up file/dir: {:?}", name);
-                node.find(name)
-            } else {
-                // println!("  ↳ Skipping empty component");
-                Some(node)
+    lookup(ROOT_INODE.clone(), path, true, &current_cred(), &mut 0)
+}
+
+/// 与 `find_inode` 相同，但路径的最后一个分量是符号链接时不跟随，返回链接本身
+pub fn find_inode_nofollow(path: &str) -> Option<Arc<Inode>> {
+    lookup(ROOT_INODE.clone(), path, false, &current_cred(), &mut 0)
+}
+
+/// 从目录 `start` 开始逐个分量解析 `path`，`depth` 记录已经跟随的符号链接数
+fn lookup(
+    start: Arc<Inode>,
+    path: &str,
+    follow_last: bool,
+    cred: &Credentials,
+    depth: &mut usize,
+) -> Option<Arc<Inode>> {
+    // 绝对路径总是从根目录开始
+    let mut node = if path.starts_with('/') {
+        ROOT_INODE.clone()
+    } else {
+        start
+    };
+    let components: Vec<&str> = path.split('/').filter(|name| !name.is_empty()).collect();
+    for (i, name) in components.iter().enumerate() {
+        if !node.is_dir() || !check_access(&node, cred, Access::EXEC) {
+            return None;
+        }
+        let mut next = node.find(name)?;
+        let is_last = i + 1 == components.len();
+        if next.is_symlink() && (follow_last || !is_last) {
+            *depth += 1;
+            if *depth > MAX_SYMLINK_DEPTH {
+                return None;
             }
-        } else {
-            // println!("  ↳ Skipping (previous lookup failed)");
-            None
+            let target = next.read_link()?;
+            // 相对路径的链接目标相对于链接所在的目录解析
+            next = lookup(node, target.as_str(), true, cred, depth)?;
         }
-    })
+        node = next;
+    }
+    Some(node)
 }
 
 /*
@@ -107,32 +204,55 @@ impl OpenFlags {
             (true, true)
         }
     }
+
+    /// 以写方式打开、创建或者截断文件都会修改文件系统
+    pub fn modifies(&self) -> bool {
+        self.intersects(Self::WRONLY | Self::RDWR | Self::CREATE | Self::TRUNC)
+    }
 }
 
+/// 按 `flags` 打开文件，没有相应的读写权限或者以写方式打开目录时返回 None
 pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
     // println!("open_file: {}", name);
+    let cred = current_cred();
     let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = find_inode(name) {
-            // clear size
-            inode.clear();
-            Some(Arc::new(OSInode::new(readable, writable, inode)))
-        } else {
-            // create file
-            let (parent_path, target) = name.rsplit_once('/').unwrap();
-            let parent_inode = find_inode(parent_path).unwrap();
-            parent_inode
-                .create(target)
-                .map(|inode| Arc::new(OSInode::new(readable, writable, inode)))
+    let mut access = Access::empty();
+    access.set(Access::READ, readable);
+    access.set(Access::WRITE, writable || flags.contains(OpenFlags::TRUNC));
+    let Some(inode) = find_inode(name) else {
+        if !flags.contains(OpenFlags::CREATE) {
+            return None;
         }
-    } else {
-        find_inode(name).map(|inode| {
-            if flags.contains(OpenFlags::TRUNC) {
-                inode.clear();
-            }
+        // create file
+        let (parent_path, target) = name.rsplit_once('/').unwrap();
+        let parent_inode = find_inode(parent_path)?;
+        if !check_access(&parent_inode, &cred, Access::WRITE | Access::EXEC) {
+            return None;
+        }
+        return parent_inode.create(target).map(|inode| {
+            inode.set_owner(cred.euid, cred.egid);
             Arc::new(OSInode::new(readable, writable, inode))
-        })
+        });
+    };
+    if flags.modifies() && inode.is_dir() {
+        return None;
+    }
+    if !check_access(&inode, &cred, access) {
+        return None;
+    }
+    if flags.contains(OpenFlags::TRUNC) && inode.is_file() {
+        inode.truncate(0);
+    }
+    Some(Arc::new(OSInode::new(readable, writable, inode)))
+}
+
+/// 打开要执行的程序，只需要执行权限
+pub fn open_exec(name: &str) -> Option<Arc<OSInode>> {
+    let inode = find_inode(name)?;
+    if !inode.is_file() || !check_access(&inode, &current_cred(), Access::EXEC) {
+        return None;
     }
+    Some(Arc::new(OSInode::new(true, false, inode)))
 }
 
 impl File for OSInode {
@@ -160,37 +280,95 @@ impl File for OSInode {
         let mut total_write_size = 0usize;
         for slice in buf.buffers.iter() {
             let write_size = inner.inode.write_at(inner.offset, *slice);
-            assert_eq!(write_size, slice.len());
             inner.offset += write_size;
             total_write_size += write_size;
+            // 到达文件的最大大小，返回写入的部分
+            if write_size < slice.len() {
+                break;
+            }
         }
         total_write_size
     }
 
-    fn get_offset(&self) -> usize {
-        self.inner.exclusive_access().offset
+    fn stat(&self) -> Stat {
+        Stat::from(self.inner.exclusive_access().inode.as_ref())
     }
 
-    fn set_offset(&self, offset: usize) {
-        self.inner.exclusive_access().offset = offset;
+    fn truncate(&self, len: u32) -> bool {
+        let inner = self.inner.exclusive_access();
+        if !inner.inode.is_file() {
+            return false;
+        }
+        inner.inode.truncate(len)
     }
 
-    fn get_file_size(&self) -> usize {
-        self.inner.exclusive_access().inode.get_file_size() as usize
+    fn statfs(&self) -> Option<StatFs> {
+        Some(StatFs::from(self.inner.exclusive_access().inode.fs_stat()))
     }
 
-    fn get_inode_id(&self) -> usize {
-        self.inner.exclusive_access().inode.get_inode_id() as usize
+    fn flags(&self) -> Option<u32> {
+        let inner = self.inner.exclusive_access();
+        Some(if inner.inode.is_compressed() {
+            FS_COMPR_FL
+        } else {
+            0
+        })
     }
 
-    fn get_mode(&self) -> usize {
-        let inode = &self.inner.exclusive_access().inode;
-        if inode.is_file() {
-            REG
-        } else if inode.is_dir() {
-            DIR
-        } else {
-            LNK
+    fn read_only(&self) -> bool {
+        self.inner.exclusive_access().inode.is_read_only()
+    }
+
+    /// 只支持压缩标志，只能在文件还没有数据块时打开，打开后不能关闭
+    fn set_flags(&self, flags: u32) -> bool {
+        let inner = self.inner.exclusive_access();
+        match flags {
+            0 => !inner.inode.is_compressed(),
+            FS_COMPR_FL => inner.inode.is_compressed() || inner.inode.set_compressed(),
+            _ => false,
+        }
+    }
+}
+
+impl From<&Inode> for Stat {
+    fn from(inode: &Inode) -> Self {
+        let metadata = inode.metadata();
+        let file_type = match metadata.type_ {
+            DiskInodeType::File => S_IFREG,
+            DiskInodeType::Directory => S_IFDIR,
+            DiskInodeType::Symlink => S_IFLNK,
+        };
+        Self {
+            ino: metadata.inode_id as u64,
+            mode: file_type | metadata.mode as u32,
+            nlink: metadata.nlink as u32,
+            uid: metadata.uid,
+            gid: metadata.gid,
+            size: metadata.size as i64,
+            blksize: BLOCK_SZ as i32,
+            // st_blocks 以 512 字节为单位
+            blocks: (metadata.blocks as usize * BLOCK_SZ / 512) as i64,
+            atime: metadata.atime as i64,
+            mtime: metadata.mtime as i64,
+            ctime: metadata.ctime as i64,
+            ..Stat::default()
+        }
+    }
+}
+
+impl From<FsStat> for StatFs {
+    fn from(fs_stat: FsStat) -> Self {
+        Self {
+            bsize: BLOCK_SZ as i64,
+            blocks: fs_stat.blocks as u64,
+            bfree: fs_stat.free_blocks as u64,
+            // 没有为 root 保留的块
+            bavail: fs_stat.free_blocks as u64,
+            files: fs_stat.inodes as u64,
+            ffree: fs_stat.free_inodes as u64,
+            namelen: fs_stat.name_max as i64,
+            frsize: BLOCK_SZ as i64,
+            ..StatFs::default()
         }
     }
 }
@@ -3,33 +3,61 @@ mod pipe;
 mod stdio;
 
 use crate::mm::UserBuffer;
-use alloc::sync::Arc;
 
-const CHR: usize = 0;
-const REG: usize = 1;
-const DIR: usize = 2;
-const LNK: usize = 3;
+/// 文件类型，即 `st_mode` 的高 4 位
+const S_IFCHR: u32 = 0o020000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+/// 属性标志中的压缩标志，与 Linux 的 `FS_COMPR_FL` 相同
+const FS_COMPR_FL: u32 = 0x4;
 
 const EOT: char = '\x04';
 const LF: char = '\x0a';
 const CR: char = '\x0d';
 
+/// 与 Linux (asm-generic) 的 `struct stat` 布局相同
+#[repr(C)]
+#[derive(Default)]
 pub struct Stat {
-    pub ino: u32,
+    pub dev: u64,
+    pub ino: u64,
     pub mode: u32,
-    pub off: u32,
-    pub size: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u64,
+    __pad1: u64,
+    pub size: i64,
+    pub blksize: i32,
+    __pad2: i32,
+    pub blocks: i64,
+    pub atime: i64,
+    pub atime_nsec: u64,
+    pub mtime: i64,
+    pub mtime_nsec: u64,
+    pub ctime: i64,
+    pub ctime_nsec: u64,
+    __unused: [u32; 2],
 }
 
-impl From<Arc<dyn File + Send + Sync>> for Stat {
-    fn from(file: Arc<dyn File + Send + Sync>) -> Self {
-        Self {
-            ino: file.get_inode_id() as u32,
-            mode: file.get_mode() as u32,
-            off: file.get_offset() as u32,
-            size: file.get_file_size() as u32,
-        }
-    }
+/// 与 Linux (asm-generic) 的 `struct statfs` 布局相同
+#[repr(C)]
+#[derive(Default)]
+pub struct StatFs {
+    pub type_: i64,
+    pub bsize: i64,
+    pub blocks: u64,
+    pub bfree: u64,
+    pub bavail: u64,
+    pub files: u64,
+    pub ffree: u64,
+    pub fsid: [i32; 2],
+    pub namelen: i64,
+    pub frsize: i64,
+    pub flags: i64,
+    __spare: [i64; 4],
 }
 
 pub trait File: Send + Sync {
@@ -38,21 +66,44 @@ pub trait File: Send + Sync {
     fn read(&self, buf: UserBuffer) -> usize;
     fn write(&self, buf: UserBuffer) -> usize;
 
-    fn get_offset(&self) -> usize {
-        0
+    /// 默认按字符设备处理，如标准输入输出和管道
+    fn stat(&self) -> Stat {
+        Stat {
+            mode: S_IFCHR | 0o666,
+            nlink: 1,
+            ..Stat::default()
+        }
     }
-    fn set_offset(&self, _offset: usize) {}
-    fn get_file_size(&self) -> usize {
-        0
+
+    /// 把文件截断或扩展到 `len` 字节，不是普通文件或者超出文件的最大大小时返回 false
+    fn truncate(&self, _len: u32) -> bool {
+        false
     }
-    fn get_inode_id(&self) -> usize {
-        0
+
+    /// 文件所在文件系统的使用情况，不属于任何文件系统时返回 None
+    fn statfs(&self) -> Option<StatFs> {
+        None
     }
-    fn get_mode(&self) -> usize {
-        CHR
+
+    /// 文件所在的文件系统已经不再提交修改，这时写入和修改文件返回 EROFS
+    fn read_only(&self) -> bool {
+        false
+    }
+
+    /// 文件的属性标志，不属于任何文件系统时返回 None
+    fn flags(&self) -> Option<u32> {
+        None
+    }
+
+    /// 设置文件的属性标志，不支持的标志或者无法设置时返回 false
+    fn set_flags(&self, _flags: u32) -> bool {
+        false
     }
 }
 
-pub use inode::{OpenFlags, ROOT_INODE, find_inode, open_file};
+pub use inode::{
+    Access, OpenFlags, ROOT_INODE, check_access, current_cred, find_inode, find_inode_nofollow,
+    flush_if_due, fs_read_only, open_exec, open_file, sync_fs,
+};
 pub use pipe::make_pipe;
 pub use stdio::{Stdin, Stdout};
@@ -0,0 +1,52 @@
+use super::UPIntrFreeCell;
+use crate::task::{TaskControlBlock, block_current_and_run_next, current_task, wakeup_task};
+use alloc::collections::vec_deque::VecDeque;
+use alloc::sync::Arc;
+
+/// 可重复使用的 N 方屏障
+pub struct Barrier {
+    pub inner: UPIntrFreeCell<BarrierInner>,
+}
+
+pub struct BarrierInner {
+    /// 需要到达屏障的线程数
+    pub parties: usize,
+    /// 本轮已经到达的线程数
+    pub arrived: usize,
+    pub wait_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Barrier {
+    pub fn new(parties: usize) -> Self {
+        Self {
+            inner: unsafe {
+                UPIntrFreeCell::new(BarrierInner {
+                    parties,
+                    arrived: 0,
+                    wait_queue: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    /// 等待所有线程到达屏障
+    ///
+    /// 最后一个到达的线程负责唤醒其他线程并返回 true，其余线程返回 false
+    pub fn wait(&self) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        inner.arrived += 1;
+        if inner.arrived >= inner.parties {
+            // 开始新的一轮，屏障可以被重复使用
+            inner.arrived = 0;
+            while let Some(task) = inner.wait_queue.pop_front() {
+                wakeup_task(task);
+            }
+            true
+        } else {
+            inner.wait_queue.push_back(current_task().unwrap());
+            drop(inner);
+            block_current_and_run_next();
+            false
+        }
+    }
+}
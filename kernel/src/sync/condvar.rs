@@ -1,4 +1,4 @@
-use super::{Mutex, UPIntrFreeCell};
+use super::{Mutex, UPIntrFreeCell, block_with_timeout, finish_timed_wait};
 use crate::task::{TaskContext, TaskControlBlock, block_current_and_run_next, wakeup_task};
 use crate::task::{block_current_task, current_task};
 use alloc::collections::vec_deque::VecDeque;
@@ -30,6 +30,19 @@ impl Condvar {
         }
     }
 
+    /// 有线程在等待时返回 true，此时不能销毁
+    pub fn is_busy(&self) -> bool {
+        !self.inner.exclusive_access().wait_queue.is_empty()
+    }
+
+    /// 唤醒所有等待在该条件变量上的线程
+    pub fn broadcast(&self) {
+        let mut inner = self.inner.exclusive_access();
+        while let Some(task) = inner.wait_queue.pop_front() {
+            wakeup_task(task);
+        }
+    }
+
     /*
     pub fn wait(&self, mutex: Arc<dyn Mutex>) {
         mutex.unlock();
@@ -47,12 +60,36 @@ impl Condvar {
         });
         block_current_task()
     }
-    pub fn wait_with_mutex(&self, mutex: Arc<dyn Mutex>) {
-        mutex.unlock();
+    /// 释放互斥锁并等待，被唤醒后重新获得互斥锁；当前线程未持有该锁时返回 false
+    pub fn wait_with_mutex(&self, mutex: Arc<dyn Mutex>) -> bool {
+        if !mutex.unlock() {
+            return false;
+        }
         self.inner.exclusive_session(|inner| {
             inner.wait_queue.push_back(current_task().unwrap());
         });
         block_current_and_run_next();
         mutex.lock();
+        true
+    }
+
+    /// 与 `wait_with_mutex` 相同，但最多等待 `timeout_ms` 毫秒
+    ///
+    /// 无论是否超时，返回前都会重新获得互斥锁；超时返回 `Some(false)`，
+    /// 当前线程未持有该锁时返回 `None`
+    pub fn wait_timeout(&self, mutex: Arc<dyn Mutex>, timeout_ms: usize) -> Option<bool> {
+        if !mutex.unlock() {
+            return None;
+        }
+        let task = current_task().unwrap();
+        self.inner.exclusive_session(|inner| {
+            inner.wait_queue.push_back(Arc::clone(&task));
+        });
+        block_with_timeout(&task, timeout_ms);
+        let timed_out = self
+            .inner
+            .exclusive_session(|inner| finish_timed_wait(&mut inner.wait_queue, &task));
+        mutex.lock();
+        Some(!timed_out)
     }
 }
@@ -1,9 +1,48 @@
+mod barrier;
 mod condvar;
+mod mqueue;
 mod mutex;
+mod named;
+mod rwlock;
 mod semaphore;
 mod up;
 
+pub use barrier::Barrier;
 pub use condvar::Condvar;
-pub use mutex::{Mutex, MutexBlocking, MutexSpin};
+pub use mqueue::{MQ_PRIO_MAX, MessageQueue, MqAttr, MqDescriptor};
+pub use mutex::{Mutex, MutexBlocking, MutexBlockingInner, MutexSpin};
+pub use named::{
+    IpcFlags, MESSAGE_QUEUES, NAMED_SEMAPHORES, NamedOpenError, open_named, unlink_named,
+};
+pub use rwlock::RwLock;
 pub use semaphore::Semaphore;
 pub use up::{UPIntrFreeCell, UPIntrRefMut};
+
+use crate::task::{TaskControlBlock, block_current_task, schedule};
+use crate::timer::{add_timer, get_time_ms, remove_timer};
+use alloc::collections::vec_deque::VecDeque;
+use alloc::sync::Arc;
+
+/// 阻塞当前任务，直到被同步对象唤醒或者等待超时
+///
+/// 先进入阻塞状态再加入定时器，定时器不会在任务阻塞之前到期而被忽略
+fn block_with_timeout(task: &Arc<TaskControlBlock>, timeout_ms: usize) {
+    let task_cx_ptr = block_current_task();
+    add_timer(get_time_ms() + timeout_ms, Arc::clone(task));
+    schedule(task_cx_ptr);
+}
+
+/// 带超时的等待返回后调用：如果任务仍在等待队列中，说明是被定时器唤醒的，
+/// 将其移出队列并返回 true；否则任务已被同步对象唤醒，撤销其定时器并返回 false
+fn finish_timed_wait(
+    wait_queue: &mut VecDeque<Arc<TaskControlBlock>>,
+    task: &Arc<TaskControlBlock>,
+) -> bool {
+    if let Some(idx) = wait_queue.iter().position(|t| Arc::ptr_eq(t, task)) {
+        wait_queue.remove(idx);
+        true
+    } else {
+        remove_timer(Arc::clone(task));
+        false
+    }
+}
@@ -0,0 +1,134 @@
+use super::UPIntrFreeCell;
+use crate::task::{TaskControlBlock, block_current_and_run_next, current_task, wakeup_task};
+use alloc::collections::vec_deque::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// 未指定属性时消息队列的默认容量
+pub const MQ_DEFAULT_MAX_MSG: usize = 10;
+/// 未指定属性时单条消息的默认最大长度
+pub const MQ_DEFAULT_MSG_SIZE: usize = 256;
+/// 消息队列容量的上限
+pub const MQ_MAX_MSG: usize = 64;
+/// 单条消息长度的上限
+pub const MQ_MAX_MSG_SIZE: usize = 4096;
+/// 消息优先级必须小于该值
+pub const MQ_PRIO_MAX: usize = 32;
+
+/// 创建消息队列时由用户传入的属性
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MqAttr {
+    /// 队列中最多容纳的消息数
+    pub max_msg: usize,
+    /// 单条消息的最大长度
+    pub msg_size: usize,
+}
+
+impl Default for MqAttr {
+    fn default() -> Self {
+        Self {
+            max_msg: MQ_DEFAULT_MAX_MSG,
+            msg_size: MQ_DEFAULT_MSG_SIZE,
+        }
+    }
+}
+
+impl MqAttr {
+    pub fn is_valid(&self) -> bool {
+        (1..=MQ_MAX_MSG).contains(&self.max_msg) && (1..=MQ_MAX_MSG_SIZE).contains(&self.msg_size)
+    }
+}
+
+struct Message {
+    priority: usize,
+    data: Vec<u8>,
+}
+
+/// POSIX 风格的消息队列，优先级高的消息先被接收，同优先级的消息先进先出
+pub struct MessageQueue {
+    pub attr: MqAttr,
+    inner: UPIntrFreeCell<MessageQueueInner>,
+}
+
+pub struct MessageQueueInner {
+    messages: VecDeque<Message>,
+    /// 因队列为空而等待接收的线程
+    recv_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// 因队列已满而等待发送的线程
+    send_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl MessageQueue {
+    pub fn new(attr: MqAttr) -> Self {
+        Self {
+            attr,
+            inner: unsafe {
+                UPIntrFreeCell::new(MessageQueueInner {
+                    messages: VecDeque::new(),
+                    recv_queue: VecDeque::new(),
+                    send_queue: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    /// 发送一条消息，队列已满时阻塞；`nonblock` 为 true 时队列已满直接返回 false
+    pub fn send(&self, data: Vec<u8>, priority: usize, nonblock: bool) -> bool {
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if inner.messages.len() < self.attr.max_msg {
+                // 插入到所有优先级不低于它的消息之后
+                let pos = inner
+                    .messages
+                    .iter()
+                    .position(|msg| msg.priority < priority)
+                    .unwrap_or(inner.messages.len());
+                inner.messages.insert(pos, Message { priority, data });
+                if let Some(task) = inner.recv_queue.pop_front() {
+                    wakeup_task(task);
+                }
+                return true;
+            }
+            if nonblock {
+                return false;
+            }
+            inner.send_queue.push_back(current_task().unwrap());
+            drop(inner);
+            block_current_and_run_next();
+        }
+    }
+
+    /// 接收优先级最高的消息，队列为空时阻塞；`nonblock` 为 true 时队列为空直接返回 None
+    pub fn receive(&self, nonblock: bool) -> Option<(Vec<u8>, usize)> {
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if let Some(msg) = inner.messages.pop_front() {
+                if let Some(task) = inner.send_queue.pop_front() {
+                    wakeup_task(task);
+                }
+                return Some((msg.data, msg.priority));
+            }
+            if nonblock {
+                return None;
+            }
+            inner.recv_queue.push_back(current_task().unwrap());
+            drop(inner);
+            block_current_and_run_next();
+        }
+    }
+
+    /// 进程退出时将其线程从等待队列中移除
+    pub fn remove_waiter(&self, task: &Arc<TaskControlBlock>) {
+        let mut inner = self.inner.exclusive_access();
+        inner.recv_queue.retain(|t| !Arc::ptr_eq(t, task));
+        inner.send_queue.retain(|t| !Arc::ptr_eq(t, task));
+    }
+}
+
+/// 进程打开的消息队列描述符
+pub struct MqDescriptor {
+    pub queue: Arc<MessageQueue>,
+    /// 以非阻塞模式打开
+    pub nonblock: bool,
+}
@@ -1,90 +1,265 @@
 use alloc::collections::vec_deque::VecDeque;
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
 
 use crate::task::{
     TaskControlBlock, block_current_and_run_next, current_task, suspend_current_and_run_next,
     wakeup_task,
 };
+use crate::timer::get_time_ms;
 
-use super::UPIntrFreeCell;
+use super::{UPIntrFreeCell, UPIntrRefMut, block_with_timeout, finish_timed_wait};
 
 pub trait Mutex: Sync + Send {
     fn lock(&self);
-    fn unlock(&self);
+    /// 解锁，当前线程并未持有该锁时返回 false
+    fn unlock(&self) -> bool;
+    /// 尝试加锁，锁已被占用时立即返回 false
+    fn try_lock(&self) -> bool;
+    /// 加锁，若在 `timeout_ms` 毫秒内未能获得锁则返回 false
+    fn lock_timeout(&self, timeout_ms: usize) -> bool;
+    /// 锁被占用或者有线程在等待时返回 true，此时不能销毁
+    fn is_busy(&self) -> bool;
+    /// 持有锁的线程退出时调用，如果锁由 `task` 持有则将其释放
+    fn release_owned_by(&self, task: &Arc<TaskControlBlock>);
 }
 
 pub struct MutexSpin {
-    locked: UPIntrFreeCell<bool>,
+    /// 当前持有锁的线程，为 None 时锁空闲
+    owner: UPIntrFreeCell<Option<Arc<TaskControlBlock>>>,
 }
 
 impl MutexSpin {
     pub fn new() -> Self {
         Self {
-            locked: unsafe { UPIntrFreeCell::new(false) },
+            owner: unsafe { UPIntrFreeCell::new(None) },
         }
     }
 }
 
 impl Mutex for MutexSpin {
     fn lock(&self) {
+        while !self.try_lock() {
+            suspend_current_and_run_next();
+        }
+    }
+    fn unlock(&self) -> bool {
+        let task = current_task().unwrap();
+        let mut owner = self.owner.exclusive_access();
+        if !owner
+            .as_ref()
+            .is_some_and(|owner| Arc::ptr_eq(owner, &task))
+        {
+            return false;
+        }
+        *owner = None;
+        true
+    }
+    fn try_lock(&self) -> bool {
+        let mut owner = self.owner.exclusive_access();
+        if owner.is_some() {
+            false
+        } else {
+            *owner = current_task();
+            true
+        }
+    }
+    fn lock_timeout(&self, timeout_ms: usize) -> bool {
+        let expire_ms = get_time_ms() + timeout_ms;
         loop {
-            let mut locked = self.locked.exclusive_access();
-            if *locked {
-                drop(locked);
-                suspend_current_and_run_next();
-                continue;
-            } else {
-                *locked = true;
-                return;
+            if self.try_lock() {
+                return true;
             }
+            if get_time_ms() >= expire_ms {
+                return false;
+            }
+            suspend_current_and_run_next();
         }
     }
-    fn unlock(&self) {
-        let mut locked = self.locked.exclusive_access();
-        *locked = false;
+    fn is_busy(&self) -> bool {
+        self.owner.exclusive_access().is_some()
+    }
+    fn release_owned_by(&self, task: &Arc<TaskControlBlock>) {
+        let mut owner = self.owner.exclusive_access();
+        if owner.as_ref().is_some_and(|owner| Arc::ptr_eq(owner, task)) {
+            *owner = None;
+        }
     }
 }
 
+/// 优先级继承沿持有者链传递的最大深度，死锁形成环路时也能停下来
+const PRIORITY_CHAIN_DEPTH: usize = 8;
+
 pub struct MutexBlocking {
-    inner: UPIntrFreeCell<MutexBlockingInner>,
+    /// 等待者通过 `blocked_on` 引用这里，以便沿持有者链传递优先级
+    inner: Arc<UPIntrFreeCell<MutexBlockingInner>>,
 }
 
 pub struct MutexBlockingInner {
     locked: bool,
+    /// 当前持有锁的线程
+    owner: Option<Arc<TaskControlBlock>>,
     wait_queue: VecDeque<Arc<TaskControlBlock>>,
 }
 
 impl MutexBlocking {
     pub fn new() -> Self {
         Self {
-            inner: unsafe {
+            inner: Arc::new(unsafe {
                 UPIntrFreeCell::new(MutexBlockingInner {
                     locked: false,
+                    owner: None,
                     wait_queue: VecDeque::new(),
                 })
-            },
+            }),
         }
     }
+
+    /// 将当前任务加入等待队列并提升持有者的优先级，避免优先级反转
+    fn enqueue_waiter(
+        &self,
+        mut mutex_inner: UPIntrRefMut<'_, MutexBlockingInner>,
+        task: &Arc<TaskControlBlock>,
+    ) {
+        task.inner_exclusive_access().blocked_on = Some(Arc::downgrade(&self.inner));
+        mutex_inner.wait_queue.push_back(Arc::clone(task));
+        let next = mutex_inner.update_inherited_priority();
+        drop(mutex_inner);
+        propagate_priority(next);
+    }
 }
+
+impl MutexBlockingInner {
+    /// 用于在持有者的 `inherited_priority` 中区分不同的锁
+    fn key(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// 将持有者从本锁继承的优先级更新为等待者中的最高有效优先级，
+    /// 返回持有者正在等待的锁，持有者的有效优先级变化后需要继续更新那把锁
+    fn update_inherited_priority(&self) -> Option<Arc<UPIntrFreeCell<MutexBlockingInner>>> {
+        let owner = self.owner.as_ref()?;
+        let max_waiter_priority = self
+            .wait_queue
+            .iter()
+            .map(|task| task.inner_exclusive_access().effective_priority())
+            .max();
+        let mut owner_inner = owner.inner_exclusive_access();
+        match max_waiter_priority {
+            Some(priority) => {
+                owner_inner.inherited_priority.insert(self.key(), priority);
+            }
+            None => {
+                owner_inner.inherited_priority.remove(&self.key());
+            }
+        }
+        owner_inner.blocked_on.as_ref().and_then(Weak::upgrade)
+    }
+
+    /// 释放锁：有等待者时直接把所有权交给队首的线程
+    fn release(&mut self) {
+        if let Some(owner) = self.owner.take() {
+            // 恢复原持有者的优先级
+            owner
+                .inner_exclusive_access()
+                .inherited_priority
+                .remove(&self.key());
+        }
+        if let Some(waking_task) = self.wait_queue.pop_front() {
+            waking_task.inner_exclusive_access().blocked_on = None;
+            self.owner = Some(Arc::clone(&waking_task));
+            // 新的持有者继续继承剩余等待者的优先级，它没有在等待其他锁，不需要继续传递
+            self.update_inherited_priority();
+            wakeup_task(waking_task);
+        } else {
+            self.locked = false;
+        }
+    }
+
+    fn is_owned_by(&self, task: &Arc<TaskControlBlock>) -> bool {
+        self.owner
+            .as_ref()
+            .is_some_and(|owner| Arc::ptr_eq(owner, task))
+    }
+}
+
+/// 持有者的有效优先级变化后，沿着“持有者正在等待的锁”继续更新后面的持有者
+///
+/// 每次只访问一把锁，链上出现环路时不会重复借用同一把锁
+fn propagate_priority(mut next: Option<Arc<UPIntrFreeCell<MutexBlockingInner>>>) {
+    for _ in 0..PRIORITY_CHAIN_DEPTH {
+        let Some(mutex) = next else {
+            return;
+        };
+        next = mutex.exclusive_access().update_inherited_priority();
+    }
+}
+
 impl Mutex for MutexBlocking {
     fn lock(&self) {
+        let task = current_task().unwrap();
         let mut mutex_inner = self.inner.exclusive_access();
         if mutex_inner.locked {
-            mutex_inner.wait_queue.push_back(current_task().unwrap());
-            drop(mutex_inner);
+            self.enqueue_waiter(mutex_inner, &task);
             block_current_and_run_next();
         } else {
             mutex_inner.locked = true;
+            mutex_inner.owner = Some(task);
         }
     }
 
-    fn unlock(&self) {
+    fn unlock(&self) -> bool {
+        let task = current_task().unwrap();
         let mut mutex_inner = self.inner.exclusive_access();
-        assert!(mutex_inner.locked);
-        if let Some(waking_task) = mutex_inner.wait_queue.pop_front() {
-            wakeup_task(waking_task);
+        if !mutex_inner.is_owned_by(&task) {
+            return false;
+        }
+        mutex_inner.release();
+        true
+    }
+
+    fn try_lock(&self) -> bool {
+        let mut mutex_inner = self.inner.exclusive_access();
+        if mutex_inner.locked {
+            false
         } else {
-            mutex_inner.locked = false;
+            mutex_inner.locked = true;
+            mutex_inner.owner = current_task();
+            true
+        }
+    }
+
+    fn lock_timeout(&self, timeout_ms: usize) -> bool {
+        let task = current_task().unwrap();
+        let mut mutex_inner = self.inner.exclusive_access();
+        if !mutex_inner.locked {
+            mutex_inner.locked = true;
+            mutex_inner.owner = Some(task);
+            return true;
+        }
+        self.enqueue_waiter(mutex_inner, &task);
+        block_with_timeout(&task, timeout_ms);
+        // 被 unlock 唤醒时锁的所有权已经交给了当前任务
+        let mut mutex_inner = self.inner.exclusive_access();
+        if !finish_timed_wait(&mut mutex_inner.wait_queue, &task) {
+            return true;
+        }
+        task.inner_exclusive_access().blocked_on = None;
+        // 超时离开的等待者不再为持有者提供优先级
+        let next = mutex_inner.update_inherited_priority();
+        drop(mutex_inner);
+        propagate_priority(next);
+        false
+    }
+
+    fn is_busy(&self) -> bool {
+        let mutex_inner = self.inner.exclusive_access();
+        mutex_inner.locked || !mutex_inner.wait_queue.is_empty()
+    }
+
+    fn release_owned_by(&self, task: &Arc<TaskControlBlock>) {
+        let mut mutex_inner = self.inner.exclusive_access();
+        if mutex_inner.is_owned_by(task) {
+            mutex_inner.release();
         }
     }
 }
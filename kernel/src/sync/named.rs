@@ -0,0 +1,63 @@
+//! 内核全局的具名同步对象，不相关的进程可以通过名字打开同一个对象
+
+use super::{MessageQueue, Semaphore, UPIntrFreeCell};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use bitflags::*;
+use lazy_static::*;
+
+bitflags! {
+    /// 打开具名对象时使用的标志
+    pub struct IpcFlags: u32 {
+        /// 对象不存在时创建
+        const CREATE = 1 << 9;
+        /// 与 CREATE 一起使用，对象已存在时失败
+        const EXCL = 1 << 10;
+        /// 以非阻塞模式打开（仅对消息队列有效）
+        const NONBLOCK = 1 << 11;
+    }
+}
+
+pub type NamedRegistry<T> = UPIntrFreeCell<BTreeMap<String, Arc<T>>>;
+
+lazy_static! {
+    pub static ref NAMED_SEMAPHORES: NamedRegistry<Semaphore> =
+        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
+    pub static ref MESSAGE_QUEUES: NamedRegistry<MessageQueue> =
+        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
+}
+
+pub enum NamedOpenError {
+    /// 对象不存在且没有指定 CREATE
+    NotFound,
+    /// 同时指定了 CREATE 和 EXCL 但对象已存在
+    Exists,
+}
+
+/// 按名字打开对象，不存在且指定了 CREATE 时调用 `create` 新建并登记
+pub fn open_named<T>(
+    registry: &NamedRegistry<T>,
+    name: &str,
+    flags: IpcFlags,
+    create: impl FnOnce() -> T,
+) -> Result<Arc<T>, NamedOpenError> {
+    let mut registry = registry.exclusive_access();
+    if let Some(object) = registry.get(name) {
+        if flags.contains(IpcFlags::CREATE | IpcFlags::EXCL) {
+            return Err(NamedOpenError::Exists);
+        }
+        return Ok(Arc::clone(object));
+    }
+    if !flags.contains(IpcFlags::CREATE) {
+        return Err(NamedOpenError::NotFound);
+    }
+    let object = Arc::new(create());
+    registry.insert(String::from(name), Arc::clone(&object));
+    Ok(object)
+}
+
+/// 删除名字，已经打开的进程仍然可以继续使用该对象
+pub fn unlink_named<T>(registry: &NamedRegistry<T>, name: &str) -> bool {
+    registry.exclusive_access().remove(name).is_some()
+}
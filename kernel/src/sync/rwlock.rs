@@ -0,0 +1,96 @@
+use super::UPIntrFreeCell;
+use crate::task::{TaskControlBlock, block_current_and_run_next, current_task, wakeup_task};
+use alloc::collections::vec_deque::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// 阻塞式读写锁
+///
+/// 与 `MutexBlocking` 一样，解锁时直接把锁的所有权交给被唤醒的线程，
+/// 被唤醒的线程返回时已经持有锁。
+pub struct RwLock {
+    pub inner: UPIntrFreeCell<RwLockInner>,
+}
+
+pub struct RwLockInner {
+    /// 当前持有读锁的线程，同一个线程多次加读锁时出现多次
+    pub readers: Vec<Arc<TaskControlBlock>>,
+    /// 持有写锁的线程
+    pub writer: Option<Arc<TaskControlBlock>>,
+    /// 写者优先：有写者在等待时，新的读者也必须等待
+    pub writer_first: bool,
+    pub read_queue: VecDeque<Arc<TaskControlBlock>>,
+    pub write_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl RwLock {
+    pub fn new(writer_first: bool) -> Self {
+        Self {
+            inner: unsafe {
+                UPIntrFreeCell::new(RwLockInner {
+                    readers: Vec::new(),
+                    writer: None,
+                    writer_first,
+                    read_queue: VecDeque::new(),
+                    write_queue: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    pub fn read_lock(&self) {
+        let task = current_task().unwrap();
+        let mut inner = self.inner.exclusive_access();
+        if inner.writer.is_some() || (inner.writer_first && !inner.write_queue.is_empty()) {
+            inner.read_queue.push_back(task);
+            drop(inner);
+            block_current_and_run_next();
+        } else {
+            inner.readers.push(task);
+        }
+    }
+
+    pub fn write_lock(&self) {
+        let task = current_task().unwrap();
+        let mut inner = self.inner.exclusive_access();
+        if inner.writer.is_some() || !inner.readers.is_empty() {
+            inner.write_queue.push_back(task);
+            drop(inner);
+            block_current_and_run_next();
+        } else {
+            inner.writer = Some(task);
+        }
+    }
+
+    /// 释放当前线程持有的读锁或写锁，当前线程没有持有锁时返回 false
+    pub fn unlock(&self) -> bool {
+        let task = current_task().unwrap();
+        let mut inner = self.inner.exclusive_access();
+        if inner
+            .writer
+            .as_ref()
+            .is_some_and(|writer| Arc::ptr_eq(writer, &task))
+        {
+            inner.writer = None;
+        } else if let Some(idx) = inner.readers.iter().position(|t| Arc::ptr_eq(t, &task)) {
+            inner.readers.swap_remove(idx);
+        } else {
+            return false;
+        }
+        if !inner.readers.is_empty() {
+            return true;
+        }
+        // 锁已完全空闲，按策略决定唤醒一个写者还是全部读者
+        if !inner.write_queue.is_empty() && (inner.writer_first || inner.read_queue.is_empty()) {
+            let task = inner.write_queue.pop_front().unwrap();
+            inner.writer = Some(Arc::clone(&task));
+            wakeup_task(task);
+        } else {
+            while let Some(task) = inner.read_queue.pop_front() {
+                inner.readers.push(Arc::clone(&task));
+                wakeup_task(task);
+            }
+        }
+        true
+    }
+}
@@ -1,4 +1,4 @@
-use super::UPIntrFreeCell;
+use super::{UPIntrFreeCell, block_with_timeout, finish_timed_wait};
 use crate::task::{TaskControlBlock, block_current_and_run_next, current_task, wakeup_task};
 use alloc::collections::vec_deque::VecDeque;
 use alloc::sync::Arc;
@@ -43,4 +43,50 @@ impl Semaphore {
             block_current_and_run_next();
         }
     }
+
+    /// 进程退出时将其线程从等待队列中移除，并归还该线程预先扣除的计数
+    pub fn remove_waiter(&self, task: &Arc<TaskControlBlock>) {
+        let mut inner = self.inner.exclusive_access();
+        if let Some(idx) = inner.wait_queue.iter().position(|t| Arc::ptr_eq(t, task)) {
+            inner.wait_queue.remove(idx);
+            inner.count += 1;
+        }
+    }
+
+    /// 有线程在等待时返回 true，此时不能销毁
+    pub fn is_busy(&self) -> bool {
+        !self.inner.exclusive_access().wait_queue.is_empty()
+    }
+
+    /// 尝试获取一个资源，不阻塞；资源不足时返回 false
+    pub fn try_down(&self) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        if inner.count > 0 {
+            inner.count -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 获取一个资源，最多等待 `timeout_ms` 毫秒；超时返回 false
+    pub fn down_timeout(&self, timeout_ms: usize) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        inner.count -= 1;
+        if inner.count >= 0 {
+            return true;
+        }
+        let task = current_task().unwrap();
+        inner.wait_queue.push_back(Arc::clone(&task));
+        drop(inner);
+        block_with_timeout(&task, timeout_ms);
+        let mut inner = self.inner.exclusive_access();
+        if finish_timed_wait(&mut inner.wait_queue, &task) {
+            // 超时放弃等待，归还预先扣除的计数
+            inner.count += 1;
+            false
+        } else {
+            true
+        }
+    }
 }
@@ -0,0 +1,40 @@
+//! 系统调用返回的错误码，取值与 Linux 保持一致
+
+/// 操作不被允许
+pub const EPERM: isize = -1;
+/// 文件或对象不存在
+pub const ENOENT: isize = -2;
+/// 输入输出错误，文件系统的元数据已经损坏
+pub const EIO: isize = -5;
+/// 无效的描述符
+pub const EBADF: isize = -9;
+/// 资源暂时不可用，非阻塞操作需要稍后重试
+pub const EAGAIN: isize = -11;
+/// 没有访问权限
+pub const EACCES: isize = -13;
+/// 资源忙
+pub const EBUSY: isize = -16;
+/// 对象已经存在
+pub const EEXIST: isize = -17;
+/// 目标是目录
+pub const EISDIR: isize = -21;
+/// 参数无效
+pub const EINVAL: isize = -22;
+/// 对象不支持这个控制命令
+pub const ENOTTY: isize = -25;
+/// 文件超出允许的最大大小
+pub const EFBIG: isize = -27;
+/// 设备上没有剩余空间
+pub const ENOSPC: isize = -28;
+/// 文件系统只读
+pub const EROFS: isize = -30;
+/// 结果超出缓冲区的大小
+pub const ERANGE: isize = -34;
+/// 扩展属性不存在
+pub const ENODATA: isize = -61;
+/// 消息长度超出限制
+pub const EMSGSIZE: isize = -90;
+/// 文件系统不支持这个操作
+pub const EOPNOTSUPP: isize = -95;
+/// 等待超时
+pub const ETIMEDOUT: isize = -110;
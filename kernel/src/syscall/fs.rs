@@ -1,13 +1,45 @@
-use crate::fs::{OpenFlags, Stat, find_inode, make_pipe, open_file};
-use crate::mm::{UserBuffer, translated_byte_buffer, translated_refmut, translated_str};
+use super::errno::{
+    EACCES, EBADF, EEXIST, EFBIG, EINVAL, EIO, EISDIR, ENODATA, ENOENT, ENOSPC, ENOTTY, EOPNOTSUPP,
+    EPERM, ERANGE, EROFS,
+};
+use crate::fs::{
+    Access, OpenFlags, Stat, StatFs, check_access, current_cred, find_inode, find_inode_nofollow,
+    fs_read_only, make_pipe, open_file, sync_fs,
+};
+use crate::mm::{
+    UserBuffer, translated_byte_buffer, translated_ref, translated_refmut, translated_str,
+};
 use crate::task::{current_process, current_user_token};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::ptr::slice_from_raw_parts;
+use file_system::{Inode, RenameError, XattrError, take_context_errors};
+
+/// 在目录中增加或删除目录项需要写和搜索权限
+fn may_modify_dir(dir: &Inode) -> bool {
+    check_access(dir, &current_cred(), Access::WRITE | Access::EXEC)
+}
+
+/// 执行访问文件系统的系统调用，这次调用读到了损坏的元数据时返回 EIO
+///
+/// 发现损坏后文件系统不再提交修改，之后修改目录树的调用 `modifies` 为真，直接返回 EROFS
+pub fn check_io(modifies: bool, syscall: impl FnOnce() -> isize) -> isize {
+    if modifies && fs_read_only() {
+        return EROFS;
+    }
+    // 清除之前同一个线程留下的记录
+    take_context_errors();
+    let ret = syscall();
+    if take_context_errors() > 0 { EIO } else { ret }
+}
 
 pub fn sys_getdents(path: *const u8) -> isize {
     let path = translated_str(current_user_token(), path);
-    let inode = find_inode(path.as_str());
-    let mut vec = inode.unwrap().ls();
+    let inode = find_inode(path.as_str()).unwrap();
+    if !check_access(&inode, &current_cred(), Access::READ) {
+        return EACCES;
+    }
+    let mut vec = inode.ls();
     vec.sort();
     let max_width = vec.iter().map(|s| s.len()).max().unwrap_or(0);
 
@@ -34,6 +66,9 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
         let file = file.clone();
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
+        if file.read_only() {
+            return EROFS;
+        }
         file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
     } else {
         -1
@@ -63,7 +98,12 @@ pub fn sys_open(path: *const u8, flags: u32) -> isize {
     let process = current_process();
     let token = current_user_token();
     let path = translated_str(token, path);
-    if let Some(inode) = open_file(path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
+    let flags = OpenFlags::from_bits(flags).unwrap();
+    // 目录只能以只读方式打开
+    if flags.modifies() && find_inode(path.as_str()).is_some_and(|inode| inode.is_dir()) {
+        return EISDIR;
+    }
+    if let Some(inode) = open_file(path.as_str(), flags) {
         let mut inner = process.inner_exclusive_access();
         let fd = inner.alloc_fd();
         inner.fd_table[fd] = Some(inode);
@@ -121,7 +161,12 @@ pub fn sys_mkdir(path: *const u8) -> isize {
     let (parent_path, target) = dir.rsplit_once('/').unwrap();
 
     if let Some(parent_inode) = find_inode(parent_path) {
-        if let Some(_cur_inode) = parent_inode.create_dir(target) {
+        if !may_modify_dir(&parent_inode) {
+            return EACCES;
+        }
+        if let Some(cur_inode) = parent_inode.create_dir(target) {
+            let cred = current_cred();
+            cur_inode.set_owner(cred.euid, cred.egid);
             0
         } else {
             -2
@@ -130,22 +175,42 @@ pub fn sys_mkdir(path: *const u8) -> isize {
         -1
     }
 }
-const DIRENT_SZ: usize = 32;
+
+/// 删除目录而不是普通文件
+const AT_REMOVEDIR: u32 = 1;
+/// 与 `AT_REMOVEDIR` 一起使用时连同目录中的所有内容一起删除
+const AT_RECURSIVE: u32 = 2;
 
 pub fn sys_unlink(path: *const u8, flags: u32) -> isize {
     let path = translated_str(current_user_token(), path);
 
     let (parent_path, target) = path.rsplit_once('/').unwrap();
-    if let Some(inode) = find_inode(&path) {
-        if flags == 0 && inode.is_file() {
-            find_inode(parent_path).unwrap().delete(target);
-            0
-        } else if flags == 1 && inode.is_dir() {
-            if find_inode(parent_path).unwrap().delete(target) {
+    // 删除的是符号链接本身，而不是它指向的文件
+    if let Some(inode) = find_inode_nofollow(&path) {
+        let parent = find_inode(parent_path).unwrap();
+        if !may_modify_dir(&parent) {
+            return EACCES;
+        }
+        // 仍被打开的文件在最后一个引用关闭时才被回收
+        if flags == 0 && !inode.is_dir() {
+            // 查找之后目录项可能已经被其他线程删除
+            if parent.unlink(target).is_some() {
+                0
+            } else {
+                ENOENT
+            }
+        } else if flags == AT_REMOVEDIR && inode.is_dir() {
+            if parent.unlink(target).is_some() {
                 0
             } else {
                 -3
             }
+        } else if flags == AT_REMOVEDIR | AT_RECURSIVE && inode.is_dir() {
+            // 子树中有不允许修改的目录时什么也不删除
+            if parent.remove_tree(target, may_modify_dir).is_none() {
+                return EACCES;
+            }
+            0
         } else {
             -2
         }
@@ -154,25 +219,351 @@ pub fn sys_unlink(path: *const u8, flags: u32) -> isize {
     }
 }
 
+/// 为 `old_path` 指向的文件创建新的目录项 `new_path`
+pub fn sys_linkat(old_path: *const u8, new_path: *const u8) -> isize {
+    let token = current_user_token();
+    let old_path = translated_str(token, old_path);
+    let new_path = translated_str(token, new_path);
+    let Some(target) = find_inode_nofollow(old_path.as_str()) else {
+        return ENOENT;
+    };
+    if target.is_dir() {
+        return EPERM;
+    }
+    let (parent_path, name) = new_path.rsplit_once('/').unwrap_or(("", new_path.as_str()));
+    let Some(parent) = find_inode(parent_path) else {
+        return ENOENT;
+    };
+    if !may_modify_dir(&parent) {
+        return EACCES;
+    }
+    if parent.link(name, &target) {
+        0
+    } else {
+        EEXIST
+    }
+}
+
+/// 创建内容为 `target` 的符号链接 `link_path`，不检查目标是否存在
+pub fn sys_symlinkat(target: *const u8, link_path: *const u8) -> isize {
+    let token = current_user_token();
+    let target = translated_str(token, target);
+    let link_path = translated_str(token, link_path);
+    let (parent_path, name) = link_path
+        .rsplit_once('/')
+        .unwrap_or(("", link_path.as_str()));
+    let Some(parent) = find_inode(parent_path) else {
+        return ENOENT;
+    };
+    if !parent.is_dir() {
+        return ENOENT;
+    }
+    if !may_modify_dir(&parent) {
+        return EACCES;
+    }
+    if let Some(link) = parent.symlink(name, target.as_str()) {
+        let cred = current_cred();
+        link.set_owner(cred.euid, cred.egid);
+        0
+    } else {
+        EEXIST
+    }
+}
+
+/// 将符号链接的目标路径写入 `buf`，返回写入的字节数，内容超长时截断
+pub fn sys_readlinkat(path: *const u8, buf: *mut u8, len: usize) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let Some(inode) = find_inode_nofollow(path.as_str()) else {
+        return ENOENT;
+    };
+    let Some(target) = inode.read_link() else {
+        return EINVAL;
+    };
+    let target = &target.as_bytes()[..target.len().min(len)];
+    let mut copied = 0;
+    for chunk in translated_byte_buffer(token, buf, target.len()) {
+        chunk.copy_from_slice(&target[copied..copied + chunk.len()]);
+        copied += chunk.len();
+    }
+    copied as isize
+}
+
+/// 移动或重命名 `old_path`，目标存在时按 `Inode::rename` 的规则替换
+pub fn sys_renameat(old_path: *const u8, new_path: *const u8) -> isize {
+    let token = current_user_token();
+    let old_path = translated_str(token, old_path);
+    let new_path = translated_str(token, new_path);
+    let (old_parent_path, old_name) = old_path.rsplit_once('/').unwrap_or(("", old_path.as_str()));
+    let (new_parent_path, new_name) = new_path.rsplit_once('/').unwrap_or(("", new_path.as_str()));
+    let (Some(old_parent), Some(new_parent)) =
+        (find_inode(old_parent_path), find_inode(new_parent_path))
+    else {
+        return ENOENT;
+    };
+    if !may_modify_dir(&old_parent) || !may_modify_dir(&new_parent) {
+        return EACCES;
+    }
+    match old_parent.rename(old_name, &new_parent, new_name) {
+        Ok(_) => 0,
+        Err(RenameError::NotFound) => ENOENT,
+        Err(RenameError::IntoSubtree) => EINVAL,
+        Err(RenameError::TargetExists) => EEXIST,
+        Err(RenameError::ReadOnly) => EROFS,
+    }
+}
+
+/// 将 `stat` 或 `statfs` 结构体按字节复制到用户空间，结构体可能跨越页边界
+fn copy_stat_to_user<T>(token: usize, dst: *mut u8, stat: &T) {
+    let user_buffer = UserBuffer::new(translated_byte_buffer(token, dst, size_of::<T>()));
+    let stat_buf = slice_from_raw_parts(stat as *const _ as *const u8, size_of::<T>());
+    for (i, byte) in user_buffer.into_iter().enumerate() {
+        unsafe {
+            *byte = (*stat_buf)[i];
+        }
+    }
+}
+
+/// 把 `bytes` 复制到用户缓冲区，缓冲区大小为 0 时只返回需要的大小，放不下时返回 ERANGE
+fn copy_xattr_to_user(token: usize, dst: *mut u8, size: usize, bytes: &[u8]) -> isize {
+    if size == 0 {
+        return bytes.len() as isize;
+    }
+    if bytes.len() > size {
+        return ERANGE;
+    }
+    let mut copied = 0;
+    for chunk in translated_byte_buffer(token, dst, bytes.len()) {
+        chunk.copy_from_slice(&bytes[copied..copied + chunk.len()]);
+        copied += chunk.len();
+    }
+    copied as isize
+}
+
+fn xattr_errno(err: XattrError) -> isize {
+    match err {
+        XattrError::Unsupported => EOPNOTSUPP,
+        XattrError::InvalidName => ERANGE,
+        XattrError::NotFound => ENODATA,
+        XattrError::NoSpace => ENOSPC,
+    }
+}
+
+/// 设置扩展属性，需要写权限
+pub fn sys_setxattr(path: *const u8, name: *const u8, value: *const u8, size: usize) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let name = translated_str(token, name);
+    let Some(inode) = find_inode(path.as_str()) else {
+        return ENOENT;
+    };
+    if !check_access(&inode, &current_cred(), Access::WRITE) {
+        return EACCES;
+    }
+    let value: Vec<u8> = translated_byte_buffer(token, value, size).concat();
+    match inode.set_xattr(name.as_str(), &value) {
+        Ok(()) => 0,
+        Err(err) => xattr_errno(err),
+    }
+}
+
+/// 读取扩展属性的值，返回值的长度，需要读权限
+pub fn sys_getxattr(path: *const u8, name: *const u8, value: *mut u8, size: usize) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let name = translated_str(token, name);
+    let Some(inode) = find_inode(path.as_str()) else {
+        return ENOENT;
+    };
+    if !check_access(&inode, &current_cred(), Access::READ) {
+        return EACCES;
+    }
+    match inode.get_xattr(name.as_str()) {
+        Ok(bytes) => copy_xattr_to_user(token, value, size, &bytes),
+        Err(err) => xattr_errno(err),
+    }
+}
+
+/// 列出所有扩展属性的名称，每个名称以 NUL 结尾，需要读权限
+pub fn sys_listxattr(path: *const u8, list: *mut u8, size: usize) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let Some(inode) = find_inode(path.as_str()) else {
+        return ENOENT;
+    };
+    if !check_access(&inode, &current_cred(), Access::READ) {
+        return EACCES;
+    }
+    let mut names = Vec::new();
+    for name in inode.list_xattr() {
+        names.extend_from_slice(name.as_bytes());
+        names.push(0);
+    }
+    copy_xattr_to_user(token, list, size, &names)
+}
+
+/// 删除扩展属性，需要写权限
+pub fn sys_removexattr(path: *const u8, name: *const u8) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let name = translated_str(token, name);
+    let Some(inode) = find_inode(path.as_str()) else {
+        return ENOENT;
+    };
+    if !check_access(&inode, &current_cred(), Access::WRITE) {
+        return EACCES;
+    }
+    match inode.remove_xattr(name.as_str()) {
+        Ok(()) => 0,
+        Err(err) => xattr_errno(err),
+    }
+}
+
 pub fn sys_fstat(fd: usize, stat: *mut u8) -> isize {
     let process = current_process();
-    let mut inner = process.inner_exclusive_access();
+    let inner = process.inner_exclusive_access();
     let token = current_user_token();
-    let user_buffer = UserBuffer::new(translated_byte_buffer(token, stat, size_of::<Stat>()));
 
-    let fd_table = &mut inner.fd_table;
+    let fd_table = &inner.fd_table;
 
     if fd >= fd_table.len() || fd_table[fd].is_none() {
         return -1;
     }
 
     let file = fd_table[fd].clone().unwrap();
-    let tmp_stat = Stat::from(file);
-    let stat_buf = slice_from_raw_parts(&tmp_stat as *const _ as *const u8, size_of::<Stat>());
-    for (i, byte) in user_buffer.into_iter().enumerate() {
-        unsafe {
-            *byte = (*stat_buf)[i];
+    drop(inner);
+    copy_stat_to_user(token, stat, &file.stat());
+    0
+}
+
+/// 路径是符号链接时返回链接本身的信息
+const AT_SYMLINK_NOFOLLOW: u32 = 0x100;
+
+pub fn sys_fstatat(path: *const u8, stat: *mut u8, flags: u32) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let inode = if flags & AT_SYMLINK_NOFOLLOW != 0 {
+        find_inode_nofollow(path.as_str())
+    } else {
+        find_inode(path.as_str())
+    };
+    let Some(inode) = inode else {
+        return ENOENT;
+    };
+    copy_stat_to_user(token, stat, &Stat::from(inode.as_ref()));
+    0
+}
+
+/// 把 `path` 指向的文件截断或扩展到 `length` 字节
+pub fn sys_truncate(path: *const u8, length: isize) -> isize {
+    let path = translated_str(current_user_token(), path);
+    let Ok(length) = u32::try_from(length) else {
+        return EINVAL;
+    };
+    let Some(inode) = find_inode(path.as_str()) else {
+        return ENOENT;
+    };
+    if inode.is_dir() {
+        return EISDIR;
+    }
+    if !check_access(&inode, &current_cred(), Access::WRITE) {
+        return EACCES;
+    }
+    if !inode.truncate(length) {
+        return EFBIG;
+    }
+    0
+}
+
+/// 与 `sys_truncate` 相同，但作用于以写方式打开的文件描述符
+pub fn sys_ftruncate(fd: usize, length: isize) -> isize {
+    let Ok(length) = u32::try_from(length) else {
+        return EINVAL;
+    };
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let Some(Some(file)) = inner.fd_table.get(fd) else {
+        return EBADF;
+    };
+    let file = file.clone();
+    drop(inner);
+    if !file.writable() || !file.truncate(length) {
+        return EINVAL;
+    }
+    0
+}
+
+/// 获取 `path` 所在文件系统的容量和使用情况
+pub fn sys_statfs(path: *const u8, buf: *mut u8) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let Some(inode) = find_inode(path.as_str()) else {
+        return ENOENT;
+    };
+    copy_stat_to_user(token, buf, &StatFs::from(inode.fs_stat()));
+    0
+}
+
+/// 获取打开的文件所在文件系统的容量和使用情况，管道和标准输入输出返回 EINVAL
+pub fn sys_fstatfs(fd: usize, buf: *mut u8) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let Some(Some(file)) = inner.fd_table.get(fd) else {
+        return EBADF;
+    };
+    let file = file.clone();
+    drop(inner);
+    let Some(statfs) = file.statfs() else {
+        return EINVAL;
+    };
+    copy_stat_to_user(token, buf, &statfs);
+    0
+}
+
+/// 读取文件的属性标志，与 Linux 的 `FS_IOC_GETFLAGS` 相同
+const FS_IOC_GETFLAGS: usize = 0x80086601;
+/// 设置文件的属性标志，与 Linux 的 `FS_IOC_SETFLAGS` 相同
+const FS_IOC_SETFLAGS: usize = 0x40086602;
+
+/// 对文件执行控制命令，目前只支持读取和设置属性标志，`arg` 指向一个 u32
+///
+/// 设置标志需要以写方式打开文件，无法设置的标志返回 EINVAL
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let Some(Some(file)) = inner.fd_table.get(fd) else {
+        return EBADF;
+    };
+    let file = file.clone();
+    drop(inner);
+    let Some(flags) = file.flags() else {
+        return ENOTTY;
+    };
+    match cmd {
+        FS_IOC_GETFLAGS => {
+            *translated_refmut(token, arg as *mut u32) = flags;
+            0
+        }
+        FS_IOC_SETFLAGS => {
+            let flags = *translated_ref(token, arg as *const u32);
+            if !file.writable() {
+                EBADF
+            } else if file.read_only() {
+                EROFS
+            } else if !file.set_flags(flags) {
+                EINVAL
+            } else {
+                0
+            }
         }
+        _ => ENOTTY,
     }
+}
+
+/// 把所有未写回的修改写入磁盘
+pub fn sys_sync() -> isize {
+    sync_fs();
     0
 }
@@ -0,0 +1,168 @@
+use super::errno::{EAGAIN, EBADF, EEXIST, EINVAL, EMSGSIZE, ENOENT};
+use crate::mm::{translated_byte_buffer, translated_ref, translated_refmut, translated_str};
+use crate::sync::{
+    IpcFlags, MESSAGE_QUEUES, MQ_PRIO_MAX, MessageQueue, MqAttr, MqDescriptor, NAMED_SEMAPHORES,
+    NamedOpenError, Semaphore, open_named, unlink_named,
+};
+use crate::task::{current_process, current_user_token};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// 将对象放入第一个空闲的位置，返回其编号
+fn alloc_slot<T>(list: &mut Vec<Option<T>>, item: T) -> usize {
+    if let Some(id) = list.iter().position(|slot| slot.is_none()) {
+        list[id] = Some(item);
+        id
+    } else {
+        list.push(Some(item));
+        list.len() - 1
+    }
+}
+
+fn open_error(err: NamedOpenError) -> isize {
+    match err {
+        NamedOpenError::NotFound => ENOENT,
+        NamedOpenError::Exists => EEXIST,
+    }
+}
+
+fn get_mq(mqd: usize) -> Option<(Arc<MessageQueue>, bool)> {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let descriptor = process_inner.mq_list.get(mqd)?.as_ref()?;
+    Some((Arc::clone(&descriptor.queue), descriptor.nonblock))
+}
+
+/// 打开具名信号量，返回的编号可以直接用于 `semaphore_up`/`semaphore_down` 等系统调用
+pub fn sys_sem_open(name: *const u8, flags: u32, res_count: usize) -> isize {
+    let Some(flags) = IpcFlags::from_bits(flags) else {
+        return EINVAL;
+    };
+    let name = translated_str(current_user_token(), name);
+    let sem = match open_named(&NAMED_SEMAPHORES, name.as_str(), flags, || {
+        Semaphore::new(res_count)
+    }) {
+        Ok(sem) => sem,
+        Err(err) => return open_error(err),
+    };
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    alloc_slot(&mut process_inner.semaphore_list, sem) as isize
+}
+
+/// 关闭本进程中的信号量句柄，不影响其他进程
+pub fn sys_sem_close(sem_id: usize) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    match process_inner.semaphore_list.get_mut(sem_id) {
+        Some(slot @ Some(_)) => {
+            *slot = None;
+            0
+        }
+        _ => EINVAL,
+    }
+}
+
+pub fn sys_sem_unlink(name: *const u8) -> isize {
+    let name = translated_str(current_user_token(), name);
+    if unlink_named(&NAMED_SEMAPHORES, name.as_str()) {
+        0
+    } else {
+        ENOENT
+    }
+}
+
+/// 打开消息队列，`attr` 为空时使用默认属性；队列已存在时忽略 `attr`
+pub fn sys_mq_open(name: *const u8, flags: u32, attr: *const MqAttr) -> isize {
+    let Some(flags) = IpcFlags::from_bits(flags) else {
+        return EINVAL;
+    };
+    let token = current_user_token();
+    let name = translated_str(token, name);
+    let attr = if attr.is_null() {
+        MqAttr::default()
+    } else {
+        *translated_ref(token, attr)
+    };
+    if !attr.is_valid() {
+        return EINVAL;
+    }
+    let queue = match open_named(&MESSAGE_QUEUES, name.as_str(), flags, || {
+        MessageQueue::new(attr)
+    }) {
+        Ok(queue) => queue,
+        Err(err) => return open_error(err),
+    };
+    let descriptor = MqDescriptor {
+        queue,
+        nonblock: flags.contains(IpcFlags::NONBLOCK),
+    };
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    alloc_slot(&mut process_inner.mq_list, descriptor) as isize
+}
+
+pub fn sys_mq_close(mqd: usize) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    match process_inner.mq_list.get_mut(mqd) {
+        Some(slot @ Some(_)) => {
+            *slot = None;
+            0
+        }
+        _ => EBADF,
+    }
+}
+
+pub fn sys_mq_unlink(name: *const u8) -> isize {
+    let name = translated_str(current_user_token(), name);
+    if unlink_named(&MESSAGE_QUEUES, name.as_str()) {
+        0
+    } else {
+        ENOENT
+    }
+}
+
+pub fn sys_mq_send(mqd: usize, buf: *const u8, len: usize, priority: usize) -> isize {
+    let Some((queue, nonblock)) = get_mq(mqd) else {
+        return EBADF;
+    };
+    if len > queue.attr.msg_size {
+        return EMSGSIZE;
+    }
+    if priority >= MQ_PRIO_MAX {
+        return EINVAL;
+    }
+    let mut data = Vec::with_capacity(len);
+    for chunk in translated_byte_buffer(current_user_token(), buf, len) {
+        data.extend_from_slice(chunk);
+    }
+    if queue.send(data, priority, nonblock) {
+        0
+    } else {
+        EAGAIN
+    }
+}
+
+/// 接收一条消息，返回消息长度；`priority` 不为空时写入消息的优先级
+pub fn sys_mq_receive(mqd: usize, buf: *mut u8, len: usize, priority: *mut usize) -> isize {
+    let Some((queue, nonblock)) = get_mq(mqd) else {
+        return EBADF;
+    };
+    if len < queue.attr.msg_size {
+        return EMSGSIZE;
+    }
+    let Some((data, msg_priority)) = queue.receive(nonblock) else {
+        return EAGAIN;
+    };
+    let token = current_user_token();
+    let mut copied = 0;
+    for chunk in translated_byte_buffer(token, buf, data.len()) {
+        chunk.copy_from_slice(&data[copied..copied + chunk.len()]);
+        copied += chunk.len();
+    }
+    if !priority.is_null() {
+        *translated_refmut(token, priority) = msg_priority;
+    }
+    data.len() as isize
+}
@@ -7,15 +7,37 @@ const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_MKDIR: usize = 34;
 const SYSCALL_FSTAT: usize = 80;
+const SYSCALL_SYNC: usize = 81;
 const SYSCALL_UNLINK: usize = 35;
+const SYSCALL_SYMLINKAT: usize = 36;
+const SYSCALL_LINKAT: usize = 37;
+const SYSCALL_TRUNCATE: usize = 45;
+const SYSCALL_FTRUNCATE: usize = 46;
+const SYSCALL_STATFS: usize = 43;
+const SYSCALL_FSTATFS: usize = 44;
+const SYSCALL_RENAMEAT: usize = 38;
+const SYSCALL_READLINKAT: usize = 78;
+const SYSCALL_FSTATAT: usize = 79;
+const SYSCALL_SETXATTR: usize = 5;
+const SYSCALL_GETXATTR: usize = 8;
+const SYSCALL_LISTXATTR: usize = 11;
+const SYSCALL_REMOVEXATTR: usize = 14;
+const SYSCALL_IOCTL: usize = 29;
 
 // process
 const SYSCALL_EXIT: usize = 93;
 const SYSCALL_SLEEP: usize = 101;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_KILL: usize = 129;
+const SYSCALL_SET_PRIORITY: usize = 140;
+const SYSCALL_SETGID: usize = 144;
+const SYSCALL_SETUID: usize = 146;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETUID: usize = 174;
+const SYSCALL_GETEUID: usize = 175;
+const SYSCALL_GETGID: usize = 176;
+const SYSCALL_GETEGID: usize = 177;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
@@ -29,16 +51,47 @@ const SYSCALL_WAITTID: usize = 1002;
 const SYSCALL_MUTEX_CREATE: usize = 1010;
 const SYSCALL_MUTEX_LOCK: usize = 1011;
 const SYSCALL_MUTEX_UNLOCK: usize = 1012;
+const SYSCALL_MUTEX_TRYLOCK: usize = 1013;
+const SYSCALL_MUTEX_LOCK_TIMEOUT: usize = 1014;
+const SYSCALL_MUTEX_DESTROY: usize = 1015;
 
 // semaphore
 const SYSCALL_SEMAPHORE_CREATE: usize = 1020;
 const SYSCALL_SEMAPHORE_UP: usize = 1021;
 const SYSCALL_SEMAPHORE_DOWN: usize = 1022;
+const SYSCALL_SEMAPHORE_TRYDOWN: usize = 1023;
+const SYSCALL_SEMAPHORE_DOWN_TIMEOUT: usize = 1024;
+const SYSCALL_SEMAPHORE_DESTROY: usize = 1025;
 
 // condvar
 const SYSCALL_CONDVAR_CREATE: usize = 1030;
 const SYSCALL_CONDVAR_SIGNAL: usize = 1031;
 const SYSCALL_CONDVAR_WAIT: usize = 1032;
+const SYSCALL_CONDVAR_BROADCAST: usize = 1033;
+const SYSCALL_CONDVAR_WAIT_TIMEOUT: usize = 1034;
+const SYSCALL_CONDVAR_DESTROY: usize = 1035;
+
+// rwlock
+const SYSCALL_RWLOCK_CREATE: usize = 1040;
+const SYSCALL_RWLOCK_READ: usize = 1041;
+const SYSCALL_RWLOCK_WRITE: usize = 1042;
+const SYSCALL_RWLOCK_UNLOCK: usize = 1043;
+
+// barrier
+const SYSCALL_BARRIER_CREATE: usize = 1050;
+const SYSCALL_BARRIER_WAIT: usize = 1051;
+
+// named semaphore
+const SYSCALL_SEM_OPEN: usize = 1060;
+const SYSCALL_SEM_CLOSE: usize = 1061;
+const SYSCALL_SEM_UNLINK: usize = 1062;
+
+// message queue
+const SYSCALL_MQ_OPEN: usize = 1070;
+const SYSCALL_MQ_CLOSE: usize = 1071;
+const SYSCALL_MQ_UNLINK: usize = 1072;
+const SYSCALL_MQ_SEND: usize = 1073;
+const SYSCALL_MQ_RECEIVE: usize = 1074;
 
 //gpu
 const SYSCALL_FRAMEBUFFER: usize = 2000;
@@ -50,9 +103,11 @@ const SYSCALL_KEY_PRESSED: usize = 3001;
 
 const SYSCALL_GETDENTS: usize = 61;
 
+mod errno;
 mod fs;
 mod gui;
 mod input;
+mod ipc;
 mod process;
 mod sync;
 mod thread;
@@ -60,13 +115,16 @@ mod thread;
 use fs::*;
 use gui::*;
 use input::*;
+use ipc::*;
 use process::*;
 use sync::*;
 use thread::*;
 
+use crate::fs::OpenFlags;
+use crate::sync::MqAttr;
 use log::*;
 
-pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
+pub fn syscall(syscall_id: usize, args: [usize; 4]) -> isize {
     match syscall_id {
         SYSCALL_DUP => {
             info!("syscall_dup");
@@ -74,7 +132,8 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
         }
         SYSCALL_OPEN => {
             info!("syscall_open");
-            sys_open(args[0] as *const u8, args[1] as u32)
+            let modifies = OpenFlags::from_bits_truncate(args[1] as u32).modifies();
+            check_io(modifies, || sys_open(args[0] as *const u8, args[1] as u32))
         }
         SYSCALL_CLOSE => {
             info!("syscall_close");
@@ -84,21 +143,111 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
             info!("syscall_pipe");
             sys_pipe(args[0] as *mut usize)
         }
-        SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
-        SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_READ => check_io(false, || sys_read(args[0], args[1] as *const u8, args[2])),
+        SYSCALL_WRITE => check_io(false, || sys_write(args[0], args[1] as *const u8, args[2])),
         SYSCALL_MKDIR => {
             info!("syscall_mkdir");
-            sys_mkdir(args[0] as *const u8)
+            check_io(true, || sys_mkdir(args[0] as *const u8))
         }
 
         SYSCALL_FSTAT => {
             info!("syscall_fstat");
-            sys_fstat(args[0], args[1] as *mut u8)
+            check_io(false, || sys_fstat(args[0], args[1] as *mut u8))
         }
 
         SYSCALL_UNLINK => {
             info!("syscall_unlink");
-            sys_unlink(args[0] as *const u8, args[1] as u32)
+            check_io(true, || sys_unlink(args[0] as *const u8, args[1] as u32))
+        }
+        SYSCALL_LINKAT => {
+            info!("syscall_linkat");
+            check_io(true, || {
+                sys_linkat(args[0] as *const u8, args[1] as *const u8)
+            })
+        }
+        SYSCALL_RENAMEAT => {
+            info!("syscall_renameat");
+            check_io(true, || {
+                sys_renameat(args[0] as *const u8, args[1] as *const u8)
+            })
+        }
+        SYSCALL_SYMLINKAT => {
+            info!("syscall_symlinkat");
+            check_io(true, || {
+                sys_symlinkat(args[0] as *const u8, args[1] as *const u8)
+            })
+        }
+        SYSCALL_READLINKAT => {
+            info!("syscall_readlinkat");
+            check_io(false, || {
+                sys_readlinkat(args[0] as *const u8, args[1] as *mut u8, args[2])
+            })
+        }
+        SYSCALL_FSTATAT => {
+            info!("syscall_fstatat");
+            check_io(false, || {
+                sys_fstatat(args[0] as *const u8, args[1] as *mut u8, args[2] as u32)
+            })
+        }
+        SYSCALL_SETXATTR => {
+            info!("syscall_setxattr");
+            check_io(true, || {
+                sys_setxattr(
+                    args[0] as *const u8,
+                    args[1] as *const u8,
+                    args[2] as *const u8,
+                    args[3],
+                )
+            })
+        }
+        SYSCALL_GETXATTR => {
+            info!("syscall_getxattr");
+            check_io(false, || {
+                sys_getxattr(
+                    args[0] as *const u8,
+                    args[1] as *const u8,
+                    args[2] as *mut u8,
+                    args[3],
+                )
+            })
+        }
+        SYSCALL_LISTXATTR => {
+            info!("syscall_listxattr");
+            check_io(false, || {
+                sys_listxattr(args[0] as *const u8, args[1] as *mut u8, args[2])
+            })
+        }
+        SYSCALL_REMOVEXATTR => {
+            info!("syscall_removexattr");
+            check_io(true, || {
+                sys_removexattr(args[0] as *const u8, args[1] as *const u8)
+            })
+        }
+        SYSCALL_TRUNCATE => {
+            info!("syscall_truncate");
+            check_io(true, || {
+                sys_truncate(args[0] as *const u8, args[1] as isize)
+            })
+        }
+        SYSCALL_FTRUNCATE => {
+            info!("syscall_ftruncate");
+            check_io(true, || sys_ftruncate(args[0], args[1] as isize))
+        }
+        SYSCALL_STATFS => {
+            info!("syscall_statfs");
+            sys_statfs(args[0] as *const u8, args[1] as *mut u8)
+        }
+        SYSCALL_FSTATFS => {
+            info!("syscall_fstatfs");
+            sys_fstatfs(args[0], args[1] as *mut u8)
+        }
+        SYSCALL_IOCTL => {
+            info!("syscall_ioctl");
+            check_io(false, || sys_ioctl(args[0], args[1], args[2]))
+        }
+        SYSCALL_SYNC => {
+            info!("syscall_sync");
+            sys_sync()
         }
 
         SYSCALL_SLEEP => {
@@ -114,12 +263,28 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
             info!("syscall_kill");
             sys_kill(args[0], args[1] as u32)
         }
+        SYSCALL_SET_PRIORITY => {
+            info!("syscall_set_priority");
+            sys_set_priority(args[0])
+        }
+        SYSCALL_SETGID => {
+            info!("syscall_setgid");
+            sys_setgid(args[0] as u32)
+        }
+        SYSCALL_SETUID => {
+            info!("syscall_setuid");
+            sys_setuid(args[0] as u32)
+        }
 
         SYSCALL_GET_TIME => sys_get_time(),
         SYSCALL_GETPID => {
             info!("syscall_gitpid");
             sys_getpid()
         }
+        SYSCALL_GETUID => sys_getuid(),
+        SYSCALL_GETEUID => sys_geteuid(),
+        SYSCALL_GETGID => sys_getgid(),
+        SYSCALL_GETEGID => sys_getegid(),
         SYSCALL_FORK => {
             info!("syscall_fork");
             sys_fork()
@@ -150,6 +315,18 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
             info!("syscall_mutex_unlock");
             sys_mutex_unlock(args[0])
         }
+        SYSCALL_MUTEX_TRYLOCK => {
+            info!("syscall_mutex_trylock");
+            sys_mutex_trylock(args[0])
+        }
+        SYSCALL_MUTEX_LOCK_TIMEOUT => {
+            info!("syscall_mutex_lock_timeout");
+            sys_mutex_lock_timeout(args[0], args[1])
+        }
+        SYSCALL_MUTEX_DESTROY => {
+            info!("syscall_mutex_destroy");
+            sys_mutex_destroy(args[0])
+        }
         SYSCALL_SEMAPHORE_CREATE => {
             info!("syscall_semaphore_create");
             sys_semaphore_create(args[0])
@@ -162,6 +339,18 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
             info!("syscall_semaphore_down");
             sys_semaphore_down(args[0])
         }
+        SYSCALL_SEMAPHORE_TRYDOWN => {
+            info!("syscall_semaphore_trydown");
+            sys_semaphore_trydown(args[0])
+        }
+        SYSCALL_SEMAPHORE_DOWN_TIMEOUT => {
+            info!("syscall_semaphore_down_timeout");
+            sys_semaphore_down_timeout(args[0], args[1])
+        }
+        SYSCALL_SEMAPHORE_DESTROY => {
+            info!("syscall_semaphore_destroy");
+            sys_semaphore_destroy(args[0])
+        }
         SYSCALL_CONDVAR_CREATE => {
             info!("syscall_condvar_create");
             sys_condvar_create()
@@ -174,6 +363,78 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
             info!("syscall_condvar_wait");
             sys_condvar_wait(args[0], args[1])
         }
+        SYSCALL_CONDVAR_BROADCAST => {
+            info!("syscall_condvar_broadcast");
+            sys_condvar_broadcast(args[0])
+        }
+        SYSCALL_CONDVAR_WAIT_TIMEOUT => {
+            info!("syscall_condvar_wait_timeout");
+            sys_condvar_wait_timeout(args[0], args[1], args[2])
+        }
+        SYSCALL_CONDVAR_DESTROY => {
+            info!("syscall_condvar_destroy");
+            sys_condvar_destroy(args[0])
+        }
+        SYSCALL_RWLOCK_CREATE => {
+            info!("syscall_rwlock_create");
+            sys_rwlock_create(args[0] == 1)
+        }
+        SYSCALL_RWLOCK_READ => {
+            info!("syscall_rwlock_read");
+            sys_rwlock_read(args[0])
+        }
+        SYSCALL_RWLOCK_WRITE => {
+            info!("syscall_rwlock_write");
+            sys_rwlock_write(args[0])
+        }
+        SYSCALL_RWLOCK_UNLOCK => {
+            info!("syscall_rwlock_unlock");
+            sys_rwlock_unlock(args[0])
+        }
+        SYSCALL_BARRIER_CREATE => {
+            info!("syscall_barrier_create");
+            sys_barrier_create(args[0])
+        }
+        SYSCALL_BARRIER_WAIT => {
+            info!("syscall_barrier_wait");
+            sys_barrier_wait(args[0])
+        }
+        SYSCALL_SEM_OPEN => {
+            info!("syscall_sem_open");
+            sys_sem_open(args[0] as *const u8, args[1] as u32, args[2])
+        }
+        SYSCALL_SEM_CLOSE => {
+            info!("syscall_sem_close");
+            sys_sem_close(args[0])
+        }
+        SYSCALL_SEM_UNLINK => {
+            info!("syscall_sem_unlink");
+            sys_sem_unlink(args[0] as *const u8)
+        }
+        SYSCALL_MQ_OPEN => {
+            info!("syscall_mq_open");
+            sys_mq_open(
+                args[0] as *const u8,
+                args[1] as u32,
+                args[2] as *const MqAttr,
+            )
+        }
+        SYSCALL_MQ_CLOSE => {
+            info!("syscall_mq_close");
+            sys_mq_close(args[0])
+        }
+        SYSCALL_MQ_UNLINK => {
+            info!("syscall_mq_unlink");
+            sys_mq_unlink(args[0] as *const u8)
+        }
+        SYSCALL_MQ_SEND => {
+            info!("syscall_mq_send");
+            sys_mq_send(args[0], args[1] as *const u8, args[2], args[3])
+        }
+        SYSCALL_MQ_RECEIVE => {
+            info!("syscall_mq_receive");
+            sys_mq_receive(args[0], args[1] as *mut u8, args[2], args[3] as *mut usize)
+        }
 
         SYSCALL_FRAMEBUFFER => {
             info!("syscall_framebuffer");
@@ -193,7 +454,7 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
         }
         SYSCALL_GETDENTS => {
             info!("syscall_getdents");
-            sys_getdents(args[0] as *const u8)
+            check_io(false, || sys_getdents(args[0] as *const u8))
         }
         _ => panic!("Unsupported syscall_id: {}", syscall_id),
     }
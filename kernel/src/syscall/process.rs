@@ -1,4 +1,5 @@
-use crate::fs::{OpenFlags, open_file};
+use super::errno::EPERM;
+use crate::fs::open_exec;
 use crate::mm::{translated_ref, translated_refmut, translated_str};
 use crate::task::{
     SignalFlags, current_process, current_task, current_user_token, exit_current_and_run_next,
@@ -57,7 +58,7 @@ pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
             args = args.add(1);
         }
     }
-    if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
+    if let Some(app_inode) = open_exec(path.as_str()) {
         let all_data = app_inode.read_all();
         let process = current_process();
         let argc = args_vec.len();
@@ -110,3 +111,51 @@ pub fn sys_kill(pid: usize, signal: u32) -> isize {
         -1
     }
 }
+
+pub fn sys_getuid() -> isize {
+    current_process().inner_exclusive_access().cred.uid as isize
+}
+
+pub fn sys_geteuid() -> isize {
+    current_process().inner_exclusive_access().cred.euid as isize
+}
+
+pub fn sys_getgid() -> isize {
+    current_process().inner_exclusive_access().cred.gid as isize
+}
+
+pub fn sys_getegid() -> isize {
+    current_process().inner_exclusive_access().cred.egid as isize
+}
+
+/// root 可以任意设置 uid 和 euid，其他用户只能把 euid 切换为自己的 uid 或 euid
+pub fn sys_setuid(uid: u32) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let cred = &mut inner.cred;
+    if cred.euid == 0 {
+        cred.uid = uid;
+        cred.euid = uid;
+    } else if uid == cred.uid || uid == cred.euid {
+        cred.euid = uid;
+    } else {
+        return EPERM;
+    }
+    0
+}
+
+/// 与 `sys_setuid` 相同，作用于 gid 和 egid，特权同样由 euid 决定
+pub fn sys_setgid(gid: u32) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let cred = &mut inner.cred;
+    if cred.euid == 0 {
+        cred.gid = gid;
+        cred.egid = gid;
+    } else if gid == cred.gid || gid == cred.egid {
+        cred.egid = gid;
+    } else {
+        return EPERM;
+    }
+    0
+}
@@ -1,15 +1,48 @@
+use super::errno::{EBUSY, EINVAL, EPERM, ETIMEDOUT};
 use crate::{
-    sync::{Condvar, Mutex, MutexBlocking, MutexSpin, Semaphore},
-    task::{block_current_and_run_next, current_process, current_task},
+    sync::{Barrier, Condvar, Mutex, MutexBlocking, MutexSpin, RwLock, Semaphore},
+    task::{block_current_task, current_process, current_task, schedule},
     timer::{add_timer, get_time_ms},
 };
 use alloc::sync::Arc;
 
+fn get_mutex(mutex_id: usize) -> Option<Arc<dyn Mutex>> {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    process_inner.mutex_list.get(mutex_id)?.clone()
+}
+
+fn get_semaphore(sem_id: usize) -> Option<Arc<Semaphore>> {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    process_inner.semaphore_list.get(sem_id)?.clone()
+}
+
+fn get_condvar(condvar_id: usize) -> Option<Arc<Condvar>> {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    process_inner.condvar_list.get(condvar_id)?.clone()
+}
+
+fn get_rwlock(rwlock_id: usize) -> Option<Arc<RwLock>> {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    process_inner.rwlock_list.get(rwlock_id)?.clone()
+}
+
+fn get_barrier(barrier_id: usize) -> Option<Arc<Barrier>> {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    process_inner.barrier_list.get(barrier_id)?.clone()
+}
+
 pub fn sys_sleep(ms: usize) -> isize {
     let expire_ms = get_time_ms() + ms;
     let task = current_task().unwrap();
+    // 先进入阻塞状态，定时器在这之前到期时唤醒会被忽略
+    let task_cx_ptr = block_current_task();
     add_timer(expire_ms, task);
-    block_current_and_run_next();
+    schedule(task_cx_ptr);
     0
 }
 
@@ -37,23 +70,49 @@ pub fn sys_mutex_create(blocking: bool) -> isize {
 }
 
 pub fn sys_mutex_lock(mutex_id: usize) -> isize {
-    let process = current_process();
-    let process_inner = process.inner_exclusive_access();
-    let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
-    drop(process_inner);
-    drop(process);
+    let Some(mutex) = get_mutex(mutex_id) else {
+        return EINVAL;
+    };
     mutex.lock();
     0
 }
 
 pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
+    let Some(mutex) = get_mutex(mutex_id) else {
+        return EINVAL;
+    };
+    if mutex.unlock() { 0 } else { EPERM }
+}
+
+pub fn sys_mutex_trylock(mutex_id: usize) -> isize {
+    let Some(mutex) = get_mutex(mutex_id) else {
+        return EINVAL;
+    };
+    if mutex.try_lock() { 0 } else { EBUSY }
+}
+
+pub fn sys_mutex_lock_timeout(mutex_id: usize, timeout_ms: usize) -> isize {
+    let Some(mutex) = get_mutex(mutex_id) else {
+        return EINVAL;
+    };
+    if mutex.lock_timeout(timeout_ms) {
+        0
+    } else {
+        ETIMEDOUT
+    }
+}
+
+pub fn sys_mutex_destroy(mutex_id: usize) -> isize {
     let process = current_process();
-    let process_inner = process.inner_exclusive_access();
-    let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
-    drop(process_inner);
-    drop(process);
-    mutex.unlock();
-    0
+    let mut process_inner = process.inner_exclusive_access();
+    match process_inner.mutex_list.get(mutex_id) {
+        Some(Some(mutex)) if mutex.is_busy() => EBUSY,
+        Some(Some(_)) => {
+            process_inner.mutex_list[mutex_id] = None;
+            0
+        }
+        _ => EINVAL,
+    }
 }
 
 pub fn sys_semaphore_create(res_count: usize) -> isize {
@@ -78,23 +137,52 @@ pub fn sys_semaphore_create(res_count: usize) -> isize {
 }
 
 pub fn sys_semaphore_up(sem_id: usize) -> isize {
-    let process = current_process();
-    let process_inner = process.inner_exclusive_access();
-    let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
-    drop(process_inner);
+    let Some(sem) = get_semaphore(sem_id) else {
+        return EINVAL;
+    };
     sem.up();
     0
 }
 
 pub fn sys_semaphore_down(sem_id: usize) -> isize {
-    let process = current_process();
-    let process_inner = process.inner_exclusive_access();
-    let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
-    drop(process_inner);
+    let Some(sem) = get_semaphore(sem_id) else {
+        return EINVAL;
+    };
     sem.down();
     0
 }
 
+pub fn sys_semaphore_trydown(sem_id: usize) -> isize {
+    let Some(sem) = get_semaphore(sem_id) else {
+        return EINVAL;
+    };
+    if sem.try_down() { 0 } else { EBUSY }
+}
+
+pub fn sys_semaphore_down_timeout(sem_id: usize, timeout_ms: usize) -> isize {
+    let Some(sem) = get_semaphore(sem_id) else {
+        return EINVAL;
+    };
+    if sem.down_timeout(timeout_ms) {
+        0
+    } else {
+        ETIMEDOUT
+    }
+}
+
+pub fn sys_semaphore_destroy(sem_id: usize) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    match process_inner.semaphore_list.get(sem_id) {
+        Some(Some(sem)) if sem.is_busy() => EBUSY,
+        Some(Some(_)) => {
+            process_inner.semaphore_list[sem_id] = None;
+            0
+        }
+        _ => EINVAL,
+    }
+}
+
 pub fn sys_condvar_create() -> isize {
     let process = current_process();
     let mut process_inner = process.inner_exclusive_access();
@@ -118,20 +206,128 @@ pub fn sys_condvar_create() -> isize {
 }
 
 pub fn sys_condvar_signal(condvar_id: usize) -> isize {
-    let process = current_process();
-    let process_inner = process.inner_exclusive_access();
-    let condvar = Arc::clone(process_inner.condvar_list[condvar_id].as_ref().unwrap());
-    drop(process_inner);
+    let Some(condvar) = get_condvar(condvar_id) else {
+        return EINVAL;
+    };
     condvar.signal();
     0
 }
 
 pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    let (Some(condvar), Some(mutex)) = (get_condvar(condvar_id), get_mutex(mutex_id)) else {
+        return EINVAL;
+    };
+    if condvar.wait_with_mutex(mutex) {
+        0
+    } else {
+        EPERM
+    }
+}
+
+pub fn sys_condvar_broadcast(condvar_id: usize) -> isize {
+    let Some(condvar) = get_condvar(condvar_id) else {
+        return EINVAL;
+    };
+    condvar.broadcast();
+    0
+}
+
+pub fn sys_condvar_wait_timeout(condvar_id: usize, mutex_id: usize, timeout_ms: usize) -> isize {
+    let (Some(condvar), Some(mutex)) = (get_condvar(condvar_id), get_mutex(mutex_id)) else {
+        return EINVAL;
+    };
+    match condvar.wait_timeout(mutex, timeout_ms) {
+        Some(true) => 0,
+        Some(false) => ETIMEDOUT,
+        None => EPERM,
+    }
+}
+
+pub fn sys_condvar_destroy(condvar_id: usize) -> isize {
     let process = current_process();
-    let process_inner = process.inner_exclusive_access();
-    let condvar = Arc::clone(process_inner.condvar_list[condvar_id].as_ref().unwrap());
-    let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
-    drop(process_inner);
-    condvar.wait_with_mutex(mutex);
+    let mut process_inner = process.inner_exclusive_access();
+    match process_inner.condvar_list.get(condvar_id) {
+        Some(Some(condvar)) if condvar.is_busy() => EBUSY,
+        Some(Some(_)) => {
+            process_inner.condvar_list[condvar_id] = None;
+            0
+        }
+        _ => EINVAL,
+    }
+}
+
+pub fn sys_rwlock_create(writer_first: bool) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let id = if let Some(id) = process_inner
+        .rwlock_list
+        .iter()
+        .enumerate()
+        .find(|(_, item)| item.is_none())
+        .map(|(id, _)| id)
+    {
+        process_inner.rwlock_list[id] = Some(Arc::new(RwLock::new(writer_first)));
+        id
+    } else {
+        process_inner
+            .rwlock_list
+            .push(Some(Arc::new(RwLock::new(writer_first))));
+        process_inner.rwlock_list.len() - 1
+    };
+    id as isize
+}
+
+pub fn sys_rwlock_read(rwlock_id: usize) -> isize {
+    let Some(rwlock) = get_rwlock(rwlock_id) else {
+        return EINVAL;
+    };
+    rwlock.read_lock();
+    0
+}
+
+pub fn sys_rwlock_write(rwlock_id: usize) -> isize {
+    let Some(rwlock) = get_rwlock(rwlock_id) else {
+        return EINVAL;
+    };
+    rwlock.write_lock();
     0
 }
+
+pub fn sys_rwlock_unlock(rwlock_id: usize) -> isize {
+    let Some(rwlock) = get_rwlock(rwlock_id) else {
+        return EINVAL;
+    };
+    if rwlock.unlock() { 0 } else { EPERM }
+}
+
+pub fn sys_barrier_create(parties: usize) -> isize {
+    if parties == 0 {
+        return EINVAL;
+    }
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let id = if let Some(id) = process_inner
+        .barrier_list
+        .iter()
+        .enumerate()
+        .find(|(_, item)| item.is_none())
+        .map(|(id, _)| id)
+    {
+        process_inner.barrier_list[id] = Some(Arc::new(Barrier::new(parties)));
+        id
+    } else {
+        process_inner
+            .barrier_list
+            .push(Some(Arc::new(Barrier::new(parties))));
+        process_inner.barrier_list.len() - 1
+    };
+    id as isize
+}
+
+/// 最后一个到达屏障的线程返回 1，其余线程返回 0
+pub fn sys_barrier_wait(barrier_id: usize) -> isize {
+    let Some(barrier) = get_barrier(barrier_id) else {
+        return EINVAL;
+    };
+    barrier.wait() as isize
+}
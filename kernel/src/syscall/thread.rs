@@ -1,3 +1,6 @@
+use super::errno::{EINVAL, EPERM};
+use crate::config::MAX_PRIORITY;
+use crate::fs::current_cred;
 use crate::trap::TrapContext;
 use crate::{
     mm::kernel_token,
@@ -83,3 +86,20 @@ pub fn sys_gettid() -> isize {
         .unwrap()
         .tid as isize
 }
+
+/// 设置当前线程的优先级，数值越大优先级越高；成功时返回新的优先级
+///
+/// 只有有效用户为 root 的线程可以提高自己的优先级
+pub fn sys_set_priority(priority: usize) -> isize {
+    if priority > MAX_PRIORITY {
+        return EINVAL;
+    }
+    let is_root = current_cred().euid == 0;
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    if priority > task_inner.priority && !is_root {
+        return EPERM;
+    }
+    task_inner.priority = priority;
+    priority as isize
+}
@@ -8,7 +8,11 @@ pub struct TaskManager {
     ready_queue: VecDeque<Arc<TaskControlBlock>>,
 }
 
-/// FIFO
+/// 就绪的任务每被越过这么多次，调度时的优先级提高 1
+const AGING_ROUNDS: usize = 10;
+
+/// 按有效优先级调度，优先级相同的任务之间 FIFO；
+/// 等待越久的任务优先级越高，低优先级的任务不会一直得不到运行
 impl TaskManager {
     pub fn new() -> Self {
         Self {
@@ -21,7 +25,20 @@ impl TaskManager {
     }
     /// 从就绪队列中取出一个进程
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        self.ready_queue.pop_front()
+        let mut best: Option<(usize, usize)> = None;
+        for (id, task) in self.ready_queue.iter().enumerate() {
+            let inner = task.inner_exclusive_access();
+            let priority = inner.effective_priority() + inner.skipped / AGING_ROUNDS;
+            if best.is_none_or(|(_, best_priority)| priority > best_priority) {
+                best = Some((id, priority));
+            }
+        }
+        let task = self.ready_queue.remove(best?.0)?;
+        for other in self.ready_queue.iter() {
+            other.inner_exclusive_access().skipped += 1;
+        }
+        task.inner_exclusive_access().skipped = 0;
+        Some(task)
     }
 
     pub fn remove(&mut self, task: Arc<TaskControlBlock>) {
@@ -49,6 +66,11 @@ pub fn add_task(task: Arc<TaskControlBlock>) {
 
 pub fn wakeup_task(task: Arc<TaskControlBlock>) {
     let mut task_inner = task.inner_exclusive_access();
+    // 带超时的等待可能先后被同步对象和定时器唤醒，被唤醒后任务可能已经就绪甚至正在运行，
+    // 只有仍在阻塞的任务才加入就绪队列
+    if task_inner.task_status != TaskStatus::Blocked {
+        return;
+    }
     task_inner.task_status = TaskStatus::Ready;
     drop(task_inner);
     add_task(task);
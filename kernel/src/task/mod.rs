@@ -10,6 +10,7 @@ mod task;
 
 use crate::fs::{OpenFlags, open_file};
 use crate::sbi::shutdown;
+use crate::sync::Mutex;
 use crate::timer::remove_timer;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
@@ -23,6 +24,7 @@ use switch::__switch;
 
 pub use id::{KernelStack, PidHandle, pid_alloc};
 pub use manager::{add_task, pid2process, wakeup_task};
+pub use process::Credentials;
 pub use processor::{
     current_kstack_top, current_process, current_task, current_trap_cx, current_trap_cx_user_va,
     current_user_token, run_tasks, schedule, take_current_task,
@@ -66,6 +68,18 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     task_inner.res = None;
 
     drop(task_inner);
+
+    // 释放该线程仍持有的互斥锁，并把锁交给下一个等待者
+    let mutexes: Vec<Arc<dyn Mutex>> = process
+        .inner_exclusive_access()
+        .mutex_list
+        .iter()
+        .flatten()
+        .cloned()
+        .collect();
+    for mutex in mutexes {
+        mutex.release_owned_by(&task);
+    }
     drop(task);
 
     // 如果时主线程
@@ -104,6 +118,13 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         for task in process_inner.tasks.iter().filter(|t| t.is_some()) {
             let task = task.as_ref().unwrap();
             remove_inactive_task(Arc::clone(&task));
+            // 具名信号量和消息队列由多个进程共享，需要把本进程的线程从其等待队列中移除
+            for sem in process_inner.semaphore_list.iter().flatten() {
+                sem.remove_waiter(task);
+            }
+            for mqd in process_inner.mq_list.iter().flatten() {
+                mqd.queue.remove_waiter(task);
+            }
             let mut task_inner = task.inner_exclusive_access();
             if let Some(res) = task_inner.res.take() {
                 recycle_res.push(res);
@@ -117,6 +138,13 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         process_inner.children.clear();
         process_inner.memory_set.recycle_data_pages();
         process_inner.fd_table.clear();
+        // 同步对象的等待队列中可能还有本进程其他线程的引用，一并释放
+        process_inner.mutex_list.clear();
+        process_inner.semaphore_list.clear();
+        process_inner.condvar_list.clear();
+        process_inner.rwlock_list.clear();
+        process_inner.barrier_list.clear();
+        process_inner.mq_list.clear();
         while process_inner.tasks.len() > 1 {
             process_inner.tasks.pop();
         }
@@ -4,7 +4,7 @@ use super::task::TaskControlBlock;
 use super::{PidHandle, SignalFlags, pid_alloc};
 use crate::fs::{File, Stdin, Stdout};
 use crate::mm::{KERNEL_SPACE, MemorySet, translated_refmut};
-use crate::sync::{Condvar, Mutex, Semaphore};
+use crate::sync::{Barrier, Condvar, MqDescriptor, Mutex, RwLock, Semaphore};
 use crate::sync::{UPIntrFreeCell, UPIntrRefMut};
 use crate::trap::{TrapContext, trap_handler};
 use alloc::string::String;
@@ -12,6 +12,15 @@ use alloc::sync::{Arc, Weak};
 use alloc::vec;
 use alloc::vec::Vec;
 
+/// 进程的用户和组身份，权限检查使用有效 id
+#[derive(Clone, Copy, Default)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    pub euid: u32,
+    pub egid: u32,
+}
+
 pub struct ProcessControlBlock {
     // immutable
     pub pid: PidHandle,
@@ -34,6 +43,10 @@ pub struct ProcessControlBlockInner {
     pub mutex_list: Vec<Option<Arc<dyn Mutex>>>,
     pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
     pub condvar_list: Vec<Option<Arc<Condvar>>>,
+    pub rwlock_list: Vec<Option<Arc<RwLock>>>,
+    pub barrier_list: Vec<Option<Arc<Barrier>>>,
+    pub mq_list: Vec<Option<MqDescriptor>>,
+    pub cred: Credentials,
 }
 
 impl ProcessControlBlockInner {
@@ -98,6 +111,10 @@ impl ProcessControlBlock {
                     mutex_list: Vec::new(),
                     semaphore_list: Vec::new(),
                     condvar_list: Vec::new(),
+                    rwlock_list: Vec::new(),
+                    barrier_list: Vec::new(),
+                    mq_list: Vec::new(),
+                    cred: Credentials::default(),
                 })
             },
         });
@@ -226,6 +243,10 @@ impl ProcessControlBlock {
                     mutex_list: Vec::new(),
                     semaphore_list: Vec::new(),
                     condvar_list: Vec::new(),
+                    rwlock_list: Vec::new(),
+                    barrier_list: Vec::new(),
+                    mq_list: Vec::new(),
+                    cred: parent_inner.cred,
                 })
             },
         });
@@ -1,11 +1,13 @@
 use super::id::kstack_alloc;
 use super::process::ProcessControlBlock;
 use super::{KernelStack, TaskContext, id::TaskUserRes};
+use crate::config::DEFAULT_PRIORITY;
 use crate::trap::TrapContext;
 use crate::{
     mm::PhysPageNum,
-    sync::{UPIntrFreeCell, UPIntrRefMut},
+    sync::{MutexBlockingInner, UPIntrFreeCell, UPIntrRefMut},
 };
+use alloc::collections::BTreeMap;
 use alloc::sync::{Arc, Weak};
 
 pub struct TaskControlBlock {
@@ -20,6 +22,14 @@ pub struct TaskControlBlockInner {
     pub task_cx: TaskContext,
     pub task_status: TaskStatus,
     pub exit_code: Option<i32>,
+    /// 线程自身的优先级
+    pub priority: usize,
+    /// 优先级继承：所持有的每把阻塞互斥锁（以地址区分）上等待者的最高优先级
+    pub inherited_priority: BTreeMap<usize, usize>,
+    /// 正在等待的阻塞互斥锁，优先级继承沿着它传递给更远的持有者
+    pub blocked_on: Option<Weak<UPIntrFreeCell<MutexBlockingInner>>>,
+    /// 在就绪队列中被其他任务越过的次数，调度时据此提高优先级
+    pub skipped: usize,
 }
 
 impl TaskControlBlock {
@@ -42,6 +52,10 @@ impl TaskControlBlock {
                     task_cx: TaskContext::goto_trap_return(kstack_top),
                     task_status: TaskStatus::Ready,
                     exit_code: None,
+                    priority: DEFAULT_PRIORITY,
+                    inherited_priority: BTreeMap::new(),
+                    blocked_on: None,
+                    skipped: 0,
                 })
             },
         }
@@ -63,6 +77,14 @@ impl TaskControlBlockInner {
         self.trap_cx_ppn.get_mut()
     }
 
+    /// 调度时使用的有效优先级，可能因优先级继承而高于线程自身的优先级
+    pub fn effective_priority(&self) -> usize {
+        self.inherited_priority
+            .values()
+            .copied()
+            .fold(self.priority, usize::max)
+    }
+
     #[allow(unused)]
     pub fn get_status(&self) -> TaskStatus {
         self.task_status
@@ -1,4 +1,4 @@
-use crate::config::CLOCK_FREQ;
+use crate::config::{CLOCK_FREQ, VIRT_RTC};
 use crate::sbi::set_timer;
 use crate::sync::UPIntrFreeCell;
 use crate::task::{TaskControlBlock, wakeup_task};
@@ -18,6 +18,15 @@ pub fn get_time_ms() -> usize {
     time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
 }
 
+/// 从 goldfish RTC 读取墙上时间，单位为秒
+pub fn get_wall_time_sec() -> u32 {
+    const NSEC_PER_SEC: u64 = 1_000_000_000;
+    // 读取低 32 位时会锁存高 32 位，必须先读低位
+    let low = unsafe { core::ptr::read_volatile(VIRT_RTC as *const u32) };
+    let high = unsafe { core::ptr::read_volatile((VIRT_RTC + 4) as *const u32) };
+    ((((high as u64) << 32) | low as u64) / NSEC_PER_SEC) as u32
+}
+
 pub fn set_next_trigger() {
     set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
 }
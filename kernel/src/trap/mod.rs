@@ -1,6 +1,7 @@
 mod context;
 
 use crate::config::TRAMPOLINE;
+use crate::fs::flush_if_due;
 use crate::syscall::syscall;
 use crate::task::{
     SignalFlags, check_signals_of_current, current_add_signal, current_trap_cx,
@@ -73,7 +74,9 @@ pub fn trap_handler() -> ! {
 
             enable_supervisor_interrupt();
 
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
+            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12], cx.x[13]]);
+            // 定期刷新文件系统放在系统调用返回前，时钟中断中不能等待文件系统的锁
+            flush_if_due();
             cx = current_trap_cx();
             cx.x[10] = result as usize;
         }
@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use user_lib::errno::{EACCES, EINVAL, EISDIR, ENOENT, ENOTTY};
+use user_lib::*;
+
+#[unsafe(no_mangle)]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    // chattr +c|-c FILE，不带模式时打印当前的标志
+    let (mode, path) = match argc {
+        3 if argv[1] == "+c" || argv[1] == "-c" => (Some(argv[1]), argv[2]),
+        2 => (None, argv[1]),
+        _ => {
+            println!("usage: chattr [+c|-c] FILE");
+            return 1;
+        }
+    };
+    let flags = if mode.is_some() {
+        OpenFlags::WRONLY
+    } else {
+        OpenFlags::RDONLY
+    };
+    let fd = open(path, flags);
+    if fd < 0 {
+        match fd {
+            ENOENT => println!("chattr: {}: No such file or directory", path),
+            EACCES => println!("chattr: {}: Permission denied", path),
+            EISDIR => println!("chattr: {}: Is a directory", path),
+            _ => println!("chattr: {}: error {}", path, fd),
+        }
+        return 1;
+    }
+    let fd = fd as usize;
+    let mut flags = 0u32;
+    let mut ret = ioctl(fd, FS_IOC_GETFLAGS, &mut flags);
+    if ret == 0 {
+        match mode {
+            Some("+c") => flags |= FS_COMPR_FL,
+            Some(_) => flags &= !FS_COMPR_FL,
+            None => {
+                let compressed = if flags & FS_COMPR_FL != 0 { 'c' } else { '-' };
+                println!("{} {}", compressed, path);
+            }
+        }
+        if mode.is_some() {
+            ret = ioctl(fd, FS_IOC_SETFLAGS, &mut flags);
+        }
+    }
+    close(fd);
+    match ret {
+        0 => 0,
+        ENOTTY => {
+            println!("chattr: {}: Flags not supported", path);
+            1
+        }
+        // 压缩只能在文件还没有数据时打开，打开后不能关闭
+        EINVAL => {
+            println!(
+                "chattr: {}: Compression can only be set on empty files",
+                path
+            );
+            1
+        }
+        _ => {
+            println!("chattr: {}: error {}", path, ret);
+            1
+        }
+    }
+}
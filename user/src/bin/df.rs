@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use user_lib::*;
+
+/// 已用部分占总量的百分比，向上取整
+fn percent(used: u64, total: u64) -> u64 {
+    if total == 0 {
+        0
+    } else {
+        (used * 100).div_ceil(total)
+    }
+}
+
+#[unsafe(no_mangle)]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let paths: &[&str] = if argc == 1 { &["/"] } else { &argv[1..] };
+    println!("Path\t1K-blocks\tUsed\tAvailable\tUse%\tInodes\tIUsed\tIFree\tIUse%");
+    for path in paths {
+        let mut st = StatFs::new();
+        if statfs(path, &mut st) != 0 {
+            println!("df: {}: No such file or directory", path);
+            return 1;
+        }
+        let kib = |blocks: u64| blocks * st.bsize as u64 / 1024;
+        let used = st.blocks - st.bfree;
+        let iused = st.files - st.ffree;
+        println!(
+            "{}\t{}\t{}\t{}\t{}%\t{}\t{}\t{}\t{}%",
+            path,
+            kib(st.blocks),
+            kib(used),
+            kib(st.bavail),
+            percent(used, st.blocks),
+            st.files,
+            iused,
+            st.ffree,
+            percent(iused, st.files)
+        );
+    }
+    0
+}
@@ -10,7 +10,10 @@ use user_lib::{OpenFlags, close, open, read, write};
 pub fn main() -> i32 {
     let test_str = "Hello, world!";
     let filea = "filea\0";
-    let fd = open(filea, OpenFlags::CREATE | OpenFlags::WRONLY);
+    let fd = open(
+        filea,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
     assert!(fd > 0);
     let fd = fd as usize;
     write(fd, test_str.as_bytes());
@@ -0,0 +1,74 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::{EAGAIN, EEXIST, ENOENT};
+use user_lib::{IpcFlags, MqAttr, exit, fork, waitpid};
+use user_lib::{mq_close, mq_open, mq_receive, mq_send, mq_unlink};
+use user_lib::{sem_close, sem_open, sem_unlink, semaphore_down, semaphore_up};
+
+const MQ_NAME: &str = "ipc_mqueue_test\0";
+const SEM_NAME: &str = "ipc_mqueue_sem\0";
+const MSG_SIZE: usize = 32;
+
+// 子进程按此顺序发送，父进程应当按优先级从高到低、同优先级先进先出的顺序收到
+const MESSAGES: [(&str, usize); 4] = [("low", 1), ("high-1", 5), ("high-2", 5), ("mid", 3)];
+const EXPECTED: [&str; 4] = ["high-1", "high-2", "mid", "low"];
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    let attr = MqAttr {
+        max_msg: MESSAGES.len(),
+        msg_size: MSG_SIZE,
+    };
+    assert_eq!(mq_open(MQ_NAME, IpcFlags::empty(), None), ENOENT);
+    let mqd = mq_open(MQ_NAME, IpcFlags::CREATE | IpcFlags::EXCL, Some(&attr));
+    assert!(mqd >= 0);
+    assert_eq!(
+        mq_open(MQ_NAME, IpcFlags::CREATE | IpcFlags::EXCL, Some(&attr)),
+        EEXIST
+    );
+    let sem_id = sem_open(SEM_NAME, IpcFlags::CREATE, 0);
+    assert!(sem_id >= 0);
+
+    let pid = fork();
+    if pid == 0 {
+        // 子进程通过名字重新打开同一个队列和信号量
+        let mqd = mq_open(MQ_NAME, IpcFlags::empty(), None) as usize;
+        let sem_id = sem_open(SEM_NAME, IpcFlags::empty(), 0) as usize;
+        for (msg, priority) in MESSAGES.iter() {
+            assert_eq!(mq_send(mqd, msg.as_bytes(), *priority), 0);
+        }
+        semaphore_up(sem_id);
+        mq_close(mqd);
+        sem_close(sem_id);
+        exit(0);
+    }
+
+    // 等子进程发完所有消息后再接收，才能检验优先级顺序
+    semaphore_down(sem_id as usize);
+    let mut buf = [0u8; MSG_SIZE];
+    let mut priority = 0;
+    for expected in EXPECTED.iter() {
+        let len = mq_receive(mqd as usize, &mut buf, &mut priority);
+        assert_eq!(&buf[..len as usize], expected.as_bytes());
+    }
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+
+    // 非阻塞模式下队列为空时立即返回
+    let nb_mqd = mq_open(MQ_NAME, IpcFlags::NONBLOCK, None);
+    assert_eq!(mq_receive(nb_mqd as usize, &mut buf, &mut priority), EAGAIN);
+
+    assert_eq!(mq_close(nb_mqd as usize), 0);
+    assert_eq!(mq_close(mqd as usize), 0);
+    assert_eq!(mq_unlink(MQ_NAME), 0);
+    assert_eq!(mq_unlink(MQ_NAME), ENOENT);
+    assert_eq!(sem_close(sem_id as usize), 0);
+    assert_eq!(sem_unlink(SEM_NAME), 0);
+    println!("ipc_mqueue passed!");
+    0
+}
@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use user_lib::errno::{EEXIST, ENOENT, EPERM};
+use user_lib::*;
+
+#[unsafe(no_mangle)]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let (soft, target, link_name) = match argc {
+        3 => (false, argv[1], argv[2]),
+        4 if argv[1] == "-s" => (true, argv[2], argv[3]),
+        _ => {
+            println!("usage: ln [-s] <target> <link_name>");
+            return 1;
+        }
+    };
+    let ret = if soft {
+        symlink(target, link_name)
+    } else {
+        link(target, link_name)
+    };
+    match ret {
+        0 => 0,
+        ENOENT => {
+            println!(
+                "ln: failed to create link {}: No such file or directory",
+                link_name
+            );
+            1
+        }
+        EEXIST => {
+            println!("ln: failed to create link {}: File exists", link_name);
+            1
+        }
+        EPERM => {
+            println!("ln: {}: hard link not allowed for directory", target);
+            1
+        }
+        _ => panic!(),
+    }
+}
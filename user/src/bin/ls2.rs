@@ -32,25 +32,28 @@ fn main(argc: usize, argv: &[&str]) -> i32 {
             }
             _ => panic!(),
         }
-        match stat.mode as usize {
-            REG => {
+        match stat.file_type() {
+            S_IFREG => {
                 println!("{}", target);
             }
-            DIR => {
+            S_IFDIR => {
                 let mut buf = vec![0u8; stat.size as usize];
                 read(fd as usize, &mut buf);
-                for i in 2..buf.len() / DIRENT_SZ {
-                    let offset = i * DIRENT_SZ;
-                    let dirent = unsafe { &*(buf.as_ptr().add(offset) as *const DirEntry) };
-                    let len = dirent
-                        .name
-                        .iter()
-                        .position(|&v| v == 0)
-                        .unwrap_or(dirent.name.len());
-                    let name = str::from_utf8(&dirent.name[0..len]).unwrap();
-                    print!("{}\t", name);
+                let mut offset = 0;
+                let mut count = 0;
+                while offset + DIRENT_HEADER_SZ <= buf.len() {
+                    let dirent = unsafe { &*(buf.as_ptr().add(offset) as *const DirEntryHeader) };
+                    if dirent.rec_len == 0 {
+                        break;
+                    }
+                    if dirent.name_len > 0 {
+                        let name = &buf[offset + DIRENT_HEADER_SZ..][..dirent.name_len as usize];
+                        print!("{}\t", str::from_utf8(name).unwrap());
+                        count += 1;
+                    }
+                    offset += dirent.rec_len as usize;
                 }
-                if buf.len() / DIRENT_SZ > 2 {
+                if count > 0 {
                     println!("");
                 }
             }
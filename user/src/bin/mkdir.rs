@@ -5,6 +5,7 @@ extern crate alloc;
 extern crate user_lib;
 
 use core::str;
+use user_lib::errno::EACCES;
 use user_lib::*;
 
 #[unsafe(no_mangle)]
@@ -22,6 +23,7 @@ fn main(argc: usize, argv: &[&str]) -> i32 {
                 target
             ),
             -2 => println!("cannot create directory {}: File exists", target),
+            EACCES => println!("cannot create directory {}: Permission denied", target),
             _ => panic!(),
         }
     }
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::format;
+use alloc::string::String;
+use user_lib::errno::{EEXIST, EINVAL, ENOENT};
+use user_lib::*;
+
+/// 目标是已存在的目录时，移动到该目录下并保留原来的文件名
+fn is_dir(path: &str) -> bool {
+    let mut st = Stat::new();
+    stat(path, &mut st) == 0 && st.is_dir()
+}
+
+#[unsafe(no_mangle)]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc != 3 {
+        println!("usage: mv <source> <dest>");
+        return 1;
+    }
+    let (source, dest) = (argv[1], argv[2]);
+    let dest = if is_dir(dest) {
+        let name = source.rsplit('/').next().unwrap();
+        format!("{}/{}", dest.trim_end_matches('/'), name)
+    } else {
+        String::from(dest)
+    };
+    match rename(source, dest.as_str()) {
+        0 => 0,
+        ENOENT => {
+            println!("mv: cannot move {}: No such file or directory", source);
+            1
+        }
+        EINVAL => {
+            println!("mv: cannot move {} to a subdirectory of itself", source);
+            1
+        }
+        EEXIST => {
+            println!("mv: cannot overwrite {}", dest);
+            1
+        }
+        _ => panic!(),
+    }
+}
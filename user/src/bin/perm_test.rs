@@ -0,0 +1,54 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::{EACCES, EISDIR, EPERM};
+use user_lib::{
+    OpenFlags, close, exit, fork, geteuid, getuid, mkdir, open, set_priority, setuid, waitpid,
+};
+
+const USER: u32 = 1000;
+
+// 普通用户不能修改 root 拥有的文件和目录，也不能重新变回 root
+fn as_user() -> ! {
+    assert_eq!(setuid(USER), 0);
+    assert_eq!((getuid(), geteuid()), (USER, USER));
+    let fd = open("/bin/initproc\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(open("/bin/initproc\0", OpenFlags::WRONLY), -1);
+    assert_eq!(
+        open("/perm_test_file\0", OpenFlags::CREATE | OpenFlags::WRONLY),
+        -1
+    );
+    assert_eq!(mkdir("/perm_test_dir"), EACCES);
+    assert_eq!(setuid(0), EPERM);
+    // 普通用户只能降低自己的优先级
+    assert_eq!(set_priority(8), 8);
+    assert_eq!(set_priority(9), EPERM);
+    exit(0)
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    assert_eq!((getuid(), geteuid()), (0, 0));
+    let pid = fork();
+    if pid == 0 {
+        as_user();
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    // 身份只在子进程中改变
+    assert_eq!(getuid(), 0);
+    // 目录只能以只读方式打开
+    assert_eq!(open("/bin\0", OpenFlags::WRONLY), EISDIR);
+    assert_eq!(open("/bin\0", OpenFlags::RDWR), EISDIR);
+    let fd = open("/bin\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    println!("perm_test passed!");
+    0
+}
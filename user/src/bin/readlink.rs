@@ -0,0 +1,26 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use core::str;
+use user_lib::*;
+
+#[unsafe(no_mangle)]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 1 {
+        println!("missing operand");
+        return 1;
+    }
+    let mut buf = [0u8; 256];
+    for path in &argv[1..] {
+        let len = readlink(path, &mut buf);
+        if len < 0 {
+            println!("readlink: {}: not a symbolic link", path);
+            return 1;
+        }
+        println!("{}", str::from_utf8(&buf[..len as usize]).unwrap());
+    }
+    0
+}
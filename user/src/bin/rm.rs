@@ -5,19 +5,28 @@ extern crate alloc;
 extern crate user_lib;
 
 use core::str;
+use user_lib::errno::EACCES;
 use user_lib::*;
 
 #[unsafe(no_mangle)]
 pub fn main(argc: usize, argv: &[&str]) -> i32 {
-    if argc == 1 {
+    let recursive = argc > 1 && (argv[1] == "-r" || argv[1] == "-R");
+    let targets = if recursive { &argv[2..] } else { &argv[1..] };
+    if targets.is_empty() {
         println!("missing operand");
         return 1;
     }
-    for target in &argv[1..] {
-        match unlink(target, 0) {
+    for target in targets {
+        let mut ret = unlink(target, 0);
+        if ret == -2 && recursive {
+            // 目标是目录，由内核删除整棵子树
+            ret = unlink(target, AT_REMOVEDIR | AT_RECURSIVE);
+        }
+        match ret {
             0 => println!("remove success"),
             -1 => println!("cannot remove {}, Nosuch file or directory", target),
             -2 => println!("cannot remove {}, Is a directory", target),
+            EACCES => println!("cannot remove {}, Permission denied", target),
             _ => panic!(),
         }
     }
@@ -5,6 +5,7 @@ extern crate alloc;
 extern crate user_lib;
 
 use core::str;
+use user_lib::errno::EACCES;
 use user_lib::*;
 
 #[unsafe(no_mangle)]
@@ -19,6 +20,7 @@ pub fn main(argc: usize, argv: &[&str]) -> i32 {
             -1 => println!("failed to remove '{}': No such file or directory", target),
             -2 => println!("failed to remove '{}': Not a directory", target),
             -3 => println!("failed to remove '{}': Directory not empty", target),
+            EACCES => println!("failed to remove '{}': Permission denied", target),
             _ => panic!(),
         }
     }
@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use user_lib::*;
+
+fn type_name(stat: &Stat) -> &'static str {
+    match stat.file_type() {
+        S_IFREG => "regular file",
+        S_IFDIR => "directory",
+        S_IFLNK => "symbolic link",
+        S_IFCHR => "character device",
+        _ => "unknown",
+    }
+}
+
+#[unsafe(no_mangle)]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 1 {
+        println!("missing operand");
+        return 1;
+    }
+    for path in &argv[1..] {
+        let mut st = Stat::new();
+        if lstat(path, &mut st) != 0 {
+            println!("stat: cannot stat '{}': No such file or directory", path);
+            return 1;
+        }
+        println!("  File: {}", path);
+        println!(
+            "  Size: {}\tBlocks: {}\tIO Block: {}\t{}",
+            st.size,
+            st.blocks,
+            st.blksize,
+            type_name(&st)
+        );
+        println!("Inode: {}\tLinks: {}", st.ino, st.nlink);
+        println!(
+            "Access: ({:04o})\tUid: {}\tGid: {}",
+            st.mode & 0o7777,
+            st.uid,
+            st.gid
+        );
+        println!("Access: {}", st.atime);
+        println!("Modify: {}", st.mtime);
+        println!("Change: {}", st.ctime);
+    }
+    0
+}
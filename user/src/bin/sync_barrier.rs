@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::errno::EINVAL;
+use user_lib::{barrier_create, barrier_wait};
+use user_lib::{exit, sleep, thread_create, waittid};
+
+const BARRIER_ID: usize = 0;
+const THREAD_COUNT: usize = 4;
+const PHASE: usize = 3;
+
+// 每个线程在每一阶段将自己的计数加一
+static mut PROGRESS: [usize; THREAD_COUNT] = [0; THREAD_COUNT];
+
+fn worker(id: usize) -> ! {
+    for phase in 0..PHASE {
+        // 不同线程到达屏障的时间不同
+        sleep(10 * (id + 1));
+        unsafe {
+            PROGRESS[id] += 1;
+        }
+        barrier_wait(BARRIER_ID);
+        // 越过屏障后，所有线程都应已完成本阶段
+        let progress = unsafe { PROGRESS };
+        assert!(progress.iter().all(|count| *count > phase));
+        barrier_wait(BARRIER_ID);
+    }
+    exit(0)
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    assert_eq!(barrier_create(THREAD_COUNT) as usize, BARRIER_ID);
+    assert_eq!(barrier_create(0), EINVAL);
+    let mut threads = Vec::new();
+    for i in 0..THREAD_COUNT {
+        threads.push(thread_create(worker as usize, i));
+    }
+    for thread in threads.iter() {
+        waittid(*thread as usize);
+    }
+    println!("sync_barrier passed!");
+    0
+}
@@ -0,0 +1,86 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::errno::{EBUSY, EINVAL};
+use user_lib::{condvar_create, condvar_destroy, condvar_signal, condvar_wait};
+use user_lib::{exit, sleep, thread_create, waittid};
+use user_lib::{mutex_blocking_create, mutex_destroy, mutex_lock, mutex_unlock};
+use user_lib::{semaphore_create, semaphore_destroy, semaphore_down, semaphore_up};
+
+const ROUNDS: usize = 100;
+
+static mut MUTEX_ID: usize = 0;
+
+// 持有互斥锁后直接退出，内核需要把锁交给等待者
+fn exit_with_lock() -> ! {
+    mutex_lock(unsafe { MUTEX_ID });
+    sleep(20);
+    exit(0)
+}
+
+fn sem_waiter(sem_id: usize) -> ! {
+    semaphore_down(sem_id);
+    exit(0)
+}
+
+fn condvar_waiter(condvar_id: usize) -> ! {
+    let mutex_id = unsafe { MUTEX_ID };
+    mutex_lock(mutex_id);
+    condvar_wait(condvar_id, mutex_id);
+    mutex_unlock(mutex_id);
+    exit(0)
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    // 反复创建和销毁，释放的编号会被重新使用
+    for _ in 0..ROUNDS {
+        assert_eq!(mutex_blocking_create(), 0);
+        assert_eq!(semaphore_create(1), 0);
+        assert_eq!(condvar_create(), 0);
+        assert_eq!(mutex_destroy(0), 0);
+        assert_eq!(semaphore_destroy(0), 0);
+        assert_eq!(condvar_destroy(0), 0);
+    }
+    assert_eq!(mutex_destroy(0), EINVAL);
+    assert_eq!(semaphore_destroy(0), EINVAL);
+    assert_eq!(condvar_destroy(0), EINVAL);
+
+    // 持锁线程退出后，等待者仍然能够获得锁
+    let mutex_id = mutex_blocking_create() as usize;
+    unsafe {
+        MUTEX_ID = mutex_id;
+    }
+    let tid = thread_create(exit_with_lock as usize, 0);
+    sleep(10);
+    assert_eq!(mutex_destroy(mutex_id), EBUSY);
+    mutex_lock(mutex_id);
+    waittid(tid as usize);
+    mutex_unlock(mutex_id);
+
+    // 有线程等待时不能销毁
+    let sem_id = semaphore_create(0) as usize;
+    let tid = thread_create(sem_waiter as usize, sem_id);
+    sleep(10);
+    assert_eq!(semaphore_destroy(sem_id), EBUSY);
+    semaphore_up(sem_id);
+    waittid(tid as usize);
+    assert_eq!(semaphore_destroy(sem_id), 0);
+
+    let condvar_id = condvar_create() as usize;
+    let tid = thread_create(condvar_waiter as usize, condvar_id);
+    sleep(10);
+    assert_eq!(condvar_destroy(condvar_id), EBUSY);
+    mutex_lock(mutex_id);
+    condvar_signal(condvar_id);
+    mutex_unlock(mutex_id);
+    waittid(tid as usize);
+    assert_eq!(condvar_destroy(condvar_id), 0);
+    assert_eq!(mutex_destroy(mutex_id), 0);
+
+    println!("sync_destroy passed!");
+    0
+}
@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{exit, get_time, set_priority, sleep, thread_create, waittid};
+use user_lib::{mutex_blocking_create, mutex_lock, mutex_unlock};
+
+const OUTER_MUTEX_ID: usize = 0;
+const INNER_MUTEX_ID: usize = 1;
+const LOW_PRIORITY: usize = 1;
+const WAITER_PRIORITY: usize = 2;
+const MID_PRIORITY: usize = 10;
+const HIGH_PRIORITY: usize = 20;
+
+static FINISHED: AtomicUsize = AtomicUsize::new(0);
+static HIGH_FINISHED: AtomicUsize = AtomicUsize::new(0);
+static MID_FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+fn busy_for(ms: isize) {
+    let start = get_time();
+    while get_time() - start < ms {}
+}
+
+fn low() -> ! {
+    set_priority(LOW_PRIORITY);
+    mutex_lock(INNER_MUTEX_ID);
+    busy_for(50);
+    mutex_unlock(INNER_MUTEX_ID);
+    exit(0)
+}
+
+fn waiter() -> ! {
+    set_priority(WAITER_PRIORITY);
+    mutex_lock(OUTER_MUTEX_ID);
+    // 持有外层锁时阻塞在内层锁上，高优先级线程的优先级要经过这里传递给 low
+    mutex_lock(INNER_MUTEX_ID);
+    mutex_unlock(INNER_MUTEX_ID);
+    mutex_unlock(OUTER_MUTEX_ID);
+    exit(0)
+}
+
+fn mid() -> ! {
+    set_priority(MID_PRIORITY);
+    busy_for(300);
+    MID_FINISHED.store(FINISHED.fetch_add(1, Ordering::SeqCst), Ordering::SeqCst);
+    exit(0)
+}
+
+fn high() -> ! {
+    set_priority(HIGH_PRIORITY);
+    sleep(20);
+    mutex_lock(OUTER_MUTEX_ID);
+    HIGH_FINISHED.store(FINISHED.fetch_add(1, Ordering::SeqCst), Ordering::SeqCst);
+    mutex_unlock(OUTER_MUTEX_ID);
+    exit(0)
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    assert_eq!(mutex_blocking_create() as usize, OUTER_MUTEX_ID);
+    assert_eq!(mutex_blocking_create() as usize, INNER_MUTEX_ID);
+
+    // 主线程以最低优先级轮询等待，不影响其他线程的调度
+    set_priority(0);
+    let low_tid = thread_create(low as usize, 0);
+    let waiter_tid = thread_create(waiter as usize, 0);
+    let mid_tid = thread_create(mid as usize, 0);
+    let high_tid = thread_create(high as usize, 0);
+    for tid in [low_tid, waiter_tid, mid_tid, high_tid] {
+        waittid(tid as usize);
+    }
+    // 优先级沿着 high -> waiter -> low 传递，高优先级线程应当先于中优先级线程完成
+    assert!(HIGH_FINISHED.load(Ordering::SeqCst) < MID_FINISHED.load(Ordering::SeqCst));
+    println!("sync_pi_chain passed!");
+    0
+}
@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::errno::EPERM;
+use user_lib::{exit, sleep, thread_create, waittid};
+use user_lib::{rwlock_read, rwlock_unlock, rwlock_write, rwlock_writer_first_create};
+
+const RWLOCK_ID: usize = 0;
+const READER_COUNT: usize = 4;
+const WRITER_COUNT: usize = 2;
+const ROUND: usize = 5;
+
+// 写者总是成对地修改 A 和 B，读者在任何时刻都应看到 A == B
+static mut A: usize = 0;
+static mut B: usize = 0;
+
+fn reader() -> ! {
+    for _ in 0..ROUND {
+        rwlock_read(RWLOCK_ID);
+        let (a, b) = unsafe { (A, B) };
+        sleep(2);
+        assert_eq!(a, unsafe { B });
+        assert_eq!(a, b);
+        rwlock_unlock(RWLOCK_ID);
+        sleep(3);
+    }
+    exit(0)
+}
+
+fn writer() -> ! {
+    for _ in 0..ROUND {
+        rwlock_write(RWLOCK_ID);
+        unsafe {
+            A += 1;
+        }
+        sleep(2);
+        unsafe {
+            B += 1;
+        }
+        rwlock_unlock(RWLOCK_ID);
+        sleep(3);
+    }
+    exit(0)
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    assert_eq!(rwlock_writer_first_create() as usize, RWLOCK_ID);
+    let mut threads = Vec::new();
+    for _ in 0..WRITER_COUNT {
+        threads.push(thread_create(writer as usize, 0));
+    }
+    for _ in 0..READER_COUNT {
+        threads.push(thread_create(reader as usize, 0));
+    }
+    for thread in threads.iter() {
+        waittid(*thread as usize);
+    }
+    assert_eq!(unsafe { A }, WRITER_COUNT * ROUND);
+    assert_eq!(unsafe { B }, WRITER_COUNT * ROUND);
+    // 没有持有锁的线程不能解锁
+    assert_eq!(rwlock_unlock(RWLOCK_ID), EPERM);
+    println!("sync_rwlock passed!");
+    0
+}
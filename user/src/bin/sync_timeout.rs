@@ -0,0 +1,88 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::errno::{EBUSY, ETIMEDOUT};
+use user_lib::{condvar_broadcast, condvar_create, condvar_wait_timeout};
+use user_lib::{exit, get_time, sleep, thread_create, waittid};
+use user_lib::{
+    mutex_blocking_create, mutex_lock, mutex_lock_timeout, mutex_trylock, mutex_unlock,
+};
+use user_lib::{semaphore_create, semaphore_down_timeout, semaphore_trydown, semaphore_up};
+
+const MUTEX_ID: usize = 0;
+const SEM_ID: usize = 0;
+const CONDVAR_ID: usize = 0;
+const WAITER_COUNT: usize = 3;
+
+static mut READY: bool = false;
+
+fn holder() -> ! {
+    mutex_lock(MUTEX_ID);
+    sleep(100);
+    mutex_unlock(MUTEX_ID);
+    exit(0)
+}
+
+fn waiter() -> ! {
+    mutex_lock(MUTEX_ID);
+    while !unsafe { READY } {
+        // broadcast 之前最多等待一秒，足够在正常情况下被唤醒
+        assert_eq!(condvar_wait_timeout(CONDVAR_ID, MUTEX_ID, 1000), 0);
+    }
+    mutex_unlock(MUTEX_ID);
+    exit(0)
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    assert_eq!(mutex_blocking_create() as usize, MUTEX_ID);
+    assert_eq!(semaphore_create(0) as usize, SEM_ID);
+    assert_eq!(condvar_create() as usize, CONDVAR_ID);
+
+    // 互斥锁：被占用时 trylock 立即失败，lock_timeout 超时返回
+    let tid = thread_create(holder as usize, 0);
+    sleep(10);
+    assert_eq!(mutex_trylock(MUTEX_ID), EBUSY);
+    let start = get_time();
+    assert_eq!(mutex_lock_timeout(MUTEX_ID, 20), ETIMEDOUT);
+    assert!(get_time() - start >= 20);
+    assert_eq!(mutex_lock_timeout(MUTEX_ID, 1000), 0);
+    mutex_unlock(MUTEX_ID);
+    waittid(tid as usize);
+    assert_eq!(mutex_trylock(MUTEX_ID), 0);
+    mutex_unlock(MUTEX_ID);
+
+    // 信号量：计数为零时 trydown 失败，down_timeout 超时后计数保持不变
+    assert_eq!(semaphore_trydown(SEM_ID), EBUSY);
+    assert_eq!(semaphore_down_timeout(SEM_ID, 20), ETIMEDOUT);
+    semaphore_up(SEM_ID);
+    assert_eq!(semaphore_down_timeout(SEM_ID, 20), 0);
+    assert_eq!(semaphore_trydown(SEM_ID), EBUSY);
+
+    // 条件变量：无人 broadcast 时超时，broadcast 唤醒全部等待者
+    mutex_lock(MUTEX_ID);
+    assert_eq!(condvar_wait_timeout(CONDVAR_ID, MUTEX_ID, 20), ETIMEDOUT);
+    mutex_unlock(MUTEX_ID);
+    let mut threads = Vec::new();
+    for _ in 0..WAITER_COUNT {
+        threads.push(thread_create(waiter as usize, 0));
+    }
+    sleep(50);
+    mutex_lock(MUTEX_ID);
+    unsafe {
+        READY = true;
+    }
+    condvar_broadcast(CONDVAR_ID);
+    mutex_unlock(MUTEX_ID);
+    for thread in threads.iter() {
+        waittid(*thread as usize);
+    }
+    println!("sync_timeout passed!");
+    0
+}
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use user_lib::errno::{EACCES, EFBIG, EISDIR, ENOENT};
+use user_lib::*;
+
+#[unsafe(no_mangle)]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let (size, path) = match argc {
+        4 if argv[1] == "-s" => (argv[2], argv[3]),
+        _ => {
+            println!("usage: truncate -s <size> <file>");
+            return 1;
+        }
+    };
+    let Ok(size) = size.parse::<isize>() else {
+        println!("truncate: invalid number: '{}'", size);
+        return 1;
+    };
+    let mut ret = truncate(path, size);
+    if ret == ENOENT {
+        // 与 coreutils 相同，文件不存在时先创建
+        let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+        if fd >= 0 {
+            ret = ftruncate(fd as usize, size);
+            close(fd as usize);
+        }
+    }
+    match ret {
+        0 => 0,
+        ENOENT => {
+            println!(
+                "truncate: cannot open '{}': No such file or directory",
+                path
+            );
+            1
+        }
+        EISDIR => {
+            println!("truncate: cannot open '{}': Is a directory", path);
+            1
+        }
+        EACCES => {
+            println!("truncate: cannot open '{}': Permission denied", path);
+            1
+        }
+        EFBIG => {
+            println!("truncate: failed to truncate '{}': File too large", path);
+            1
+        }
+        _ => {
+            println!("truncate: failed to truncate '{}'", path);
+            1
+        }
+    }
+}
@@ -149,7 +149,7 @@ pub fn main() -> i32 {
                                 if !output.is_empty() {
                                     let output_fd = open(
                                         output.as_str(),
-                                        OpenFlags::CREATE | OpenFlags::WRONLY,
+                                        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
                                     );
                                     if output_fd == -1 {
                                         println!("Error when opening file {}", output);
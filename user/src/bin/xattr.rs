@@ -0,0 +1,85 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate user_lib;
+
+use alloc::vec;
+use core::str;
+use user_lib::errno::{EACCES, ENODATA, ENOENT, ENOSPC, EOPNOTSUPP};
+use user_lib::*;
+
+/// 属性值的最大长度，不超过一个属性块
+const VALUE_MAX: usize = 512;
+
+fn report(path: &str, ret: isize) -> i32 {
+    match ret {
+        ENOENT => println!("xattr: {}: No such file or directory", path),
+        EACCES => println!("xattr: {}: Permission denied", path),
+        ENODATA => println!("xattr: {}: No such attribute", path),
+        ENOSPC => println!("xattr: {}: No space left for attributes", path),
+        EOPNOTSUPP => println!("xattr: {}: Attributes not supported", path),
+        _ => println!("xattr: {}: error {}", path, ret),
+    }
+    1
+}
+
+/// 打印属性值，不是 UTF-8 时打印长度
+fn print_value(path: &str, name: &str) -> i32 {
+    let mut value = vec![0u8; VALUE_MAX];
+    let len = getxattr(path, name, &mut value);
+    if len < 0 {
+        return report(path, len);
+    }
+    match str::from_utf8(&value[..len as usize]) {
+        Ok(text) => println!("{}: {}", name, text),
+        Err(_) => println!("{}: <{} bytes>", name, len),
+    }
+    0
+}
+
+#[unsafe(no_mangle)]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    match argc {
+        // xattr -w NAME VALUE PATH
+        5 if argv[1] == "-w" => {
+            let ret = setxattr(argv[4], argv[2], argv[3].as_bytes());
+            if ret != 0 { report(argv[4], ret) } else { 0 }
+        }
+        // xattr -d NAME PATH
+        4 if argv[1] == "-d" => {
+            let ret = removexattr(argv[3], argv[2]);
+            if ret != 0 { report(argv[3], ret) } else { 0 }
+        }
+        // xattr -p NAME PATH
+        4 if argv[1] == "-p" => print_value(argv[3], argv[2]),
+        // xattr PATH
+        2 => {
+            let path = argv[1];
+            let len = listxattr(path, &mut []);
+            if len < 0 {
+                return report(path, len);
+            }
+            let mut names = vec![0u8; len as usize];
+            let len = listxattr(path, &mut names);
+            if len < 0 {
+                return report(path, len);
+            }
+            for name in names[..len as usize].split(|&b| b == 0) {
+                if let Ok(name) = str::from_utf8(name)
+                    && !name.is_empty()
+                    && print_value(path, name) != 0
+                {
+                    return 1;
+                }
+            }
+            0
+        }
+        _ => {
+            println!(
+                "usage: xattr PATH | xattr -p NAME PATH | xattr -w NAME VALUE PATH | xattr -d NAME PATH"
+            );
+            1
+        }
+    }
+}
@@ -35,33 +35,86 @@ pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
 
-const NAME_LENGTH_LIMIT: usize = 27;
-pub struct DirEntry {
-    pub name: [u8; NAME_LENGTH_LIMIT + 1],
+/// 目录项的头部，后面紧跟 `name_len` 字节的名称，整条记录占 `rec_len` 字节
+///
+/// 读取目录文件得到的是若干这样的记录，`name_len` 为 0 的记录未被使用
+#[repr(C)]
+pub struct DirEntryHeader {
     pub inode_number: u32,
+    pub rec_len: u16,
+    pub name_len: u8,
+    pub file_type: u8,
 }
 
-pub const DIRENT_SZ: usize = 32;
+pub const DIRENT_HEADER_SZ: usize = 8;
 
-pub const CHR: usize = 0;
-pub const REG: usize = 1;
-pub const DIR: usize = 2;
+/// `Stat::mode` 中的文件类型
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
 
+/// 与 Linux (asm-generic) 的 `struct stat` 布局相同
+#[repr(C)]
+#[derive(Default)]
 pub struct Stat {
-    pub ino: u32,
+    pub dev: u64,
+    pub ino: u64,
     pub mode: u32,
-    pub off: u32,
-    pub size: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u64,
+    __pad1: u64,
+    pub size: i64,
+    pub blksize: i32,
+    __pad2: i32,
+    pub blocks: i64,
+    pub atime: i64,
+    pub atime_nsec: u64,
+    pub mtime: i64,
+    pub mtime_nsec: u64,
+    pub ctime: i64,
+    pub ctime_nsec: u64,
+    __unused: [u32; 2],
 }
 
 impl Stat {
     pub fn new() -> Self {
-        Self {
-            ino: 0,
-            mode: 0,
-            off: 0,
-            size: 0,
-        }
+        Self::default()
+    }
+
+    pub fn file_type(&self) -> u32 {
+        self.mode & S_IFMT
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == S_IFDIR
+    }
+}
+
+/// 与 Linux (asm-generic) 的 `struct statfs` 布局相同
+#[repr(C)]
+#[derive(Default)]
+pub struct StatFs {
+    pub type_: i64,
+    pub bsize: i64,
+    pub blocks: u64,
+    pub bfree: u64,
+    pub bavail: u64,
+    pub files: u64,
+    pub ffree: u64,
+    pub fsid: [i32; 2],
+    pub namelen: i64,
+    pub frsize: i64,
+    pub flags: i64,
+    __spare: [i64; 4],
+}
+
+impl StatFs {
+    pub fn new() -> Self {
+        Self::default()
     }
 }
 
@@ -74,7 +127,107 @@ pub fn fstat(fd: usize, stat: &mut Stat) -> isize {
     sys_fstat(fd, stat as *mut _ as *mut _)
 }
 
+/// 把文件截断或扩展到 `length` 字节
+pub fn truncate(path: &str, length: isize) -> isize {
+    let path = String::from(path) + "\0";
+    sys_truncate(path.as_ptr(), length)
+}
+
+pub fn ftruncate(fd: usize, length: isize) -> isize {
+    sys_ftruncate(fd, length)
+}
+
+/// 获取 `path` 所在文件系统的容量和使用情况
+pub fn statfs(path: &str, buf: &mut StatFs) -> isize {
+    let path = String::from(path) + "\0";
+    sys_statfs(path.as_ptr(), buf as *mut _ as *mut _)
+}
+
+pub fn fstatfs(fd: usize, buf: &mut StatFs) -> isize {
+    sys_fstatfs(fd, buf as *mut _ as *mut _)
+}
+
+/// 读取文件的属性标志
+pub const FS_IOC_GETFLAGS: usize = 0x80086601;
+/// 设置文件的属性标志，需要以写方式打开文件
+pub const FS_IOC_SETFLAGS: usize = 0x40086602;
+/// 文件压缩存放，只能在文件还是空的时候设置
+pub const FS_COMPR_FL: u32 = 0x4;
+
+/// 对文件执行控制命令，属性标志的读写都通过 `flags` 传递
+pub fn ioctl(fd: usize, cmd: usize, flags: &mut u32) -> isize {
+    sys_ioctl(fd, cmd, flags as *mut u32 as usize)
+}
+
+/// 设置扩展属性，已有同名属性时替换它的值
+pub fn setxattr(path: &str, name: &str, value: &[u8]) -> isize {
+    let path = String::from(path) + "\0";
+    let name = String::from(name) + "\0";
+    sys_setxattr(path.as_ptr(), name.as_ptr(), value)
+}
+
+/// 读取扩展属性的值，返回值的长度；`buf` 为空时只返回长度
+pub fn getxattr(path: &str, name: &str, buf: &mut [u8]) -> isize {
+    let path = String::from(path) + "\0";
+    let name = String::from(name) + "\0";
+    sys_getxattr(path.as_ptr(), name.as_ptr(), buf)
+}
+
+/// 列出所有扩展属性的名称，每个名称以 NUL 结尾，返回写入的字节数
+pub fn listxattr(path: &str, buf: &mut [u8]) -> isize {
+    let path = String::from(path) + "\0";
+    sys_listxattr(path.as_ptr(), buf)
+}
+
+pub fn removexattr(path: &str, name: &str) -> isize {
+    let path = String::from(path) + "\0";
+    let name = String::from(name) + "\0";
+    sys_removexattr(path.as_ptr(), name.as_ptr())
+}
+
+/// 把文件系统中所有未写回的修改写入磁盘
+pub fn sync() -> isize {
+    sys_sync()
+}
+
+pub const AT_SYMLINK_NOFOLLOW: u32 = 0x100;
+pub fn stat(path: &str, stat: &mut Stat) -> isize {
+    let path = String::from(path) + "\0";
+    sys_fstatat(path.as_ptr(), stat as *mut _ as *mut _, 0)
+}
+
+/// 与 `stat` 相同，但不跟随路径最后的符号链接
+pub fn lstat(path: &str, stat: &mut Stat) -> isize {
+    let path = String::from(path) + "\0";
+    sys_fstatat(path.as_ptr(), stat as *mut _ as *mut _, AT_SYMLINK_NOFOLLOW)
+}
+
+pub fn link(old_path: &str, new_path: &str) -> isize {
+    let old_path = String::from(old_path) + "\0";
+    let new_path = String::from(new_path) + "\0";
+    sys_linkat(old_path.as_ptr(), new_path.as_ptr())
+}
+
+pub fn rename(old_path: &str, new_path: &str) -> isize {
+    let old_path = String::from(old_path) + "\0";
+    let new_path = String::from(new_path) + "\0";
+    sys_renameat(old_path.as_ptr(), new_path.as_ptr())
+}
+
+pub fn symlink(target: &str, link_path: &str) -> isize {
+    let target = String::from(target) + "\0";
+    let link_path = String::from(link_path) + "\0";
+    sys_symlinkat(target.as_ptr(), link_path.as_ptr())
+}
+
+/// 读取符号链接的目标路径，返回写入 `buf` 的字节数
+pub fn readlink(path: &str, buf: &mut [u8]) -> isize {
+    let path = String::from(path) + "\0";
+    sys_readlinkat(path.as_ptr(), buf)
+}
+
 pub const AT_REMOVEDIR: u32 = 1;
+pub const AT_RECURSIVE: u32 = 2;
 pub fn unlink(path: &str, flags: u32) -> isize {
     let path = String::from(path) + "\0";
     sys_unlink(path.as_ptr(), flags)
@@ -4,6 +4,7 @@
 
 #[macro_use]
 pub mod console;
+pub mod errno;
 mod file;
 mod io;
 mod lang_items;
@@ -1,5 +1,24 @@
 use super::*;
 
+bitflags! {
+    /// 打开具名信号量和消息队列时使用的标志
+    pub struct IpcFlags: u32 {
+        const CREATE = 1 << 9;
+        const EXCL = 1 << 10;
+        const NONBLOCK = 1 << 11;
+    }
+}
+
+/// 消息队列的属性
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MqAttr {
+    /// 队列中最多容纳的消息数
+    pub max_msg: usize,
+    /// 单条消息的最大长度
+    pub msg_size: usize,
+}
+
 pub fn mutex_create() -> isize {
     sys_mutex_create(false)
 }
@@ -9,8 +28,17 @@ pub fn mutex_blocking_create() -> isize {
 pub fn mutex_lock(mutex_id: usize) {
     sys_mutex_lock(mutex_id);
 }
-pub fn mutex_unlock(mutex_id: usize) {
-    sys_mutex_unlock(mutex_id);
+pub fn mutex_unlock(mutex_id: usize) -> isize {
+    sys_mutex_unlock(mutex_id)
+}
+pub fn mutex_trylock(mutex_id: usize) -> isize {
+    sys_mutex_trylock(mutex_id)
+}
+pub fn mutex_lock_timeout(mutex_id: usize, timeout_ms: usize) -> isize {
+    sys_mutex_lock_timeout(mutex_id, timeout_ms)
+}
+pub fn mutex_destroy(mutex_id: usize) -> isize {
+    sys_mutex_destroy(mutex_id)
 }
 pub fn semaphore_create(res_count: usize) -> isize {
     sys_semaphore_create(res_count)
@@ -21,6 +49,15 @@ pub fn semaphore_up(sem_id: usize) {
 pub fn semaphore_down(sem_id: usize) {
     sys_semaphore_down(sem_id);
 }
+pub fn semaphore_trydown(sem_id: usize) -> isize {
+    sys_semaphore_trydown(sem_id)
+}
+pub fn semaphore_down_timeout(sem_id: usize, timeout_ms: usize) -> isize {
+    sys_semaphore_down_timeout(sem_id, timeout_ms)
+}
+pub fn semaphore_destroy(sem_id: usize) -> isize {
+    sys_semaphore_destroy(sem_id)
+}
 pub fn condvar_create() -> isize {
     sys_condvar_create()
 }
@@ -30,3 +67,59 @@ pub fn condvar_signal(condvar_id: usize) {
 pub fn condvar_wait(condvar_id: usize, mutex_id: usize) {
     sys_condvar_wait(condvar_id, mutex_id);
 }
+pub fn condvar_broadcast(condvar_id: usize) {
+    sys_condvar_broadcast(condvar_id);
+}
+pub fn condvar_wait_timeout(condvar_id: usize, mutex_id: usize, timeout_ms: usize) -> isize {
+    sys_condvar_wait_timeout(condvar_id, mutex_id, timeout_ms)
+}
+pub fn condvar_destroy(condvar_id: usize) -> isize {
+    sys_condvar_destroy(condvar_id)
+}
+pub fn rwlock_create() -> isize {
+    sys_rwlock_create(false)
+}
+pub fn rwlock_writer_first_create() -> isize {
+    sys_rwlock_create(true)
+}
+pub fn rwlock_read(rwlock_id: usize) {
+    sys_rwlock_read(rwlock_id);
+}
+pub fn rwlock_write(rwlock_id: usize) {
+    sys_rwlock_write(rwlock_id);
+}
+pub fn rwlock_unlock(rwlock_id: usize) -> isize {
+    sys_rwlock_unlock(rwlock_id)
+}
+pub fn barrier_create(parties: usize) -> isize {
+    sys_barrier_create(parties)
+}
+pub fn barrier_wait(barrier_id: usize) -> isize {
+    sys_barrier_wait(barrier_id)
+}
+pub fn sem_open(name: &str, flags: IpcFlags, res_count: usize) -> isize {
+    sys_sem_open(name, flags.bits, res_count)
+}
+pub fn sem_close(sem_id: usize) -> isize {
+    sys_sem_close(sem_id)
+}
+pub fn sem_unlink(name: &str) -> isize {
+    sys_sem_unlink(name)
+}
+pub fn mq_open(name: &str, flags: IpcFlags, attr: Option<&MqAttr>) -> isize {
+    let attr = attr.map_or(core::ptr::null(), |attr| attr as *const MqAttr);
+    sys_mq_open(name, flags.bits, attr)
+}
+pub fn mq_close(mqd: usize) -> isize {
+    sys_mq_close(mqd)
+}
+pub fn mq_unlink(name: &str) -> isize {
+    sys_mq_unlink(name)
+}
+pub fn mq_send(mqd: usize, msg: &[u8], priority: usize) -> isize {
+    sys_mq_send(mqd, msg, priority)
+}
+/// 返回消息长度，`priority` 中存放消息的优先级
+pub fn mq_receive(mqd: usize, buf: &mut [u8], priority: &mut usize) -> isize {
+    sys_mq_receive(mqd, buf, priority)
+}
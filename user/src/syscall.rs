@@ -1,3 +1,4 @@
+use super::MqAttr;
 use core::arch::asm;
 
 //fs
@@ -9,6 +10,21 @@ const SYSCALL_READ: usize = 63;
 const SYSCALL_MKDIR: usize = 34;
 const SYSCALL_FSTAT: usize = 80;
 const SYSCALL_UNLINK: usize = 35;
+const SYSCALL_SYMLINKAT: usize = 36;
+const SYSCALL_LINKAT: usize = 37;
+const SYSCALL_RENAMEAT: usize = 38;
+const SYSCALL_READLINKAT: usize = 78;
+const SYSCALL_FSTATAT: usize = 79;
+const SYSCALL_SYNC: usize = 81;
+const SYSCALL_TRUNCATE: usize = 45;
+const SYSCALL_FTRUNCATE: usize = 46;
+const SYSCALL_STATFS: usize = 43;
+const SYSCALL_FSTATFS: usize = 44;
+const SYSCALL_SETXATTR: usize = 5;
+const SYSCALL_GETXATTR: usize = 8;
+const SYSCALL_LISTXATTR: usize = 11;
+const SYSCALL_REMOVEXATTR: usize = 14;
+const SYSCALL_IOCTL: usize = 29;
 
 // process
 const SYSCALL_WRITE: usize = 64;
@@ -16,8 +32,15 @@ const SYSCALL_EXIT: usize = 93;
 const SYSCALL_SLEEP: usize = 101;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_KILL: usize = 129;
+const SYSCALL_SET_PRIORITY: usize = 140;
+const SYSCALL_SETGID: usize = 144;
+const SYSCALL_SETUID: usize = 146;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETUID: usize = 174;
+const SYSCALL_GETEUID: usize = 175;
+const SYSCALL_GETGID: usize = 176;
+const SYSCALL_GETEGID: usize = 177;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
@@ -31,16 +54,47 @@ const SYSCALL_WAITTID: usize = 1002;
 const SYSCALL_MUTEX_CREATE: usize = 1010;
 const SYSCALL_MUTEX_LOCK: usize = 1011;
 const SYSCALL_MUTEX_UNLOCK: usize = 1012;
+const SYSCALL_MUTEX_TRYLOCK: usize = 1013;
+const SYSCALL_MUTEX_LOCK_TIMEOUT: usize = 1014;
+const SYSCALL_MUTEX_DESTROY: usize = 1015;
 
 // semaphore
 const SYSCALL_SEMAPHORE_CREATE: usize = 1020;
 const SYSCALL_SEMAPHORE_UP: usize = 1021;
 const SYSCALL_SEMAPHORE_DOWN: usize = 1022;
+const SYSCALL_SEMAPHORE_TRYDOWN: usize = 1023;
+const SYSCALL_SEMAPHORE_DOWN_TIMEOUT: usize = 1024;
+const SYSCALL_SEMAPHORE_DESTROY: usize = 1025;
 
 //condvar
 const SYSCALL_CONDVAR_CREATE: usize = 1030;
 const SYSCALL_CONDVAR_SIGNAL: usize = 1031;
 const SYSCALL_CONDVAR_WAIT: usize = 1032;
+const SYSCALL_CONDVAR_BROADCAST: usize = 1033;
+const SYSCALL_CONDVAR_WAIT_TIMEOUT: usize = 1034;
+const SYSCALL_CONDVAR_DESTROY: usize = 1035;
+
+// rwlock
+const SYSCALL_RWLOCK_CREATE: usize = 1040;
+const SYSCALL_RWLOCK_READ: usize = 1041;
+const SYSCALL_RWLOCK_WRITE: usize = 1042;
+const SYSCALL_RWLOCK_UNLOCK: usize = 1043;
+
+// barrier
+const SYSCALL_BARRIER_CREATE: usize = 1050;
+const SYSCALL_BARRIER_WAIT: usize = 1051;
+
+// named semaphore
+const SYSCALL_SEM_OPEN: usize = 1060;
+const SYSCALL_SEM_CLOSE: usize = 1061;
+const SYSCALL_SEM_UNLINK: usize = 1062;
+
+// message queue
+const SYSCALL_MQ_OPEN: usize = 1070;
+const SYSCALL_MQ_CLOSE: usize = 1071;
+const SYSCALL_MQ_UNLINK: usize = 1072;
+const SYSCALL_MQ_SEND: usize = 1073;
+const SYSCALL_MQ_RECEIVE: usize = 1074;
 
 // gui
 const SYSCALL_FRAMEBUFFER: usize = 2000;
@@ -52,6 +106,21 @@ const SYSCALL_KEY_PRESSED: usize = 3001;
 
 const SYSCALL_GETDENTS: usize = 61;
 
+fn syscall4(id: usize, args: [usize; 4]) -> isize {
+    let mut ret: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("x10") args[0] => ret,
+            in("x11") args[1],
+            in("x12") args[2],
+            in("x13") args[3],
+            in("x17") id
+        );
+    }
+    ret
+}
+
 fn syscall(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
     unsafe {
@@ -111,6 +180,91 @@ pub fn sys_unlink(path: *const u8, flags: u32) -> isize {
     syscall(SYSCALL_UNLINK, [path as usize, flags as usize, 0])
 }
 
+pub fn sys_linkat(old_path: *const u8, new_path: *const u8) -> isize {
+    syscall(SYSCALL_LINKAT, [old_path as usize, new_path as usize, 0])
+}
+
+pub fn sys_renameat(old_path: *const u8, new_path: *const u8) -> isize {
+    syscall(SYSCALL_RENAMEAT, [old_path as usize, new_path as usize, 0])
+}
+
+pub fn sys_symlinkat(target: *const u8, link_path: *const u8) -> isize {
+    syscall(SYSCALL_SYMLINKAT, [target as usize, link_path as usize, 0])
+}
+
+pub fn sys_readlinkat(path: *const u8, buf: &mut [u8]) -> isize {
+    syscall(
+        SYSCALL_READLINKAT,
+        [path as usize, buf.as_mut_ptr() as usize, buf.len()],
+    )
+}
+
+pub fn sys_fstatat(path: *const u8, stat: *mut u8, flags: u32) -> isize {
+    syscall(
+        SYSCALL_FSTATAT,
+        [path as usize, stat as usize, flags as usize],
+    )
+}
+
+pub fn sys_truncate(path: *const u8, length: isize) -> isize {
+    syscall(SYSCALL_TRUNCATE, [path as usize, length as usize, 0])
+}
+
+pub fn sys_ftruncate(fd: usize, length: isize) -> isize {
+    syscall(SYSCALL_FTRUNCATE, [fd, length as usize, 0])
+}
+
+pub fn sys_statfs(path: *const u8, buf: *mut u8) -> isize {
+    syscall(SYSCALL_STATFS, [path as usize, buf as usize, 0])
+}
+
+pub fn sys_fstatfs(fd: usize, buf: *mut u8) -> isize {
+    syscall(SYSCALL_FSTATFS, [fd, buf as usize, 0])
+}
+
+pub fn sys_setxattr(path: *const u8, name: *const u8, value: &[u8]) -> isize {
+    syscall4(
+        SYSCALL_SETXATTR,
+        [
+            path as usize,
+            name as usize,
+            value.as_ptr() as usize,
+            value.len(),
+        ],
+    )
+}
+
+pub fn sys_getxattr(path: *const u8, name: *const u8, value: &mut [u8]) -> isize {
+    syscall4(
+        SYSCALL_GETXATTR,
+        [
+            path as usize,
+            name as usize,
+            value.as_mut_ptr() as usize,
+            value.len(),
+        ],
+    )
+}
+
+pub fn sys_listxattr(path: *const u8, list: &mut [u8]) -> isize {
+    syscall(
+        SYSCALL_LISTXATTR,
+        [path as usize, list.as_mut_ptr() as usize, list.len()],
+    )
+}
+
+pub fn sys_removexattr(path: *const u8, name: *const u8) -> isize {
+    syscall(SYSCALL_REMOVEXATTR, [path as usize, name as usize, 0])
+}
+
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, cmd, arg])
+}
+
+pub fn sys_sync() -> isize {
+    syscall(SYSCALL_SYNC, [0, 0, 0])
+}
+
 pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len()])
 }
@@ -132,6 +286,10 @@ pub fn sys_kill(pid: usize, signal: i32) -> isize {
     syscall(SYSCALL_KILL, [pid, signal as usize, 0])
 }
 
+pub fn sys_set_priority(priority: usize) -> isize {
+    syscall(SYSCALL_SET_PRIORITY, [priority, 0, 0])
+}
+
 pub fn sys_get_time() -> isize {
     syscall(SYSCALL_GET_TIME, [0, 0, 0])
 }
@@ -140,6 +298,30 @@ pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
 
+pub fn sys_setuid(uid: u32) -> isize {
+    syscall(SYSCALL_SETUID, [uid as usize, 0, 0])
+}
+
+pub fn sys_setgid(gid: u32) -> isize {
+    syscall(SYSCALL_SETGID, [gid as usize, 0, 0])
+}
+
+pub fn sys_getuid() -> isize {
+    syscall(SYSCALL_GETUID, [0, 0, 0])
+}
+
+pub fn sys_geteuid() -> isize {
+    syscall(SYSCALL_GETEUID, [0, 0, 0])
+}
+
+pub fn sys_getgid() -> isize {
+    syscall(SYSCALL_GETGID, [0, 0, 0])
+}
+
+pub fn sys_getegid() -> isize {
+    syscall(SYSCALL_GETEGID, [0, 0, 0])
+}
+
 pub fn sys_fork() -> isize {
     syscall(SYSCALL_FORK, [0, 0, 0])
 }
@@ -179,6 +361,18 @@ pub fn sys_mutex_unlock(id: usize) -> isize {
     syscall(SYSCALL_MUTEX_UNLOCK, [id, 0, 0])
 }
 
+pub fn sys_mutex_trylock(id: usize) -> isize {
+    syscall(SYSCALL_MUTEX_TRYLOCK, [id, 0, 0])
+}
+
+pub fn sys_mutex_lock_timeout(id: usize, timeout_ms: usize) -> isize {
+    syscall(SYSCALL_MUTEX_LOCK_TIMEOUT, [id, timeout_ms, 0])
+}
+
+pub fn sys_mutex_destroy(id: usize) -> isize {
+    syscall(SYSCALL_MUTEX_DESTROY, [id, 0, 0])
+}
+
 pub fn sys_semaphore_create(res_count: usize) -> isize {
     syscall(SYSCALL_SEMAPHORE_CREATE, [res_count, 0, 0])
 }
@@ -191,6 +385,18 @@ pub fn sys_semaphore_down(sem_id: usize) -> isize {
     syscall(SYSCALL_SEMAPHORE_DOWN, [sem_id, 0, 0])
 }
 
+pub fn sys_semaphore_trydown(sem_id: usize) -> isize {
+    syscall(SYSCALL_SEMAPHORE_TRYDOWN, [sem_id, 0, 0])
+}
+
+pub fn sys_semaphore_down_timeout(sem_id: usize, timeout_ms: usize) -> isize {
+    syscall(SYSCALL_SEMAPHORE_DOWN_TIMEOUT, [sem_id, timeout_ms, 0])
+}
+
+pub fn sys_semaphore_destroy(sem_id: usize) -> isize {
+    syscall(SYSCALL_SEMAPHORE_DESTROY, [sem_id, 0, 0])
+}
+
 pub fn sys_condvar_create() -> isize {
     syscall(SYSCALL_CONDVAR_CREATE, [0, 0, 0])
 }
@@ -203,6 +409,94 @@ pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
     syscall(SYSCALL_CONDVAR_WAIT, [condvar_id, mutex_id, 0])
 }
 
+pub fn sys_condvar_broadcast(condvar_id: usize) -> isize {
+    syscall(SYSCALL_CONDVAR_BROADCAST, [condvar_id, 0, 0])
+}
+
+pub fn sys_condvar_wait_timeout(condvar_id: usize, mutex_id: usize, timeout_ms: usize) -> isize {
+    syscall(
+        SYSCALL_CONDVAR_WAIT_TIMEOUT,
+        [condvar_id, mutex_id, timeout_ms],
+    )
+}
+
+pub fn sys_condvar_destroy(condvar_id: usize) -> isize {
+    syscall(SYSCALL_CONDVAR_DESTROY, [condvar_id, 0, 0])
+}
+
+pub fn sys_rwlock_create(writer_first: bool) -> isize {
+    syscall(SYSCALL_RWLOCK_CREATE, [writer_first as usize, 0, 0])
+}
+
+pub fn sys_rwlock_read(rwlock_id: usize) -> isize {
+    syscall(SYSCALL_RWLOCK_READ, [rwlock_id, 0, 0])
+}
+
+pub fn sys_rwlock_write(rwlock_id: usize) -> isize {
+    syscall(SYSCALL_RWLOCK_WRITE, [rwlock_id, 0, 0])
+}
+
+pub fn sys_rwlock_unlock(rwlock_id: usize) -> isize {
+    syscall(SYSCALL_RWLOCK_UNLOCK, [rwlock_id, 0, 0])
+}
+
+pub fn sys_barrier_create(parties: usize) -> isize {
+    syscall(SYSCALL_BARRIER_CREATE, [parties, 0, 0])
+}
+
+pub fn sys_barrier_wait(barrier_id: usize) -> isize {
+    syscall(SYSCALL_BARRIER_WAIT, [barrier_id, 0, 0])
+}
+
+pub fn sys_sem_open(name: &str, flags: u32, res_count: usize) -> isize {
+    syscall(
+        SYSCALL_SEM_OPEN,
+        [name.as_ptr() as usize, flags as usize, res_count],
+    )
+}
+
+pub fn sys_sem_close(sem_id: usize) -> isize {
+    syscall(SYSCALL_SEM_CLOSE, [sem_id, 0, 0])
+}
+
+pub fn sys_sem_unlink(name: &str) -> isize {
+    syscall(SYSCALL_SEM_UNLINK, [name.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_mq_open(name: &str, flags: u32, attr: *const MqAttr) -> isize {
+    syscall(
+        SYSCALL_MQ_OPEN,
+        [name.as_ptr() as usize, flags as usize, attr as usize],
+    )
+}
+
+pub fn sys_mq_close(mqd: usize) -> isize {
+    syscall(SYSCALL_MQ_CLOSE, [mqd, 0, 0])
+}
+
+pub fn sys_mq_unlink(name: &str) -> isize {
+    syscall(SYSCALL_MQ_UNLINK, [name.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_mq_send(mqd: usize, buf: &[u8], priority: usize) -> isize {
+    syscall4(
+        SYSCALL_MQ_SEND,
+        [mqd, buf.as_ptr() as usize, buf.len(), priority],
+    )
+}
+
+pub fn sys_mq_receive(mqd: usize, buf: &mut [u8], priority: &mut usize) -> isize {
+    syscall4(
+        SYSCALL_MQ_RECEIVE,
+        [
+            mqd,
+            buf.as_mut_ptr() as usize,
+            buf.len(),
+            priority as *mut usize as usize,
+        ],
+    )
+}
+
 pub fn sys_framebuffer() -> isize {
     syscall(SYSCALL_FRAMEBUFFER, [0, 0, 0])
 }
@@ -22,6 +22,24 @@ pub fn get_time() -> isize {
 pub fn getpid() -> isize {
     sys_getpid()
 }
+pub fn setuid(uid: u32) -> isize {
+    sys_setuid(uid)
+}
+pub fn setgid(gid: u32) -> isize {
+    sys_setgid(gid)
+}
+pub fn getuid() -> u32 {
+    sys_getuid() as u32
+}
+pub fn geteuid() -> u32 {
+    sys_geteuid() as u32
+}
+pub fn getgid() -> u32 {
+    sys_getgid() as u32
+}
+pub fn getegid() -> u32 {
+    sys_getegid() as u32
+}
 pub fn fork() -> isize {
     sys_fork()
 }
@@ -70,6 +88,9 @@ pub fn thread_create(entry: usize, arg: usize) -> isize {
 pub fn gettid() -> isize {
     sys_gettid()
 }
+pub fn set_priority(priority: usize) -> isize {
+    sys_set_priority(priority)
+}
 pub fn waittid(tid: usize) -> isize {
     loop {
         match sys_waittid(tid) {